@@ -0,0 +1,343 @@
+//! Opening books: moves to play in known positions without searching
+//!
+//! A book file is text with one line per move, giving the position's hash (see
+//! `Board::position_hash`) in hex, the ply at which the position was reached when the book was
+//! built, the move, and its weight:
+//!
+//! ```text
+//! # theseus opening book
+//! 5c0d2f63a1e0b7f4 0 e8 812
+//! 5c0d2f63a1e0b7f4 0 d8v 140
+//! ```
+//!
+//! Blank lines and lines starting with `#` are ignored.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
+
+use super::{analyze, Config, Line};
+use crate::board::{parse_move, Board, Player, Transform};
+use crate::game::Game;
+
+/// The moves known in one position
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Entry {
+    /// the earliest ply the position was reached at
+    ply: u32,
+    /// moves and their weights, heaviest first
+    moves: Vec<(String, f64)>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Book {
+    entries: BTreeMap<u64, Entry>,
+}
+
+impl Book {
+    pub fn load(path: &str) -> Result<Book, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        text.parse().map_err(|error| format!("{}: {}", path, error))
+    }
+
+    /// The number of positions in the book
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds `weight` to `move_string` in `board`, reached at `ply`
+    pub fn add(&mut self, board: &Board, ply: u32, move_string: &str, weight: f64) {
+        self.add_hash(board.position_hash(), ply, move_string, weight);
+    }
+
+    fn add_hash(&mut self, hash: u64, ply: u32, move_string: &str, weight: f64) {
+        let entry = self.entries.entry(hash).or_insert(Entry {
+            ply,
+            moves: Vec::new(),
+        });
+        entry.ply = entry.ply.min(ply);
+        match entry.moves.iter_mut().find(|(m, _)| m == move_string) {
+            Some((_, total)) => *total += weight,
+            None => entry.moves.push((move_string.to_string(), weight)),
+        }
+        entry.moves.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    }
+
+    /// The book's legal moves in `board` and their weights, if the position was reached within
+    /// `max_ply` plies
+    ///
+    /// A position missing from the book is looked up by its mirror image and color flips too,
+    /// mapping their moves back.
+    pub fn moves(&self, board: &Board, max_ply: u32) -> Vec<(Board, f64)> {
+        let size = board.rules().size;
+        let found = Transform::ALL.iter().find_map(|transform| {
+            match self
+                .entries
+                .get(&board.transformed(*transform).position_hash())
+            {
+                Some(entry) if entry.ply <= max_ply => Some((transform, entry)),
+                _ => None,
+            }
+        });
+        match found {
+            Some((transform, entry)) => entry
+                .moves
+                .iter()
+                .filter(|(_, weight)| *weight > 0.0)
+                // a hash collision could suggest a move from another position
+                .filter_map(|(m, weight)| {
+                    let m = transform.move_string(size, m)?;
+                    Some((board.child_for_move_string(&m)?, *weight))
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Chooses a book move in `board`, if the position was reached within `max_ply` plies
+    ///
+    /// Without `random`, the heaviest move is played. With it, moves are chosen with probability
+    /// proportional to their weights, reproducibly if `seed` is set.
+    pub fn probe(
+        &self,
+        board: &Board,
+        max_ply: u32,
+        random: bool,
+        seed: Option<u64>,
+    ) -> Option<Board> {
+        let moves = self.moves(board, max_ply);
+        if !random {
+            return moves
+                .into_iter()
+                .fold(
+                    None,
+                    |best: Option<(Board, f64)>, (child, weight)| match best {
+                        Some((_, most)) if most >= weight => best,
+                        _ => Some((child, weight)),
+                    },
+                )
+                .map(|(child, _)| child);
+        }
+
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed ^ board.position_hash()),
+            None => StdRng::from_rng(thread_rng()).unwrap(),
+        };
+        let mut choice = rng.gen::<f64>() * moves.iter().map(|(_, weight)| weight).sum::<f64>();
+        for (child, weight) in moves {
+            if choice < weight {
+                return Some(child);
+            }
+            choice -= weight;
+        }
+        None
+    }
+
+    /// Builds a book from the first `depth` plies of `games`
+    ///
+    /// Each move is weighted by the score of the player who made it: 1 for a win, 0.5 for an
+    /// unfinished game and 0 for a loss, taking the result from the `Result` tag if the moves
+    /// don't finish the game. Plies are counted from each game's start.
+    pub fn from_games(games: &[Game], depth: u32) -> Book {
+        let mut book = Book::default();
+        for game in games {
+            let winner = game.winner().or_else(|| match game.header("Result") {
+                Some("1-0") => Some(Player::White),
+                Some("0-1") => Some(Player::Black),
+                _ => None,
+            });
+            for (ply, move_string) in game.moves().iter().enumerate().take(depth as usize) {
+                let board = game.position(ply);
+                let weight = match winner {
+                    Some(winner) if winner == board.turn() => 1.0,
+                    Some(_) => 0.0,
+                    None => 0.5,
+                };
+                book.add(board, ply as u32, move_string, weight);
+            }
+        }
+        book
+    }
+
+    /// Builds a book by searching from `start` to `depth` plies
+    ///
+    /// Each position is searched with `config`, and every move given at least `min_share` of the
+    /// visits is added, weighted by its visits, and searched in turn. `on_position` is called
+    /// with each position searched and its lines.
+    pub fn from_search<F>(
+        start: &Board,
+        config: &Config,
+        depth: u32,
+        min_share: f64,
+        mut on_position: F,
+    ) -> Book
+    where
+        F: FnMut(&Board, &[Line]),
+    {
+        let mut book = Book::default();
+        let mut searched = HashSet::new();
+        let mut frontier = vec![start.clone()];
+        for ply in 0..depth {
+            let mut next = Vec::new();
+            for board in frontier {
+                if board.winner().is_some() || !searched.insert(board.position_hash()) {
+                    continue;
+                }
+                let mut log = String::new();
+                let lines = analyze(&board, config, usize::MAX, &[], &mut log);
+                on_position(&board, &lines);
+                for line in lines.iter().filter(|line| line.visit_share >= min_share) {
+                    book.add(&board, ply, &line.move_string, line.visits as f64);
+                    next.push(line.board.clone());
+                }
+            }
+            frontier = next;
+        }
+        book
+    }
+}
+
+impl FromStr for Book {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut book = Book::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let malformed = || format!("malformed line {}: {}", i + 1, line);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 4 {
+                return Err(malformed());
+            }
+            let hash = u64::from_str_radix(fields[0], 16).map_err(|_| malformed())?;
+            let ply = fields[1].parse().map_err(|_| malformed())?;
+            // the book doesn't record the board size, so moves are checked against the largest
+            if parse_move(9, fields[2]).is_none() {
+                return Err(malformed());
+            }
+            let weight: f64 = fields[3].parse().map_err(|_| malformed())?;
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(malformed());
+            }
+            book.add_hash(hash, ply, &fields[2].to_ascii_lowercase(), weight);
+        }
+        Ok(book)
+    }
+}
+
+impl fmt::Display for Book {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# theseus opening book")?;
+        for (hash, entry) in &self.entries {
+            for (move_string, weight) in &entry.moves {
+                writeln!(f, "{:016x} {} {} {}", hash, entry.ply, move_string, weight)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A book loaded from a file for an engine configuration, which compares equal to another by path
+#[derive(Clone, Debug)]
+pub struct BookFile {
+    pub path: String,
+    book: Arc<Book>,
+}
+
+impl BookFile {
+    pub fn load(path: &str) -> Result<BookFile, String> {
+        Ok(BookFile {
+            path: path.to_string(),
+            book: Arc::new(Book::load(path)?),
+        })
+    }
+}
+
+impl Deref for BookFile {
+    type Target = Book;
+
+    fn deref(&self) -> &Book {
+        &self.book
+    }
+}
+
+impl PartialEq for BookFile {
+    fn eq(&self, other: &BookFile) -> bool {
+        self.path == other.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_and_probe() {
+        let start = Board::new();
+        let game: Game = "[Result \"1-0\"]\n\n1. e8 e2 2. e7 e3 3. d7h 1-0"
+            .parse()
+            .unwrap();
+        let mut book = Book::from_games(&[game], 2);
+        book.add(&start, 0, "d9", 0.25);
+        assert_eq!(book.len(), 2);
+
+        let text = book.to_string();
+        let parsed: Book = text.parse().unwrap();
+        assert_eq!(parsed, book);
+        assert_eq!(parsed.to_string(), text);
+
+        let e8 = start.child_for_move_string("e8").unwrap();
+        assert_eq!(book.probe(&start, 0, false, None), Some(e8.clone()));
+        // the losing side's reply is kept, with no weight
+        assert_eq!(book.probe(&e8, 1, false, None), None);
+        assert_eq!(book.probe(&e8, 0, true, Some(1)), None);
+        for seed in 0..20 {
+            let child = book.probe(&start, 0, true, Some(seed)).unwrap();
+            assert!(book.moves(&start, 0).iter().any(|(c, _)| *c == child));
+        }
+        assert!("00ff 0 e8".parse::<Book>().is_err());
+        for line in &[
+            "00ff 0 e 1",
+            "00ff 0 ee 1",
+            "00ff 0 e0 1",
+            "00ff 0 e8 NaN",
+            "00ff 0 e8 -1",
+        ] {
+            assert!(line.parse::<Book>().is_err(), "{}", line);
+        }
+
+        // a move off a smaller board is skipped
+        let board = Board::with_rules(crate::board::Rules::with_size(5).unwrap());
+        let mut book = Book::default();
+        book.add(&board, 0, "h8", 1.0);
+        assert!(book.moves(&board, 0).is_empty());
+    }
+
+    #[test]
+    fn probe_symmetric_positions() {
+        let board = Board::new().child_for_move_string("d9").unwrap();
+        let mut book = Book::default();
+        book.add(&board, 1, "c1h", 1.0);
+
+        let mirror = board.mirror();
+        let child = book.probe(&mirror, 1, false, None).unwrap();
+        assert_eq!(mirror.move_string_to(&child), "f1h");
+        let flip = board.color_flip();
+        let child = book.probe(&flip, 1, false, None).unwrap();
+        assert_eq!(flip.move_string_to(&child), "f8h");
+        assert_eq!(book.probe(&flip, 0, false, None), None);
+    }
+}
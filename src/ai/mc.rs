@@ -1,11 +1,11 @@
 use crate::Board;
 
 use rand::seq::SliceRandom;
+use rand::Rng;
 
 const ITERATIONS: u32 = 500;
 
-pub fn mc(board: &Board) -> Board {
-    let mut rng = rand::thread_rng();
+pub fn mc<R: Rng>(board: &Board, rng: &mut R) -> Board {
     let moves = board.moves_detailed(true, true);
     if moves.len() == 1 {
         return moves[0].clone();
@@ -39,9 +39,9 @@ pub fn mc(board: &Board) -> Board {
                 }
 
                 let moves = current.moves_detailed(false, true);
-                let mut next = moves.choose(&mut rng).unwrap();
+                let mut next = moves.choose(rng).unwrap();
                 while !next.paths_exist() {
-                    next = moves.choose(&mut rng).unwrap();
+                    next = moves.choose(rng).unwrap();
                 }
                 current = next.clone();
                 winner = current.winner();
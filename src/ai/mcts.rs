@@ -1,14 +1,18 @@
-use std::cell::RefCell;
-use std::f64::INFINITY;
-use std::rc::Rc;
+use std::fmt;
+use std::str::FromStr;
 
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::{thread_rng, Rng};
-use std::sync::mpsc;
+use rand::{thread_rng, Rng, SeedableRng};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
+use super::book::BookFile;
 use crate::board::Board;
+use crate::settings::{self, parse, positive};
 
 const ITERATIONS: u32 = 50000;
 const UCTC: f64 = 10000.0;
@@ -25,9 +29,129 @@ const PATH_DIFF_COEFF: f64 = 1.0;
 
 const THREADS: u32 = 2;
 
+const PV_LENGTH: usize = 6;
+
+const BOOK_DEPTH: u32 = 12;
+
+/// iterations between a thread's reports to its search's `Progress`
+const REPORT_INTERVAL: u32 = 100;
+
+/// Engine configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// iterations per thread
+    pub iterations: u32,
+    pub threads: u32,
+    /// seed for the per-thread random number generators; chosen at random when None
+    pub seed: Option<u64>,
+
+    /// opening book probed before searching
+    pub book: Option<BookFile>,
+    /// plies from the start within which the book is used
+    pub book_depth: u32,
+    /// whether book moves are chosen at random by weight, rather than the heaviest
+    pub book_random: bool,
+
+    pub uctc: f64,
+    pub uctw: f64,
+    pub move_probability: f64,
+    pub sim_threshold: u32,
+    pub sim_extend_path_bias: f64,
+    pub sim_extend_path_threshold: usize,
+    pub sim_shortest_walk_bias: f64,
+    pub path_diff_coeff: f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            iterations: ITERATIONS,
+            threads: THREADS,
+            seed: None,
+            book: None,
+            book_depth: BOOK_DEPTH,
+            book_random: false,
+            uctc: UCTC,
+            uctw: UCTW,
+            move_probability: MOVE_PROBABILITY,
+            sim_threshold: SIM_THRESHOLD,
+            sim_extend_path_bias: SIM_EXTEND_PATH_BIAS,
+            sim_extend_path_threshold: SIM_EXTEND_PATH_THRESHOLD,
+            sim_shortest_walk_bias: SIM_SHORTEST_WALK_BIAS,
+            path_diff_coeff: PATH_DIFF_COEFF,
+        }
+    }
+}
+
+impl Config {
+    /// Changes the setting named `key` to `value`
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "iterations" => self.iterations = positive(key, value)?,
+            "threads" => self.threads = positive(key, value)?,
+            "seed" => self.seed = Some(parse(key, value)?),
+            "book" => self.book = Some(BookFile::load(value)?),
+            "book_depth" => self.book_depth = parse(key, value)?,
+            "book_random" => self.book_random = parse(key, value)?,
+            "uctc" => self.uctc = parse(key, value)?,
+            "uctw" => self.uctw = parse(key, value)?,
+            "move_probability" => self.move_probability = parse(key, value)?,
+            "sim_threshold" => self.sim_threshold = parse(key, value)?,
+            "sim_extend_path_bias" => self.sim_extend_path_bias = parse(key, value)?,
+            "sim_extend_path_threshold" => self.sim_extend_path_threshold = parse(key, value)?,
+            "sim_shortest_walk_bias" => self.sim_shortest_walk_bias = parse(key, value)?,
+            "path_diff_coeff" => self.path_diff_coeff = parse(key, value)?,
+            _ => return Err(format!("unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Parses a comma separated list of `key=value` overrides of the default configuration, such as
+/// `iterations=10000,uctc=5000`
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Config::default();
+        settings::apply(s, |key, value| config.set(key, value))?;
+        Ok(config)
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "iterations={},threads={}", self.iterations, self.threads)?;
+        if let Some(seed) = self.seed {
+            write!(f, ",seed={}", seed)?;
+        }
+        if let Some(book) = &self.book {
+            write!(f, ",book={}", book.path)?;
+        }
+        write!(
+            f,
+            ",book_depth={},book_random={}",
+            self.book_depth, self.book_random
+        )?;
+        write!(
+            f,
+            ",uctc={},uctw={},move_probability={},sim_threshold={},sim_extend_path_bias={},\
+             sim_extend_path_threshold={},sim_shortest_walk_bias={},path_diff_coeff={}",
+            self.uctc,
+            self.uctw,
+            self.move_probability,
+            self.sim_threshold,
+            self.sim_extend_path_bias,
+            self.sim_extend_path_threshold,
+            self.sim_shortest_walk_bias,
+            self.path_diff_coeff
+        )
+    }
+}
+
 struct Node {
     board: Board,
-    children: Vec<Rc<RefCell<Node>>>,
+    children: Vec<Node>,
     value: f64,
     visits: u32,
 }
@@ -43,22 +167,195 @@ impl Node {
     }
 
     fn expand(&mut self) {
-        for child in self.board.moves_detailed(false, true, true, true) {
-            self.children.push(Rc::new(RefCell::new(Node::new(child))));
+        let mut children = self.board.moves_detailed(false, true, true, true);
+        // a boxed-in pawn may only have walls away from the pawns, which the limit skips
+        if children.is_empty() {
+            children = self.board.moves();
         }
+        self.children = children.into_iter().map(Node::new).collect();
     }
 
     fn update(&mut self, value: f64) {
         self.visits += 1;
         self.value = (self.value * (self.visits - 1) as f64 + value) / self.visits as f64;
     }
+
+    /// Finds the node for `board` within `depth` plies of this one, discarding the rest of the tree
+    fn into_subtree(self, board: &Board, depth: usize) -> Option<Node> {
+        if self.board == *board {
+            return Some(self);
+        }
+        if depth == 0 {
+            return None;
+        }
+        self.children
+            .into_iter()
+            .find_map(|child| child.into_subtree(board, depth - 1))
+    }
+}
+
+/// Search trees kept between searches, one per thread, so that a game's later searches can
+/// continue from the work done by earlier ones
+#[derive(Default)]
+pub struct Tree {
+    roots: Vec<Node>,
+}
+
+impl Tree {
+    /// The top of the trees left by the last search, merged across threads, for looking through
+    /// offline; None if nothing has been searched
+    ///
+    /// Nodes are followed `depth` plies from the root, keeping children visited at least
+    /// `min_visits` times.
+    pub fn export(&self, depth: usize, min_visits: u32) -> Option<TreeNode> {
+        if self.roots.is_empty() {
+            return None;
+        }
+        let roots: Vec<_> = self.roots.iter().collect();
+        Some(TreeNode::merged(None, &roots, depth, min_visits))
+    }
+
+    /// Takes each thread's tree for `board`, if it was reached within two plies of the last search
+    fn take(&mut self, board: &Board, threads: u32) -> Vec<Option<Node>> {
+        let mut roots: Vec<_> = self
+            .roots
+            .drain(..)
+            .map(|root| root.into_subtree(board, 2))
+            .collect();
+        roots.resize_with(threads as usize, || None);
+        roots
+    }
 }
 
-fn simulate(mut board: Board) -> f64 {
-    let mut rng = thread_rng();
+/// A search tree node with its statistics summed over the threads' trees
+#[derive(Clone, Debug, Serialize)]
+pub struct TreeNode {
+    /// the move reaching the node, or None at the root
+    #[serde(rename = "move")]
+    pub move_string: Option<String>,
+    pub tqbn: String,
+    pub visits: u32,
+    /// mean value from the perspective of the player who made the move, or None if proven
+    pub value: Option<f64>,
+    /// "win" or "loss" for the player who made the move, once the solver has proven it
+    pub proof: Option<&'static str>,
+    /// most visited first
+    pub children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    /// merges `nodes`, which are the same position in different threads' trees
+    fn merged(
+        move_string: Option<String>,
+        nodes: &[&Node],
+        depth: usize,
+        min_visits: u32,
+    ) -> TreeNode {
+        let visits = nodes.iter().map(|node| node.visits).sum();
+        // node values are for the player to move, so the player who moved scores their negation
+        let proof = nodes.iter().find_map(|node| {
+            if node.value == f64::INFINITY {
+                Some("loss")
+            } else if node.value == -f64::INFINITY {
+                Some("win")
+            } else {
+                None
+            }
+        });
+        let value = match proof {
+            Some(_) => None,
+            None if visits == 0 => Some(0.0),
+            None => Some(
+                -nodes
+                    .iter()
+                    .map(|node| node.value * node.visits as f64)
+                    .sum::<f64>()
+                    / visits as f64,
+            ),
+        };
+
+        let mut children = Vec::new();
+        if depth > 0 {
+            let board = &nodes[0].board;
+            let mut positions: Vec<(&Board, Vec<&Node>)> = Vec::new();
+            for child in nodes.iter().flat_map(|node| &node.children) {
+                match positions
+                    .iter_mut()
+                    .find(|(board, _)| **board == child.board)
+                {
+                    Some((_, same)) => same.push(child),
+                    None => positions.push((&child.board, vec![child])),
+                }
+            }
+            for (child_board, same) in positions {
+                if same.iter().map(|node| node.visits).sum::<u32>() >= min_visits {
+                    let move_string = board.move_string_to(child_board);
+                    children.push(TreeNode::merged(
+                        Some(move_string),
+                        &same,
+                        depth - 1,
+                        min_visits,
+                    ));
+                }
+            }
+            children.sort_by(|a, b| b.visits.cmp(&a.visits));
+        }
+
+        TreeNode {
+            move_string,
+            tqbn: nodes[0].board.to_tqbn(),
+            visits,
+            value,
+            proof,
+            children,
+        }
+    }
+
+    /// The tree as a Graphviz DOT graph, with proven wins in green and losses in red
+    pub fn to_dot(&self) -> String {
+        fn add(node: &TreeNode, id: &mut usize, dot: &mut String) -> usize {
+            let own_id = *id;
+            *id += 1;
+            let mut label = match &node.move_string {
+                Some(move_string) => move_string.clone(),
+                None => format!("root\\n{}", node.tqbn),
+            };
+            label.push_str(&format!("\\nvisits {}", node.visits));
+            match (node.value, node.proof) {
+                (_, Some(proof)) => label.push_str(&format!("\\nproven {}", proof)),
+                (Some(value), None) => label.push_str(&format!("\\nvalue {:.3}", value)),
+                (None, None) => {}
+            }
+            let color = match node.proof {
+                Some("win") => ", color=green",
+                Some(_) => ", color=red",
+                None => "",
+            };
+            dot.push_str(&format!("  n{} [label=\"{}\"{}];\n", own_id, label, color));
+            for child in &node.children {
+                let child_id = add(child, id, dot);
+                dot.push_str(&format!("  n{} -> n{};\n", own_id, child_id));
+            }
+            own_id
+        }
+
+        let mut dot = String::from("digraph tree {\n  node [shape=box, fontname=\"monospace\"];\n");
+        add(self, &mut 0, &mut dot);
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn simulate(mut board: Board, config: &Config, rng: &mut StdRng) -> f64 {
     let turn = board.turn();
 
     'turn: while !board.can_win() {
+        // a pawn cut off by a wall under blocking rules, or boxed in under straight jumps
+        if let Some(winner) = board.winner() {
+            let steps = f64::from(board.rules().size).powi(2);
+            return if winner == turn { -steps } else { steps };
+        }
+
         // early termination with no walls remaining
         if board.remaining_walls()[0] == 0 && board.remaining_walls()[1] == 0 {
             return board.shortest_path(turn).len() as f64
@@ -75,12 +372,13 @@ fn simulate(mut board: Board) -> f64 {
         }
         */
 
-        if board.remaining_walls()[board.turn() as usize] > 0 && rng.gen_bool(SIM_EXTEND_PATH_BIAS)
+        if board.remaining_walls()[board.turn() as usize] > 0
+            && rng.gen_bool(config.sim_extend_path_bias)
         {
             let shortest_path = board.shortest_path(board.turn().other()).len();
             for child in board.moves_detailed(false, true, true, false) {
                 if child.shortest_path(child.turn()).len()
-                    > shortest_path + SIM_EXTEND_PATH_THRESHOLD
+                    > shortest_path + config.sim_extend_path_threshold
                 {
                     board = child.clone();
                     continue 'turn;
@@ -89,7 +387,7 @@ fn simulate(mut board: Board) -> f64 {
         }
 
         // bias towards walking along shortest path
-        if rng.gen_bool(SIM_SHORTEST_WALK_BIAS) {
+        if rng.gen_bool(config.sim_shortest_walk_bias) {
             for child in board.moves_detailed(true, false, false, true) {
                 if child.other_pawn() == *board.shortest_path(board.turn()).first().unwrap()
                 //&& !child.can_win()
@@ -107,95 +405,109 @@ fn simulate(mut board: Board) -> f64 {
         }
 
         let mut choices: Vec<_> = (0..moves.len()).collect();
-        let mut next;
-        loop {
-            let choice = *choices.choose(&mut rng).unwrap();
-            next = &moves[choice];
-
+        let mut next = None;
+        while let Some(&choice) = choices.choose(rng) {
             let index = choices.iter().position(|x| *x == choice).unwrap();
             choices.remove(index);
 
-            if choices.len() == 0 || next.paths_exist() {
+            if moves[choice].paths_exist() {
                 //&& !next.can_win()) {
+                next = Some(moves[choice].clone());
                 break;
             };
         }
 
-        while !next.paths_exist() {
-            next = moves.choose(&mut rng).unwrap();
+        // every limited move may block a path, as for a boxed-in pawn with only far walls left
+        match next.or_else(|| board.moves().choose(rng).cloned()) {
+            Some(next) => board = next,
+            None => break,
         }
-
-        board = next.clone();
     }
 
-    return board.shortest_path(turn).len() as f64 - board.shortest_path(turn.other()).len() as f64;
+    board.shortest_path(turn).len() as f64 - board.shortest_path(turn.other()).len() as f64
 }
 
-fn solver(node: &Rc<RefCell<Node>>) -> f64 {
-    let mut node = node.borrow_mut();
+fn solver(node: &mut Node, config: &Config, rng: &mut StdRng) -> f64 {
+    // the player to move was cut off by a wall
+    if node.board.winner().is_some() {
+        node.update(-f64::INFINITY);
+        return -f64::INFINITY;
+    }
 
-    if node.children.len() == 0 {
+    if node.children.is_empty() {
         node.expand();
     }
+    // nothing to search, as at a root whose searchmoves aren't legal
+    if node.children.is_empty() {
+        return 0.0;
+    }
 
+    // a winning move is proven at once; it is visited so the root's lines show it, and searchmoves
+    // may have left it out
     if node.board.can_win() {
-        node.update(INFINITY);
-        return INFINITY;
+        let turn = node.board.turn();
+        if let Some(child) = node
+            .children
+            .iter_mut()
+            .find(|child| child.board.winner() == Some(turn))
+        {
+            child.update(-f64::INFINITY);
+            node.update(f64::INFINITY);
+            return f64::INFINITY;
+        }
     }
     if node.board.remaining_walls()[0] == 0 && node.board.remaining_walls()[1] == 1 {
         if node.board.shortest_path(node.board.turn()).len()
             <= node.board.shortest_path(node.board.turn().other()).len()
         {
-            node.update(INFINITY);
-            return INFINITY;
+            node.update(f64::INFINITY);
+            return f64::INFINITY;
         } else {
-            node.update(-INFINITY);
-            return -INFINITY;
+            node.update(-f64::INFINITY);
+            return -f64::INFINITY;
         }
     }
 
-    let mut selected = &node.children[0];
-    let mut best_uct = -INFINITY;
-    for child in &node.children {
-        let c = child.borrow();
+    let mut selected = 0;
+    let mut best_uct = -f64::INFINITY;
+    for (i, c) in node.children.iter().enumerate() {
         if c.visits == 0 {
-            selected = child;
+            selected = i;
             break;
         }
 
         let probability = if node.board.pawns()[node.board.turn() as usize]
             == c.board.pawns()[node.board.turn() as usize]
         {
-            1.0 - MOVE_PROBABILITY
+            1.0 - config.move_probability
         } else {
-            MOVE_PROBABILITY
+            config.move_probability
         };
         let uct = -c.value
-            + (UCTC * (node.visits as f64).ln() / c.visits as f64).sqrt()
-            + (UCTW * probability / (c.visits + 1) as f64);
+            + (config.uctc * (node.visits as f64).ln() / c.visits as f64).sqrt()
+            + (config.uctw * probability / (c.visits + 1) as f64);
         if uct > best_uct {
-            selected = child;
+            selected = i;
             best_uct = uct;
         }
     }
 
+    let selected = &mut node.children[selected];
     let mut r;
-    if selected.borrow().value == INFINITY || selected.borrow().value == -INFINITY {
-        r = -selected.borrow().value;
+    if selected.value == f64::INFINITY || selected.value == -f64::INFINITY {
+        r = -selected.value;
+    } else if selected.visits < config.sim_threshold {
+        let path_difference = simulate(selected.board.clone(), config, rng);
+        r = if path_difference >= 0.0 { 1.0 } else { -1.0 };
+        r += path_difference * config.path_diff_coeff;
+        selected.update(-r);
     } else {
-        if selected.borrow().visits < SIM_THRESHOLD {
-            let path_difference = simulate(selected.borrow().board.clone());
-            r = if path_difference >= 0.0 { 1.0 } else { -1.0 };
-            r += path_difference * PATH_DIFF_COEFF;
-            selected.borrow_mut().update(-r);
-        } else {
-            r = -solver(&Rc::clone(selected));
-        }
+        r = -solver(selected, config, rng);
     }
 
-    if r == -INFINITY {
+    if r == -f64::INFINITY {
         for child in &node.children {
-            if child.borrow().value != INFINITY {
+            if child.value != f64::INFINITY {
                 // TODO this might need to be adjusted if PATH_DIFF_COEFF ends up being useful (since |r| can be > 1.0)
                 r = -1.0;
                 break;
@@ -207,98 +519,571 @@ fn solver(node: &Rc<RefCell<Node>>) -> f64 {
     r
 }
 
-pub fn mcts(board: &Board, log: &mut String) -> Board {
+fn principal_variation(node: &Node) -> Vec<String> {
+    let mut pv = Vec::new();
+    let mut node = node;
+    while pv.len() < PV_LENGTH {
+        let next = match node.children.iter().max_by_key(|c| c.visits) {
+            Some(child) if child.visits > 0 => child,
+            _ => break,
+        };
+        pv.push(node.board.move_string_to(&next.board));
+        node = next;
+    }
+    pv
+}
+
+fn root_node(board: &Board, searchmoves: &[String]) -> Node {
+    let mut root = Node::new(board.clone());
+    if searchmoves.is_empty() {
+        root.expand();
+    } else {
+        // searchmoves may name walls the limited expansion would skip
+        for child in board.moves() {
+            if searchmoves.contains(&board.move_string_to(&child)) {
+                root.children.push(Node::new(child));
+            }
+        }
+    }
+    root
+}
+
+fn log_header(log: &mut String, config: &Config, seed: u64) {
     log.push_str(&format!("theseus {}\n", env!("CARGO_PKG_VERSION")));
     log.push_str(&format!("commit\t{}\n", env!("HEAD_SHA")));
-    log.push_str(&format!("patch\t{}\n\n", env!("PATCH_SHA")));
+    log.push_str(&format!("patch\t{}\n", env!("PATCH_SHA")));
+    log.push_str(&format!("seed\t{}\n\n", seed));
 
     log.push_str("mcts-solver search\n");
-    log.push_str(&format!("iterations:\t{}\n", ITERATIONS));
-    log.push_str(&format!("threads:\t{}\n\n", THREADS));
-    //log.push_str(&format!("total:\t\t{}\n\n", ITERATIONS * THREADS));
+    log.push_str(&format!("iterations:\t{}\n", config.iterations));
+    log.push_str(&format!("threads:\t{}\n\n", config.threads));
+    //log.push_str(&format!("total:\t\t{}\n\n", config.iterations * config.threads));
+}
+
+/// logs the search time, returning it in milliseconds
+///
+/// `iterations` is the number each thread ran, which is less than configured if the search was
+/// stopped early.
+fn log_time(log: &mut String, config: &Config, iterations: u32, start_time: SystemTime) -> u64 {
+    if iterations < config.iterations {
+        log.push_str(&format!("stopped after:\t{} iterations\n", iterations));
+    }
+    let think_time = SystemTime::now().duration_since(start_time);
+    if let Ok(think_time) = think_time {
+        let millis = think_time.as_millis();
+        log.push_str(&format!("time:\t\t{} ms\n", millis));
+        log.push_str(&format!(
+            "iter/s:\t\t{:.3}\n",
+            iterations as f64 / (millis as f64 / 1000.0)
+        ));
+        return millis as u64;
+    }
+    0
+}
+
+/// value, visits and principal variation of a root child in one thread's tree
+type ChildStats = (f64, u32, Vec<String>);
+
+fn child_stats(root: &Node) -> Vec<ChildStats> {
+    root.children
+        .iter()
+        .map(|child| (child.value, child.visits, principal_variation(child)))
+        .collect()
+}
 
+#[derive(Default)]
+struct Snapshot {
+    board: Option<Board>,
+    searchmoves: Vec<String>,
+    /// each thread's iterations so far and root child statistics as of its last report
+    threads: Vec<(u32, Vec<ChildStats>)>,
+}
+
+/// A handle on a running search, for watching its progress and stopping it early
+///
+/// Threads report their statistics every `REPORT_INTERVAL` iterations and when they finish.
+#[derive(Default)]
+pub struct Progress {
+    stopped: AtomicBool,
+    /// the search stops by itself at this time
+    deadline: Option<Instant>,
+    snapshot: Mutex<Snapshot>,
+}
+
+impl Progress {
+    /// A handle for a search that stops by itself after `limit`
+    pub fn with_time_limit(limit: Duration) -> Progress {
+        Progress {
+            deadline: Some(Instant::now() + limit),
+            ..Default::default()
+        }
+    }
+
+    /// Asks the search to stop; it returns its results so far once each thread notices
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+            || self
+                .deadline
+                .map_or(false, |deadline| Instant::now() >= deadline)
+    }
+
+    /// Iterations reported so far, summed over all threads
+    pub fn iterations(&self) -> u64 {
+        let snapshot = self.snapshot.lock().unwrap();
+        snapshot.threads.iter().map(|(n, _)| *n as u64).sum()
+    }
+
+    /// The `multipv` most visited root moves as of the threads' last reports, best first
+    pub fn lines(&self, multipv: usize) -> Vec<Line> {
+        let snapshot = self.snapshot.lock().unwrap();
+        let board = match &snapshot.board {
+            Some(board) => board,
+            None => return Vec::new(),
+        };
+        let reported: Vec<_> = snapshot
+            .threads
+            .iter()
+            .filter(|(_, stats)| !stats.is_empty())
+            .map(|(_, stats)| stats)
+            .collect();
+        if reported.is_empty() {
+            return Vec::new();
+        }
+        let (root, pvs) = merge(board, &snapshot.searchmoves, reported.iter().copied());
+        let mut lines = lines(board, &root, pvs, reported.len() as u32);
+        lines.truncate(multipv);
+        lines
+    }
+
+    fn start(&self, board: &Board, searchmoves: &[String], threads: u32) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        snapshot.board = Some(board.clone());
+        snapshot.searchmoves = searchmoves.to_vec();
+        snapshot.threads = vec![(0, Vec::new()); threads as usize];
+    }
+
+    fn report(&self, thread_index: usize, iterations: u32, root: &Node) {
+        let stats = child_stats(root);
+        self.snapshot.lock().unwrap().threads[thread_index] = (iterations, stats);
+    }
+}
+
+/// Sums the threads' statistics for each root child, keeping the principal variation from the
+/// thread that visited the child most
+fn merge<'a>(
+    board: &Board,
+    searchmoves: &[String],
+    threads: impl Iterator<Item = &'a Vec<ChildStats>>,
+) -> (Node, Vec<Vec<String>>) {
+    let mut root = root_node(board, searchmoves);
+    let mut pvs = vec![(0, Vec::new()); root.children.len()];
+    for stats in threads {
+        for (i, (value, visits, pv)) in stats.iter().enumerate() {
+            root.children[i].value += value;
+            root.children[i].visits += visits;
+            if *visits > pvs[i].0 {
+                pvs[i] = (*visits, pv.clone());
+            }
+        }
+    }
+    (root, pvs.into_iter().map(|(_, pv)| pv).collect())
+}
+
+/// Runs the search on every thread, returning a root whose children hold the merged statistics,
+/// along with the principal variation of each child from the thread that visited it most
+///
+/// Each thread's random number generator is derived from `seed`, and results are merged in thread
+/// order, so the same seed, thread count and iteration count always produce the same statistics.
+///
+/// Threads continue from the trees in `tree` where they reach `board`, and leave their trees there.
+fn search(
+    board: &Board,
+    config: &Config,
+    seed: u64,
+    searchmoves: &[String],
+    tree: &mut Tree,
+    progress: &Arc<Progress>,
+) -> (Node, Vec<Vec<String>>) {
     let (results_tx, results_rx) = mpsc::channel();
 
-    let start_time = SystemTime::now();
+    // trees are only reused when every root move is searched
+    let roots = if searchmoves.is_empty() {
+        tree.take(board, config.threads)
+    } else {
+        tree.roots.clear();
+        (0..config.threads).map(|_| None).collect()
+    };
+    progress.start(board, searchmoves, config.threads);
 
-    for _ in 0..THREADS {
+    for (thread_index, root) in roots.into_iter().enumerate() {
         let board = board.clone();
+        let config = config.clone();
+        let searchmoves = searchmoves.to_vec();
         let results_tx = results_tx.clone();
+        let progress = Arc::clone(progress);
         thread::spawn(move || {
-            let root = Rc::new(RefCell::new(Node::new(board.clone())));
-            for _ in 0..ITERATIONS {
-                solver(&root);
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(thread_index as u64));
+            let mut root = root.unwrap_or_else(|| root_node(&board, &searchmoves));
+            let mut iterations = 0;
+            while iterations < config.iterations && !progress.stopped() {
+                solver(&mut root, &config, &mut rng);
+                iterations += 1;
+                if iterations % REPORT_INTERVAL == 0 {
+                    progress.report(thread_index, iterations, &root);
+                }
             }
+            progress.report(thread_index, iterations, &root);
 
-            let mut results = Vec::new();
-            for child in &root.borrow().children {
-                results.push((child.borrow().value, child.borrow().visits));
-            }
-            results_tx.send(results).unwrap();
+            results_tx
+                .send((thread_index, child_stats(&root), root))
+                .unwrap();
         });
     }
     drop(results_tx);
 
-    let root = Rc::new(RefCell::new(Node::new(board.clone())));
-    root.borrow_mut().expand();
-    for data in results_rx {
-        for (i, (value, visits)) in data.iter().enumerate() {
-            root.borrow_mut().children[i].borrow_mut().value += value;
-            root.borrow_mut().children[i].borrow_mut().visits += visits;
-        }
+    let mut thread_results: Vec<_> = results_rx.iter().collect();
+    thread_results.sort_by_key(|(thread_index, _, _)| *thread_index);
+
+    let merged = merge(
+        board,
+        searchmoves,
+        thread_results.iter().map(|(_, stats, _)| stats),
+    );
+    if searchmoves.is_empty() {
+        tree.roots = thread_results
+            .into_iter()
+            .map(|(_, _, thread_root)| thread_root)
+            .collect();
     }
+    merged
+}
 
-    let end_time = SystemTime::now();
+/// Chooses a move, or None if the game is over
+pub fn mcts(board: &Board, config: &Config, log: &mut String) -> Option<Board> {
+    mcts_with_tree(board, config, &mut Tree::default(), log)
+}
 
-    let mut best_score = -INFINITY;
-    let mut best_child = root.borrow().children[0].clone();
+/// Like `mcts`, but continues from the search trees left in `tree` by an earlier search in the same
+/// game, and leaves this search's trees there for the next one
+pub fn mcts_with_tree(
+    board: &Board,
+    config: &Config,
+    tree: &mut Tree,
+    log: &mut String,
+) -> Option<Board> {
+    mcts_with_stats(board, config, tree, &Arc::default(), log).map(|(child, _, _)| child)
+}
+
+/// Statistics of the search behind a chosen move
+#[derive(Clone, Debug, Serialize)]
+pub struct SearchStats {
+    pub seed: u64,
+    /// iterations run per thread
+    pub iterations: u32,
+    pub threads: u32,
+    pub time_ms: u64,
+    /// number of root moves searched
+    pub moves: usize,
+    /// value of the chosen move, from the perspective of the player making it
+    pub value: f64,
+    pub visits: u32,
+    /// fraction of all root visits spent on the chosen move
+    pub visit_share: f64,
+}
+
+/// Like `mcts_with_tree`, also returning the search's statistics and every root move's line, most
+/// visited first, and reporting its progress through `progress`, which can also stop it
+pub fn mcts_with_stats(
+    board: &Board,
+    config: &Config,
+    tree: &mut Tree,
+    progress: &Arc<Progress>,
+    log: &mut String,
+) -> Option<(Board, SearchStats, Vec<Line>)> {
+    if board.winner().is_some() {
+        log.push_str("the game is over\n");
+        return None;
+    }
+    let seed = config.seed.unwrap_or_else(|| thread_rng().gen());
+    log_header(log, config, seed);
+
+    let start_time = SystemTime::now();
+    let (root, pvs) = search(board, config, seed, &[], tree, progress);
+
+    let mut best_score = -f64::INFINITY;
+    let mut best_child = root.children.first()?;
     let mut walking_shortest_path = false;
-    for child in &root.borrow().children {
-        if -child.borrow().value > best_score {
-            best_score = -child.borrow().value;
-            best_child = child.clone();
+    for child in &root.children {
+        if -child.value > best_score {
+            best_score = -child.value;
+            best_child = child;
             walking_shortest_path = false;
-        } else if -child.borrow().value == best_score {
+        } else if -child.value == best_score {
             // prioritizing walking shortest path
             let board = board.clone();
-            if child.borrow().board.other_pawn()
-                == *board.shortest_path(board.turn()).first().unwrap()
-            {
-                best_child = child.clone();
+            if child.board.other_pawn() == *board.shortest_path(board.turn()).first().unwrap() {
+                best_child = child;
                 walking_shortest_path = true;
             }
         }
     }
 
-    let think_time = end_time.duration_since(start_time);
-    if think_time.is_ok() {
-        let millis = think_time.unwrap().as_millis();
-        log.push_str(&format!("time:\t\t{} ms\n", millis));
-        log.push_str(&format!(
-            "iter/s:\t\t{:.3}\n",
-            (ITERATIONS) as f64 / (millis as f64 / 1000.0)
-        ));
-    }
-    log.push_str(&format!("moves:\t\t{}\n\n", root.borrow().children.len()));
+    let iterations = (progress.iterations() / config.threads as u64) as u32;
+    let time_ms = log_time(log, config, iterations, start_time);
+    log.push_str(&format!("moves:\t\t{}\n\n", root.children.len()));
 
     if walking_shortest_path {
-        log.push_str(&format!("walking shortest path\n"));
+        log.push_str("walking shortest path\n");
     }
     log.push_str(&format!(
         "value:\t\t{:.3}\n",
-        -best_child.borrow().value / (THREADS as f64)
+        -best_child.value / (config.threads as f64)
     ));
-    log.push_str(&format!("visits:\t\t{}\n", best_child.borrow().visits));
+    // a reused tree's visits include earlier searches', so shares are of the root's visits
+    let total_visits: u32 = root.children.iter().map(|c| c.visits).sum();
+    let visit_share = if total_visits > 0 {
+        best_child.visits as f64 / total_visits as f64
+    } else {
+        0.0
+    };
+    log.push_str(&format!("visits:\t\t{}\n", best_child.visits));
     log.push_str(&format!(
         "focus:\t\t{:.3}\n",
-        (best_child.borrow().visits as f64)
-            / ((ITERATIONS * THREADS) as f64 / root.borrow().children.len() as f64)
-    ));
-    log.push_str(&format!(
-        "visit %:\t{:.3}%\n\n",
-        100.0 * best_child.borrow().visits as f64 / (ITERATIONS * THREADS) as f64
+        visit_share * root.children.len() as f64
     ));
+    log.push_str(&format!("visit %:\t{:.3}%\n\n", 100.0 * visit_share));
+
+    let stats = SearchStats {
+        seed,
+        iterations,
+        threads: config.threads,
+        time_ms,
+        moves: root.children.len(),
+        value: -best_child.value / (config.threads as f64),
+        visits: best_child.visits,
+        visit_share,
+    };
+    let lines = lines(board, &root, pvs, config.threads);
+    Some((best_child.board.clone(), stats, lines))
+}
+
+/// A candidate root move with its merged search statistics
+#[derive(Clone, Debug)]
+pub struct Line {
+    pub board: Board,
+    pub move_string: String,
+    /// value from the perspective of the player making the move
+    pub value: f64,
+    pub visits: u32,
+    /// fraction of all root visits spent on this move
+    pub visit_share: f64,
+    /// principal variation, starting with this move
+    pub pv: Vec<String>,
+}
+
+/// Converts merged root statistics from `threads` threads into lines, most visited first
+fn lines(board: &Board, root: &Node, pvs: Vec<Vec<String>>, threads: u32) -> Vec<Line> {
+    let total_visits: u32 = root.children.iter().map(|c| c.visits).sum();
+    let mut lines = Vec::new();
+    for (child, pv) in root.children.iter().zip(pvs) {
+        let move_string = board.move_string_to(&child.board);
+        let mut line_pv = vec![move_string.clone()];
+        line_pv.extend(pv);
+        lines.push(Line {
+            board: child.board.clone(),
+            move_string,
+            value: -child.value / (threads as f64),
+            visits: child.visits,
+            visit_share: if total_visits > 0 {
+                child.visits as f64 / total_visits as f64
+            } else {
+                0.0
+            },
+            pv: line_pv,
+        });
+    }
+    lines.sort_by(|a, b| b.visits.cmp(&a.visits));
+    lines
+}
+
+/// Searches the position and returns the `multipv` most visited root moves, best first
+///
+/// If `searchmoves` is not empty, only those moves are considered at the root.
+pub fn analyze(
+    board: &Board,
+    config: &Config,
+    multipv: usize,
+    searchmoves: &[String],
+    log: &mut String,
+) -> Vec<Line> {
+    analyze_with_progress(board, config, multipv, searchmoves, &Arc::default(), log)
+}
+
+/// Like `analyze`, reporting the search's progress through `progress`, which can also stop it
+pub fn analyze_with_progress(
+    board: &Board,
+    config: &Config,
+    multipv: usize,
+    searchmoves: &[String],
+    progress: &Arc<Progress>,
+    log: &mut String,
+) -> Vec<Line> {
+    let seed = config.seed.unwrap_or_else(|| thread_rng().gen());
+    log_header(log, config, seed);
+    log.push_str(&format!("multipv:\t{}\n", multipv));
+    if !searchmoves.is_empty() {
+        log.push_str(&format!("searchmoves:\t{}\n", searchmoves.join(" ")));
+    }
+    log.push('\n');
+
+    if board.winner().is_some() || root_node(board, searchmoves).children.is_empty() {
+        log.push_str("no moves to search\n");
+        return Vec::new();
+    }
+
+    let start_time = SystemTime::now();
+    let (root, pvs) = search(
+        board,
+        config,
+        seed,
+        searchmoves,
+        &mut Tree::default(),
+        progress,
+    );
+    let iterations = (progress.iterations() / config.threads as u64) as u32;
+    log_time(log, config, iterations, start_time);
+    log.push_str(&format!("moves:\t\t{}\n\n", root.children.len()));
+
+    let mut lines = lines(board, &root, pvs, config.threads);
+    lines.truncate(multipv);
+
+    for (i, line) in lines.iter().enumerate() {
+        log.push_str(&format!(
+            "{}\t{}\tvalue {:.3}\tvisits {} ({:.1}%)\tpv {}\n",
+            i + 1,
+            line.move_string,
+            line.value,
+            line.visits,
+            100.0 * line.visit_share,
+            line.pv.join(" ")
+        ));
+    }
+    log.push('\n');
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config: Config = "iterations=20, threads=1, seed=4".parse().unwrap();
+        assert_eq!(config.iterations, 20);
+        assert_eq!(config.threads, 1);
+        assert_eq!(config.to_string().parse::<Config>().unwrap(), config);
+        assert!("threads=0".parse::<Config>().is_err());
+        assert!("iterations=0".parse::<Config>().is_err());
+        assert!("threads=two".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn boxed_in_under_straight_jumps() {
+        // white, boxed in on e5 by walls and black, has no moves and loses
+        let walls = "nnnnnnnnnnnnnnnnnnnnhnnnnnnnvnnnnnnvhnnnnnnnnnnnnnnnnnnnnnnnnnnn";
+        let stuck = Board::from_tqbn(&format!("{}1e500e400:jumps=straight", walls));
+        let config = Config {
+            iterations: 100,
+            threads: 1,
+            seed: Some(1),
+            ..Default::default()
+        };
+        assert!(mcts(&stuck, &config, &mut String::new()).is_none());
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(simulate(stuck, &config, &mut rng), 81.0);
+
+        let board = Board::from_tqbn(&format!("{}2e500d403:jumps=straight", walls));
+        let child = crate::ai::configured(&board, &config, &mut String::new()).unwrap();
+        assert_eq!(board.move_string_to(&child), "e4");
+        assert!(mcts(&board, &config, &mut String::new()).is_some());
+    }
 
-    let board = best_child.borrow().board.clone();
-    board
+    #[test]
+    fn analyze_proven_win() {
+        // white is a step from its goal
+        let board = Board::from_tqbn("nnnnnnnnnnnnnnnn1c206c405:size=5,walls=6");
+        let config = Config {
+            iterations: 50,
+            threads: 2,
+            seed: Some(2),
+            ..Default::default()
+        };
+        let lines = analyze(&board, &config, 3, &[], &mut String::new());
+        assert_eq!(lines[0].move_string, "c1");
+        assert_eq!(lines[0].value, f64::INFINITY);
+        assert_eq!(lines[0].visits, 100);
+
+        let searchmoves = [String::from("b2"), String::from("c1")];
+        let lines = analyze(&board, &config, 3, &searchmoves, &mut String::new());
+        assert_eq!(lines[0].move_string, "c1");
+        assert_eq!(lines[0].value, f64::INFINITY);
+        // without the win, the other moves are searched, and b2 lets black win at once
+        let lines = analyze(&board, &config, 3, &searchmoves[..1], &mut String::new());
+        assert!(lines[0].visits > 0);
+        assert_eq!(lines[0].value, -f64::INFINITY);
+
+        let searchmoves = [String::from("e5")];
+        assert!(analyze(&board, &config, 3, &searchmoves, &mut String::new()).is_empty());
+    }
+
+    #[test]
+    fn export_tree() {
+        let board = Board::with_rules("size=5".parse().unwrap());
+        let config = Config {
+            iterations: 300,
+            threads: 2,
+            seed: Some(3),
+            ..Default::default()
+        };
+        let mut tree = Tree::default();
+        assert!(tree.export(2, 0).is_none());
+        mcts_with_tree(&board, &config, &mut tree, &mut String::new()).unwrap();
+
+        let root = tree.export(2, 20).unwrap();
+        assert_eq!(root.move_string, None);
+        assert_eq!(root.tqbn, board.to_tqbn());
+        assert_eq!(root.visits, 600);
+        assert!(!root.children.is_empty());
+        let mut nodes = 1;
+        for child in &root.children {
+            assert!(child.visits >= 20);
+            assert!(board
+                .child_for_move_string(child.move_string.as_ref().unwrap())
+                .is_some());
+            for grandchild in &child.children {
+                assert!(grandchild.children.is_empty());
+                nodes += 1;
+            }
+            nodes += 1;
+        }
+        assert!(root
+            .children
+            .windows(2)
+            .all(|pair| pair[0].visits >= pair[1].visits));
+
+        let dot = root.to_dot();
+        assert!(dot.starts_with("digraph tree {"));
+        assert_eq!(dot.matches(" -> ").count(), nodes - 1);
+        let json = serde_json::to_value(&root).unwrap();
+        assert_eq!(json["visits"], 600);
+        assert_eq!(
+            json["children"][0]["move"],
+            root.children[0].move_string.as_ref().unwrap().as_str()
+        );
+    }
 }
@@ -0,0 +1,363 @@
+//! Monte Carlo tree search for four-player games
+//!
+//! Each node keeps a value for every player rather than a single one for the player to move, and
+//! each player picks the child that is best for itself (max-n). With paranoid backup, the other
+//! three players are instead assumed to be working together against the player searching, which
+//! plays more cautiously when the others are close to their goals.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng, SeedableRng};
+
+use crate::four::{FourBoard, PLAYERS};
+use crate::settings::{self, parse, positive};
+
+const ITERATIONS: u32 = 5000;
+const UCTC: f64 = 0.5;
+const WALK_BIAS: f64 = 0.7;
+const ROLLOUT_PLIES: usize = 40;
+
+/// How values are backed up the tree
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backup {
+    /// each player maximizes its own value
+    MaxN,
+    /// everyone else minimizes the searching player's value
+    Paranoid,
+}
+
+impl FromStr for Backup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "maxn" => Ok(Backup::MaxN),
+            "paranoid" => Ok(Backup::Paranoid),
+            _ => Err(format!("invalid value for backup: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Backup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Backup::MaxN => write!(f, "maxn"),
+            Backup::Paranoid => write!(f, "paranoid"),
+        }
+    }
+}
+
+/// Four-player engine configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct FourConfig {
+    pub iterations: u32,
+    /// seed for the random number generator; chosen at random when None
+    pub seed: Option<u64>,
+    pub backup: Backup,
+    pub uctc: f64,
+    /// chance that a rollout move walks the shortest path rather than being chosen at random
+    pub walk_bias: f64,
+    /// plies played out before a rollout is scored by who is closest to their goal
+    pub rollout_plies: usize,
+}
+
+impl Default for FourConfig {
+    fn default() -> Self {
+        FourConfig {
+            iterations: ITERATIONS,
+            seed: None,
+            backup: Backup::MaxN,
+            uctc: UCTC,
+            walk_bias: WALK_BIAS,
+            rollout_plies: ROLLOUT_PLIES,
+        }
+    }
+}
+
+impl FourConfig {
+    /// Changes the setting named `key` to `value`
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "iterations" => self.iterations = positive(key, value)?,
+            "seed" => self.seed = Some(parse(key, value)?),
+            "backup" => self.backup = value.parse()?,
+            "uctc" => self.uctc = parse(key, value)?,
+            "walk_bias" => match parse(key, value)? {
+                bias if (0.0..=1.0).contains(&bias) => self.walk_bias = bias,
+                _ => return Err(format!("{} must be between 0 and 1", key)),
+            },
+            "rollout_plies" => self.rollout_plies = parse(key, value)?,
+            _ => return Err(format!("unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Parses comma separated `key=value` overrides of the default configuration, such as
+/// `iterations=2000,backup=paranoid`
+impl FromStr for FourConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = FourConfig::default();
+        settings::apply(s, |key, value| config.set(key, value))?;
+        Ok(config)
+    }
+}
+
+impl fmt::Display for FourConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "iterations={}", self.iterations)?;
+        if let Some(seed) = self.seed {
+            write!(f, ",seed={}", seed)?;
+        }
+        write!(
+            f,
+            ",backup={},uctc={},walk_bias={},rollout_plies={}",
+            self.backup, self.uctc, self.walk_bias, self.rollout_plies
+        )
+    }
+}
+
+/// each player's share of a result: 1 for the winner and 0 for everyone else
+type Values = [f64; PLAYERS];
+
+fn win_for(player: usize) -> Values {
+    let mut values = [0.0; PLAYERS];
+    values[player] = 1.0;
+    values
+}
+
+struct Node {
+    board: FourBoard,
+    children: Vec<Node>,
+    /// sum of each player's values over the visits
+    values: Values,
+    visits: u32,
+}
+
+impl Node {
+    fn new(board: FourBoard) -> Node {
+        Node {
+            board,
+            children: Vec::new(),
+            values: [0.0; PLAYERS],
+            visits: 0,
+        }
+    }
+
+    fn update(&mut self, values: &Values) {
+        self.visits += 1;
+        for (sum, value) in self.values.iter_mut().zip(values) {
+            *sum += value;
+        }
+    }
+
+    fn mean(&self, player: usize) -> f64 {
+        self.values[player] / self.visits as f64
+    }
+}
+
+/// Scores a position by which player would win a race to the goals, if nobody placed more walls
+fn race(board: &FourBoard) -> Values {
+    let winner = (0..PLAYERS)
+        .min_by_key(|player| {
+            let steps = board.shortest_path(*player).len();
+            let order = (player + PLAYERS - board.turn()) % PLAYERS;
+            steps.saturating_sub(1) * PLAYERS + order
+        })
+        .unwrap();
+    win_for(winner)
+}
+
+fn rollout(mut board: FourBoard, config: &FourConfig, rng: &mut StdRng) -> Values {
+    for _ in 0..config.rollout_plies {
+        if let Some(winner) = board.winner() {
+            return win_for(winner);
+        }
+
+        let turn = board.turn();
+        if rng.gen_bool(config.walk_bias) {
+            let next = board.shortest_path(turn)[0];
+            let steps = board.moves_detailed(true, false);
+            if let Some(child) = steps.iter().find(|child| child.pawns()[turn] == next) {
+                board = child.clone();
+                continue;
+            }
+        }
+
+        // unvalidated moves are cheap to generate; check paths only for the one chosen
+        let mut moves = board.moves_detailed(false, false);
+        moves.shuffle(rng);
+        match moves
+            .into_iter()
+            .find(|child| child.pawns()[turn] != board.pawns()[turn] || child.paths_exist())
+        {
+            Some(child) => board = child,
+            None => break,
+        }
+    }
+    match board.winner() {
+        Some(winner) => win_for(winner),
+        None => race(&board),
+    }
+}
+
+/// The values backed up for a result, from the perspective of `player`, who is searching
+fn backed_up(values: Values, player: usize, backup: Backup) -> Values {
+    match backup {
+        Backup::MaxN => values,
+        // everyone else scores what the searching player loses
+        Backup::Paranoid => {
+            let mut paranoid = [1.0 - values[player]; PLAYERS];
+            paranoid[player] = values[player];
+            paranoid
+        }
+    }
+}
+
+/// Runs one iteration from `node`, returning the values backed up through it
+fn iterate(node: &mut Node, player: usize, config: &FourConfig, rng: &mut StdRng) -> Values {
+    let winner = node.board.winner();
+    if winner.is_none() && node.visits > 0 && node.children.is_empty() {
+        node.children = node.board.moves().into_iter().map(Node::new).collect();
+    }
+
+    let values = if winner.is_some() || node.children.is_empty() {
+        let values = match winner {
+            Some(winner) => win_for(winner),
+            None if node.visits == 0 => rollout(node.board.clone(), config, rng),
+            // a pawn boxed in by the others, with no walls left
+            None => race(&node.board),
+        };
+        backed_up(values, player, config.backup)
+    } else {
+        let mover = node.board.turn();
+        let log_visits = (node.visits as f64).ln();
+        let mut selected = 0;
+        let mut best_uct = -f64::INFINITY;
+        for (i, child) in node.children.iter().enumerate() {
+            if child.visits == 0 {
+                selected = i;
+                break;
+            }
+            let uct = child.mean(mover) + (config.uctc * log_visits / child.visits as f64).sqrt();
+            if uct > best_uct {
+                selected = i;
+                best_uct = uct;
+            }
+        }
+        iterate(&mut node.children[selected], player, config, rng)
+    };
+    node.update(&values);
+    values
+}
+
+/// Chooses a move for the player to move in a four-player game, or None if the game is over or
+/// the player has no legal moves
+pub fn mcts4(board: &FourBoard, config: &FourConfig, log: &mut String) -> Option<FourBoard> {
+    let seed = config.seed.unwrap_or_else(|| thread_rng().gen());
+    log.push_str(&format!("theseus {}\n", env!("CARGO_PKG_VERSION")));
+    log.push_str(&format!("seed\t{}\n\n", seed));
+    log.push_str("four-player mcts search\n");
+    log.push_str(&format!("iterations:\t{}\n", config.iterations));
+    log.push_str(&format!("backup:\t\t{}\n\n", config.backup));
+
+    if board.winner().is_some() {
+        log.push_str("the game is over\n");
+        return None;
+    }
+    let player = board.turn();
+    for child in board.moves_detailed(true, false) {
+        if child.winner() == Some(player) {
+            log.push_str("presearch: taking win\n");
+            return Some(child);
+        }
+    }
+
+    let start_time = SystemTime::now();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut root = Node::new(board.clone());
+    root.children = board.moves().into_iter().map(Node::new).collect();
+    if root.children.is_empty() {
+        log.push_str("no legal moves\n");
+        return None;
+    }
+    for _ in 0..config.iterations {
+        iterate(&mut root, player, config, &mut rng);
+    }
+
+    let best = root.children.iter().max_by_key(|child| child.visits)?;
+    if let Ok(time) = SystemTime::now().duration_since(start_time) {
+        log.push_str(&format!("time:\t\t{} ms\n", time.as_millis()));
+    }
+    log.push_str(&format!("moves:\t\t{}\n", root.children.len()));
+    log.push_str(&format!("value:\t\t{:.3}\n", best.mean(player)));
+    log.push_str(&format!("visits:\t\t{}\n", best.visits));
+    Some(best.board.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config: FourConfig = "iterations=20, backup=paranoid".parse().unwrap();
+        assert_eq!(config.iterations, 20);
+        assert_eq!(config.backup, Backup::Paranoid);
+        assert_eq!(config.to_string().parse::<FourConfig>().unwrap(), config);
+        assert!("backup=best".parse::<FourConfig>().is_err());
+        assert!("walk_bias=1.5".parse::<FourConfig>().is_err());
+        assert_eq!(
+            "iterations=0".parse::<FourConfig>(),
+            Err(String::from("iterations must be at least 1"))
+        );
+    }
+
+    #[test]
+    fn takes_win() {
+        let walls = "n".repeat(64);
+        let board = FourBoard::parse_tqbn(&format!("{}2e905h505e105i405", walls)).unwrap();
+        let child = mcts4(&board, &FourConfig::default(), &mut String::new()).unwrap();
+        assert_eq!(board.move_string_to(&child), "i5");
+        assert_eq!(
+            mcts4(&child, &FourConfig::default(), &mut String::new()),
+            None
+        );
+    }
+
+    #[test]
+    fn no_legal_moves() {
+        // player 2 is cornered on a1 by a wall and the pawns on b1 and c1, with no walls left
+        let walls = format!("h{}", "n".repeat(63));
+        let board = FourBoard::parse_tqbn(&format!("{}2e905a100c105b105", walls)).unwrap();
+        assert_eq!(board.validate(), Ok(()));
+        assert!(board.moves().is_empty());
+        assert_eq!(
+            mcts4(&board, &FourConfig::default(), &mut String::new()),
+            None
+        );
+    }
+
+    #[test]
+    fn seeded_search_repeats() {
+        for backup in [Backup::MaxN, Backup::Paranoid].iter() {
+            let config = FourConfig {
+                iterations: 200,
+                seed: Some(7),
+                backup: *backup,
+                ..Default::default()
+            };
+            let board = FourBoard::new();
+            let first = mcts4(&board, &config, &mut String::new()).unwrap();
+            let second = mcts4(&board, &config, &mut String::new()).unwrap();
+            assert_eq!(first, second);
+            assert!(board.moves().contains(&first));
+        }
+    }
+}
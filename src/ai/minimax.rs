@@ -1,4 +1,3 @@
-use crate::board::Player::*;
 use crate::board::{Board, Player};
 
 use std::f64::INFINITY;
@@ -8,14 +7,8 @@ const DEPTH: u8 = 2;
 fn count_win_steps(board: &Board, player: Player) -> u32 {
     let steps = board.walk_paths(player);
     let mut sum = 0;
-    if player == White {
-        for sqnum in 0..9 {
-            sum += steps[sqnum] as u32;
-        }
-    } else if player == Black {
-        for sqnum in 72..81 {
-            sum += steps[sqnum] as u32;
-        }
+    for sqnum in board.goal(player) {
+        sum += steps[sqnum as usize];
     }
     sum
 }
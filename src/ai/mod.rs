@@ -4,13 +4,29 @@
 //mod minimax;
 //pub use minimax::minimax;
 
+mod book;
+pub use book::{Book, BookFile};
+
 mod mcts;
-pub use mcts::mcts;
+pub use mcts::{
+    analyze, analyze_with_progress, mcts, mcts_with_stats, mcts_with_tree, Config, Line, Progress,
+    SearchStats, Tree, TreeNode,
+};
 
-fn presearch(board: &crate::Board, log: &mut String) -> Option<crate::Board> {
+fn presearch(board: &crate::Board, config: &Config, log: &mut String) -> Option<crate::Board> {
     if board.can_win() {
         log.push_str("presearch: taking win\n");
-        return Some(board.moves_detailed(true, false, false, true)[0].clone());
+        // walls can win too when they may cut the other pawn off
+        return Some(board.moves_detailed(false, true, false, true)[0].clone());
+    }
+    if let Some(book) = &config.book {
+        if let Some(child) = book.probe(board, config.book_depth, config.book_random, config.seed) {
+            log.push_str(&format!(
+                "presearch: book move {}\n",
+                board.move_string_to(&child)
+            ));
+            return Some(child);
+        }
     }
     if board.remaining_walls()[0] == 0 && board.remaining_walls()[1] == 0 {
         log.push_str("presearch: walking shortest path\n");
@@ -23,12 +39,68 @@ fn presearch(board: &crate::Board, log: &mut String) -> Option<crate::Board> {
     None
 }
 
-pub fn default(board: &crate::Board, log: &mut String) -> crate::Board {
-    if let Some(board) = presearch(board, log) {
-        return board;
+pub fn default(board: &crate::Board, log: &mut String) -> Option<crate::Board> {
+    configured(board, &Config::default(), log)
+}
+
+/// Chooses a move with the engine settings in `config`, or None if the game is over
+pub fn configured(board: &crate::Board, config: &Config, log: &mut String) -> Option<crate::Board> {
+    configured_with_tree(board, config, &mut Tree::default(), log)
+}
+
+/// Like `configured`, but reuses the search trees in `tree` from earlier moves in the same game
+pub fn configured_with_tree(
+    board: &crate::Board,
+    config: &Config,
+    tree: &mut Tree,
+    log: &mut String,
+) -> Option<crate::Board> {
+    decide(board, config, tree, &Default::default(), log).map(|decision| decision.board)
+}
+
+/// A move chosen by the engine
+pub struct Decision {
+    pub board: crate::Board,
+    /// statistics of the search that chose the move, or None if it was chosen without searching
+    pub stats: Option<SearchStats>,
+    /// every root move searched, most visited first; empty if the move was chosen without searching
+    pub lines: Vec<Line>,
+}
+
+/// Chooses a move like `configured_with_tree`, also returning the search's statistics
+///
+/// `progress` reports on the search and can stop it early.
+pub fn decide(
+    board: &crate::Board,
+    config: &Config,
+    tree: &mut Tree,
+    progress: &std::sync::Arc<Progress>,
+    log: &mut String,
+) -> Option<Decision> {
+    if board.winner().is_some() {
+        log.push_str("the game is over\n");
+        return None;
     }
-    mcts(board, log)
+    if let Some(board) = presearch(board, config, log) {
+        return Some(Decision {
+            board,
+            stats: None,
+            lines: Vec::new(),
+        });
+    }
+    let (board, stats, lines) = mcts_with_stats(board, config, tree, progress, log)?;
+    Some(Decision {
+        board,
+        stats: Some(stats),
+        lines,
+    })
 }
 
-//mod random;
-//pub use random::random;
+mod mcts4;
+pub use mcts4::{mcts4, Backup, FourConfig};
+
+mod random;
+pub use random::random;
+
+mod solver;
+pub use solver::{moves_to_win, winning_line, winning_moves, wins_within};
@@ -2,7 +2,7 @@ use crate::board::Board;
 
 use rand::prelude::*;
 
-pub fn random(board: &Board) -> Board {
-    let moves = board.moves();
-    moves[rand::thread_rng().gen_range(0, moves.len())].clone()
+/// Chooses a legal move at random, or None if there is none
+pub fn random<R: Rng>(board: &Board, rng: &mut R) -> Option<Board> {
+    board.moves().choose(rng).cloned()
 }
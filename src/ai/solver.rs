@@ -0,0 +1,123 @@
+//! Exact search for short forced wins
+//!
+//! Unlike the MCTS solver, which proves results within the limited wall expansion of its tree,
+//! this searches every legal move, so a result is a proof. The cost grows steeply with the
+//! number of moves, so it is meant for endgames and puzzles a few moves deep.
+
+use crate::board::{Board, Jumps};
+
+/// whether the player to move could reach its goal within `moves` of its own moves, counting a
+/// jump as two squares; walls never shorten a path, so if not there is no forced win, unless the
+/// rules let it win by cutting off or boxing in the other pawn
+fn within_reach(board: &Board, moves: u32) -> bool {
+    let rules = board.rules();
+    rules.blocking
+        || rules.jumps == Jumps::Straight
+        || board.shortest_path(board.turn()).len() as u32 <= 2 * moves
+}
+
+/// Whether the player to move can force a win within `moves` of its own moves
+pub fn wins_within(board: &Board, moves: u32) -> bool {
+    if moves == 0 || board.winner().is_some() {
+        return false;
+    }
+    if board.can_win() {
+        return true;
+    }
+    if moves == 1 || !within_reach(board, moves) {
+        return false;
+    }
+    board
+        .moves()
+        .iter()
+        .any(|child| forces_win(board, child, moves))
+}
+
+/// whether playing `child` from `board` forces a win within `moves` moves, the first of which
+/// reaches `child`
+fn forces_win(board: &Board, child: &Board, moves: u32) -> bool {
+    if child.winner() == Some(board.turn()) {
+        return true;
+    }
+    // the other player wins first if it can win at once
+    if moves == 1 || child.can_win() {
+        return false;
+    }
+    // a defender with no moves has lost, as `winner` reports under straight jumps
+    let replies = child.moves();
+    replies.is_empty() || replies.iter().all(|reply| wins_within(reply, moves - 1))
+}
+
+/// The moves that force a win within `moves` of the mover's own moves, including the first
+pub fn winning_moves(board: &Board, moves: u32) -> Vec<Board> {
+    if board.winner().is_some() || !within_reach(board, moves) {
+        return Vec::new();
+    }
+    board
+        .moves()
+        .into_iter()
+        .filter(|child| forces_win(board, child, moves))
+        .collect()
+}
+
+/// The fewest of its own moves the player to move needs to force a win, if it is at most
+/// `max_moves`
+pub fn moves_to_win(board: &Board, max_moves: u32) -> Option<u32> {
+    (1..=max_moves).find(|moves| wins_within(board, *moves))
+}
+
+/// A line of play from `board` after `child`, if it forces a win within `moves` moves: the
+/// defender's replies hold out longest and the attacker wins as fast as it can
+pub fn winning_line(board: &Board, child: &Board, moves: u32) -> Option<Vec<String>> {
+    let mut line = vec![board.move_string_to(child)];
+    if child.winner() == Some(board.turn()) {
+        return Some(line);
+    }
+    if moves <= 1 || child.winner().is_some() {
+        return None;
+    }
+    // a reply escaping the forced win holds out longest of all
+    let reply = child
+        .moves()
+        .into_iter()
+        .max_by_key(|reply| moves_to_win(reply, moves - 1).unwrap_or(u32::MAX))?;
+    line.push(child.move_string_to(&reply));
+    let remaining = moves_to_win(&reply, moves - 1)?;
+    let next = winning_moves(&reply, remaining).into_iter().next()?;
+    line.extend(winning_line(&reply, &next, remaining)?);
+    Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forced_wins() {
+        // white is a step from its goal
+        let board = Board::from_tqbn("nnnnnnnnnnnnnnnn1c206c405");
+        assert!(wins_within(&board, 1));
+        assert_eq!(moves_to_win(&board, 3), Some(1));
+        let wins = winning_moves(&board, 1);
+        assert_eq!(wins.len(), 1);
+        assert_eq!(board.move_string_to(&wins[0]), "c1");
+
+        // black is also a step from its goal, so white must win at once or lose
+        let board = Board::from_tqbn("nnnnnnnnnnnnnnnn1c306c405");
+        assert_eq!(moves_to_win(&board, 2), None);
+        assert!(winning_moves(&board, 2).is_empty());
+        let child = board.child_for_move_string("c2").unwrap();
+        assert_eq!(winning_line(&board, &child, 2), None);
+
+        // under straight jumps, black wins by boxing white in on e5, leaving it no replies
+        let walls = "nnnnnnnnnnnnnnnnnnnnhnnnnnnnvnnnnnnvhnnnnnnnnnnnnnnnnnnnnnnnnnnn";
+        let board = Board::from_tqbn(&format!("{}2e500d400:jumps=straight", walls));
+        assert_eq!(moves_to_win(&board, 2), Some(1));
+        let wins = winning_moves(&board, 2);
+        assert_eq!(wins.len(), 1);
+        assert_eq!(
+            winning_line(&board, &wins[0], 2),
+            Some(vec![String::from("e4")])
+        );
+    }
+}
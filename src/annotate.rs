@@ -0,0 +1,397 @@
+//! Game review: searching each position of a game record to judge the move played there
+//!
+//! A move's loss is how much less it is worth than the engine's choice, both from the perspective
+//! of the player making it. Moves losing at least `inaccuracy`, `mistake` or `blunder` are flagged
+//! as such. Missing a win in one, or playing into a proven loss, loses an infinite amount and is
+//! always a blunder.
+//!
+//! The annotated record keeps any existing comments and adds one to every move:
+//!
+//! ```text
+//! 1. e8 {+0.12} e2 {+0.05, best d2h +0.11} 2. e7 {-0.02} c2h {blunder: -0.91, best e3 +0.34;
+//! alternatives d3h +0.30, f2 +0.18}
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::ai::{analyze_with_progress, Config, Line, Progress};
+use crate::board::Board;
+use crate::game::Game;
+use crate::settings::{self, parse, positive};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Judgment {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl fmt::Display for Judgment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Judgment::Inaccuracy => write!(f, "inaccuracy"),
+            Judgment::Mistake => write!(f, "mistake"),
+            Judgment::Blunder => write!(f, "blunder"),
+        }
+    }
+}
+
+/// Thresholds for judging moves, and how each position is searched
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReviewSettings {
+    /// the least loss flagged as an inaccuracy
+    pub inaccuracy: f64,
+    pub mistake: f64,
+    pub blunder: f64,
+    /// other moves listed with a flagged move
+    pub alternatives: usize,
+    /// time limit for searching each position, in milliseconds
+    pub movetime: Option<u64>,
+}
+
+impl Default for ReviewSettings {
+    fn default() -> Self {
+        ReviewSettings {
+            inaccuracy: 0.3,
+            mistake: 0.7,
+            blunder: 1.5,
+            alternatives: 3,
+            movetime: None,
+        }
+    }
+}
+
+impl ReviewSettings {
+    /// Changes the setting named `key` to `value`
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "inaccuracy" => self.inaccuracy = parse(key, value)?,
+            "mistake" => self.mistake = parse(key, value)?,
+            "blunder" => self.blunder = parse(key, value)?,
+            "alternatives" => self.alternatives = parse(key, value)?,
+            "movetime" => self.movetime = Some(positive(key, value)?),
+            _ => return Err(format!("unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+
+    /// How a move losing `loss` is judged
+    pub fn judge(&self, loss: f64) -> Option<Judgment> {
+        if loss >= self.blunder {
+            Some(Judgment::Blunder)
+        } else if loss >= self.mistake {
+            Some(Judgment::Mistake)
+        } else if loss >= self.inaccuracy {
+            Some(Judgment::Inaccuracy)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses comma separated `key=value` overrides of the default settings, such as
+/// `mistake=0.5,blunder=1`
+impl FromStr for ReviewSettings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut settings = ReviewSettings::default();
+        settings::apply(s, |key, value| settings.set(key, value))?;
+        if !(settings.inaccuracy <= settings.mistake && settings.mistake <= settings.blunder) {
+            return Err(String::from(
+                "thresholds must increase from inaccuracy to mistake to blunder",
+            ));
+        }
+        Ok(settings)
+    }
+}
+
+impl fmt::Display for ReviewSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "inaccuracy={},mistake={},blunder={},alternatives={}",
+            self.inaccuracy, self.mistake, self.blunder, self.alternatives
+        )?;
+        if let Some(movetime) = self.movetime {
+            write!(f, ",movetime={}", movetime)?;
+        }
+        Ok(())
+    }
+}
+
+/// A move the engine considered instead of the one played
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Alternative {
+    #[serde(rename = "move")]
+    pub move_string: String,
+    pub value: f64,
+}
+
+/// The engine's judgment of one move of a game
+///
+/// Values are from the perspective of the player making the move; infinite values (proven wins
+/// and losses) are written to JSON as null.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MoveReview {
+    /// the move's index in the game, from 0
+    pub ply: usize,
+    #[serde(rename = "move")]
+    pub move_string: String,
+    pub value: f64,
+    pub best_move: String,
+    pub best_value: f64,
+    /// how much less the move is worth than the best, 0 if it is the best
+    pub loss: f64,
+    pub judgment: Option<Judgment>,
+    /// the engine's other top moves, best first, leaving out the move played and the best move
+    pub alternatives: Vec<Alternative>,
+    /// iterations searched in the position, summed over threads
+    pub iterations: u64,
+}
+
+impl MoveReview {
+    /// The comment written after the move in an annotated record
+    pub fn comment(&self) -> String {
+        let mut comment = match self.judgment {
+            Some(judgment) => format!("{}: {}", judgment, value_string(self.value)),
+            None => value_string(self.value),
+        };
+        if self.best_move != self.move_string {
+            comment.push_str(&format!(
+                ", best {} {}",
+                self.best_move,
+                value_string(self.best_value)
+            ));
+        }
+        if self.judgment.is_some() && !self.alternatives.is_empty() {
+            let alternatives: Vec<String> = self
+                .alternatives
+                .iter()
+                .map(|alternative| {
+                    format!(
+                        "{} {}",
+                        alternative.move_string,
+                        value_string(alternative.value)
+                    )
+                })
+                .collect();
+            comment.push_str(&format!("; alternatives {}", alternatives.join(", ")));
+        }
+        comment
+    }
+}
+
+fn value_string(value: f64) -> String {
+    if value == f64::INFINITY {
+        String::from("won")
+    } else if value == -f64::INFINITY {
+        String::from("lost")
+    } else {
+        format!("{:+.2}", value)
+    }
+}
+
+/// how much less `value` is than `best`, without the NaN of comparing two proven results
+fn loss(value: f64, best: f64) -> f64 {
+    if value >= best {
+        0.0
+    } else {
+        best - value
+    }
+}
+
+/// Searches `board`, limited to `searchmoves` if there are any, returning the lines and the
+/// iterations searched
+fn search(
+    board: &Board,
+    config: &Config,
+    settings: &ReviewSettings,
+    searchmoves: &[String],
+) -> (Vec<Line>, u64) {
+    let progress = Arc::new(match settings.movetime {
+        Some(movetime) => Progress::with_time_limit(Duration::from_millis(movetime)),
+        None => Progress::default(),
+    });
+    let lines = analyze_with_progress(
+        board,
+        config,
+        usize::MAX,
+        searchmoves,
+        &progress,
+        &mut String::new(),
+    );
+    (lines, progress.iterations())
+}
+
+/// Judges the move played at `ply` of `game`
+pub fn review_move(
+    game: &Game,
+    ply: usize,
+    config: &Config,
+    settings: &ReviewSettings,
+) -> MoveReview {
+    let board = game.position(ply);
+    let move_string = game.moves()[ply].clone();
+    let mover = board.turn();
+    let mut review = MoveReview {
+        ply,
+        move_string: move_string.clone(),
+        value: f64::INFINITY,
+        best_move: move_string.clone(),
+        best_value: f64::INFINITY,
+        loss: 0.0,
+        judgment: None,
+        alternatives: Vec::new(),
+        iterations: 0,
+    };
+
+    // wins in one need no search, whether taken or missed
+    if game.position(ply + 1).winner() == Some(mover) {
+        return review;
+    }
+    let played = [move_string];
+    let win = board
+        .moves()
+        .into_iter()
+        .find(|child| child.winner() == Some(mover));
+    let lines = match win {
+        Some(child) => {
+            let (lines, iterations) = search(board, config, settings, &played);
+            review.best_move = board.move_string_to(&child);
+            review.iterations = iterations;
+            lines
+        }
+        None => {
+            let (mut lines, iterations) = search(board, config, settings, &[]);
+            review.best_move = lines[0].move_string.clone();
+            review.best_value = lines[0].value;
+            review.iterations = iterations;
+            // moves the engine doesn't consider at the root are searched on their own
+            if lines.iter().all(|line| line.move_string != played[0]) {
+                let (played_lines, iterations) = search(board, config, settings, &played);
+                lines.extend(played_lines);
+                review.iterations += iterations;
+            }
+            lines
+        }
+    };
+
+    review.value = lines
+        .iter()
+        .find(|line| line.move_string == played[0])
+        .map_or(-f64::INFINITY, |line| line.value);
+    review.loss = loss(review.value, review.best_value);
+    review.judgment = settings.judge(review.loss);
+    review.alternatives = lines
+        .iter()
+        .filter(|line| {
+            line.move_string != review.move_string && line.move_string != review.best_move
+        })
+        .take(settings.alternatives)
+        .map(|line| Alternative {
+            move_string: line.move_string.clone(),
+            value: line.value,
+        })
+        .collect();
+    review
+}
+
+/// Judges every move of `game`, calling `on_move` with each review as it is made
+pub fn review<F>(
+    game: &Game,
+    config: &Config,
+    settings: &ReviewSettings,
+    mut on_move: F,
+) -> Vec<MoveReview>
+where
+    F: FnMut(&MoveReview),
+{
+    (0..game.moves().len())
+        .map(|ply| {
+            let review = review_move(game, ply, config, settings);
+            on_move(&review);
+            review
+        })
+        .collect()
+}
+
+/// A copy of `game` with each reviewed move's judgment added to its comment
+pub fn annotate(game: &Game, reviews: &[MoveReview], config: &Config) -> Game {
+    let mut annotated = game.clone();
+    annotated.set_header(
+        "Annotator",
+        &format!("theseus {}", env!("CARGO_PKG_VERSION")),
+    );
+    annotated.set_header("AnnotatorEngine", &config.to_string());
+    for review in reviews {
+        let comment = match game.comments()[review.ply].as_str() {
+            "" => review.comment(),
+            existing => format!("{}; {}", existing, review.comment()),
+        };
+        annotated
+            .set_comment(review.ply, &comment)
+            .expect("judgments never contain braces");
+    }
+    annotated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_settings() {
+        let settings: ReviewSettings = "mistake=0.5, blunder=1, movetime=200".parse().unwrap();
+        assert_eq!(settings.mistake, 0.5);
+        assert_eq!(settings.movetime, Some(200));
+        assert_eq!(
+            settings.to_string().parse::<ReviewSettings>().unwrap(),
+            settings
+        );
+        assert!("mistake=2".parse::<ReviewSettings>().is_err());
+        assert!("blunders=2".parse::<ReviewSettings>().is_err());
+        assert!("movetime=0".parse::<ReviewSettings>().is_err());
+
+        assert_eq!(settings.judge(0.1), None);
+        assert_eq!(settings.judge(0.3), Some(Judgment::Inaccuracy));
+        assert_eq!(settings.judge(0.5), Some(Judgment::Mistake));
+        assert_eq!(settings.judge(f64::INFINITY), Some(Judgment::Blunder));
+        assert_eq!(loss(f64::INFINITY, f64::INFINITY), 0.0);
+    }
+
+    #[test]
+    fn missed_win() {
+        // white could win with c1, but places a wall and black wins instead
+        let record = "[TQBN \"nnnnnnnnnnnnnnnn1c206c405:size=5,walls=6\"]\n\n1. a4h c5 0-1";
+        let game: Game = record.parse().unwrap();
+        let config = Config {
+            iterations: 200,
+            seed: Some(1),
+            ..Default::default()
+        };
+        let settings = ReviewSettings::default();
+        let mut seen = 0;
+        let reviews = review(&game, &config, &settings, |_| seen += 1);
+        assert_eq!(seen, 2);
+
+        assert_eq!(reviews[0].best_move, "c1");
+        assert_eq!(reviews[0].best_value, f64::INFINITY);
+        // a4h lets black win at once
+        assert_eq!(reviews[0].value, -f64::INFINITY);
+        assert_eq!(reviews[0].judgment, Some(Judgment::Blunder));
+        assert!(reviews[0].iterations > 0);
+
+        let annotated = annotate(&game, &reviews, &config);
+        assert_eq!(annotated.comments()[0], "blunder: lost, best c1 won");
+        assert!(annotated.header("AnnotatorEngine").is_some());
+        let reparsed: Game = annotated.to_string().parse().unwrap();
+        assert_eq!(reparsed.comments(), annotated.comments());
+    }
+}
@@ -1,7 +1,13 @@
 use std::cell::RefCell;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+use crate::settings::{self, parse};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub enum Player {
+    #[default]
     White = 0,
     Black = 1,
 }
@@ -16,13 +22,7 @@ impl Player {
     }
 }
 
-impl Default for Player {
-    fn default() -> Self {
-        White
-    }
-}
-
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Direction {
     North,
     South,
@@ -32,7 +32,7 @@ pub enum Direction {
 use Direction::*;
 
 impl Direction {
-    fn left(&self) -> Direction {
+    pub(crate) fn left(&self) -> Direction {
         match self {
             North => West,
             South => East,
@@ -41,7 +41,7 @@ impl Direction {
         }
     }
 
-    fn right(&self) -> Direction {
+    pub(crate) fn right(&self) -> Direction {
         match self {
             North => East,
             South => West,
@@ -50,45 +50,347 @@ impl Direction {
         }
     }
 
-    fn move_sqnum(&self, sqnum: u8) -> u8 {
+    pub(crate) fn move_sqnum(&self, sqnum: u8, size: u8) -> u8 {
         match self {
-            North => sqnum - 9,
-            South => sqnum + 9,
+            North => sqnum - size,
+            South => sqnum + size,
             East => sqnum + 1,
             West => sqnum - 1,
         }
     }
 }
 
-/// squares are numbered, left-to-right, top-to-bottom, starting at a9
-pub fn sqnum_for_coord(col: char, row: u8) -> u8 {
-    (row - 1) * 9 + (col.to_ascii_lowercase() as u8) - 97
+/// squares are numbered, left-to-right, top-to-bottom, starting at a1
+pub fn sqnum_for_coord(size: u8, col: char, row: u8) -> u8 {
+    (row - 1) * size + (col.to_ascii_lowercase() as u8) - 97
 }
 
-pub fn sqnum_for_string(string: &str) -> u8 {
+pub fn sqnum_for_string(size: u8, string: &str) -> u8 {
     assert_eq!(string.len(), 2);
     let chars: Vec<_> = string.to_lowercase().chars().collect();
-    sqnum_for_coord(chars[0], chars[1] as u8)
+    sqnum_for_coord(size, chars[0], chars[1].to_digit(10).unwrap() as u8)
 }
 
-pub fn string_for_sqnum(sqnum: u8) -> String {
-    let row = sqnum / 9;
-    let col = sqnum % 9;
+pub fn string_for_sqnum(size: u8, sqnum: u8) -> String {
+    let row = sqnum / size;
+    let col = sqnum % size;
     let mut string = String::new();
     string.push((col + 97) as char);
     string.push((row + 49) as char);
     string
 }
 
+/// the square a move names and, for a wall, whether it is horizontal; None if it is malformed
+pub fn parse_move(size: u8, move_string: &str) -> Option<(u8, Option<bool>)> {
+    let chars: Vec<char> = move_string.trim().to_ascii_lowercase().chars().collect();
+    let last_col = (b'a' + size - 1) as char;
+    let col = *chars.first()?;
+    let row = chars.get(1)?.to_digit(10)? as u8;
+    if !('a'..=last_col).contains(&col) || row < 1 || row > size {
+        return None;
+    }
+    let sqnum = (row - 1) * size + (col as u8 - b'a');
+    match chars.get(2) {
+        None => Some((sqnum, None)),
+        // walls are named by the square northwest of their center
+        Some('h') | Some('v') if row < size && col < last_col && chars.len() == 3 => {
+            Some((sqnum, Some(chars[2] == 'h')))
+        }
+        _ => None,
+    }
+}
+
+/// whether the walls in `hwalls` and `vwalls` let a pawn step from `sqnum` in `direction`
+pub(crate) fn is_open(
+    size: u8,
+    hwalls: u64,
+    vwalls: u64,
+    sqnum: u8,
+    direction: &Direction,
+) -> bool {
+    let se_wall = (sqnum / size) * (size - 1) + (sqnum % size);
+    let sw_wall = if se_wall > 0 { se_wall - 1 } else { 0 };
+    let nw_wall = if se_wall > size { se_wall - size } else { 0 };
+    let ne_wall = if se_wall > size - 1 {
+        se_wall - (size - 1)
+    } else {
+        0
+    };
+    let last_row = size * (size - 1);
+    match direction {
+        North => {
+            sqnum >= size
+                && (sqnum % size == 0 || ((1 << nw_wall) & hwalls) == 0)
+                && ((sqnum + 1) % size == 0 || ((1 << ne_wall) & hwalls) == 0)
+        }
+        South => {
+            sqnum < last_row
+                && (sqnum % size == 0 || ((1 << sw_wall) & hwalls) == 0)
+                && ((sqnum + 1) % size == 0 || ((1 << se_wall) & hwalls) == 0)
+        }
+        East => {
+            (sqnum + 1) % size != 0
+                && (sqnum < size || ((1 << ne_wall) & vwalls) == 0)
+                && (sqnum >= last_row || ((1 << se_wall) & vwalls) == 0)
+        }
+        West => {
+            (sqnum) % size != 0
+                && (sqnum < size || ((1 << nw_wall) & vwalls) == 0)
+                && (sqnum >= last_row || ((1 << sw_wall) & vwalls) == 0)
+        }
+    }
+}
+
+/// whether a horizontal or vertical wall fits in slot `i` without crossing or overlapping the
+/// walls in `hwalls` and `vwalls`
+pub(crate) fn wall_fits(size: u8, hwalls: u64, vwalls: u64, i: u8, horizontal: bool) -> bool {
+    let wall_cols = size - 1;
+    let wall_bit = 1 << i;
+    if (hwalls | vwalls) & wall_bit > 0 {
+        return false;
+    }
+    if horizontal {
+        (i == 0 || ((wall_bit >> 1) & hwalls) == 0)
+            && (i == wall_cols * wall_cols - 1 || ((wall_bit << 1) & hwalls == 0))
+    } else {
+        (i < wall_cols || ((wall_bit >> wall_cols) & vwalls) == 0)
+            && (i >= wall_cols * (wall_cols - 1) || ((wall_bit << wall_cols) & vwalls == 0))
+    }
+}
+
+/// When a pawn may jump diagonally around the other pawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Jumps {
+    /// only when a wall or the edge of the board is behind the other pawn
+    Standard,
+    /// never; a pawn that can't jump straight can't jump at all, and a player boxed in with no
+    /// moves loses
+    Straight,
+    /// whether or not the straight jump is open
+    Diagonal,
+}
+
+impl FromStr for Jumps {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(Jumps::Standard),
+            "straight" => Ok(Jumps::Straight),
+            "diagonal" => Ok(Jumps::Diagonal),
+            _ => Err(format!("invalid value for jumps: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Jumps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Jumps::Standard => write!(f, "standard"),
+            Jumps::Straight => write!(f, "straight"),
+            Jumps::Diagonal => write!(f, "diagonal"),
+        }
+    }
+}
+
+/// The rule set a game is played with
+///
+/// Rules are written as comma separated `key=value` pairs, such as `size=7,walls=6`. Keys left
+/// out keep their defaults: a 9×9 board, one more wall each than the board's size, standard jumps
+/// and no blocking.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rules {
+    /// squares along each side of the board: 5, 7 or 9
+    pub size: u8,
+    /// walls each player starts with
+    pub walls: u8,
+    pub jumps: Jumps,
+    /// whether a wall may cut the other pawn off from its goal, which wins the game; a player may
+    /// never cut off their own pawn
+    pub blocking: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            size: 9,
+            walls: 10,
+            jumps: Jumps::Standard,
+            blocking: false,
+        }
+    }
+}
+
+impl Rules {
+    /// whether placing a wall can end the game, by cutting a pawn off or boxing it in
+    fn walls_can_win(&self) -> bool {
+        self.blocking || self.jumps == Jumps::Straight
+    }
+
+    /// The default rules for a board `size` squares across
+    pub fn with_size(size: u8) -> Result<Rules, String> {
+        let mut rules = Rules::default();
+        rules.set("size", &size.to_string())?;
+        Ok(rules)
+    }
+
+    /// Changes the rule named `key` to `value`
+    ///
+    /// Changing the size also changes the wall budget, unless it was set to something other than
+    /// the old size's default.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "size" => {
+                let size = parse(key, value)?;
+                if ![5, 7, 9].contains(&size) {
+                    return Err(format!("unsupported board size: {}", size));
+                }
+                if self.walls == self.size + 1 {
+                    self.walls = size + 1;
+                }
+                self.size = size;
+            }
+            // wall counts are written with two digits in TQBN
+            "walls" => match parse(key, value)? {
+                walls if walls < 100 => self.walls = walls,
+                _ => return Err(format!("invalid value for {}: {}", key, value)),
+            },
+            "jumps" => self.jumps = value.parse()?,
+            "blocking" => self.blocking = parse(key, value)?,
+            _ => return Err(format!("unknown rule: {}", key)),
+        }
+        Ok(())
+    }
+
+    /// Applies comma separated `key=value` changes
+    fn apply(&mut self, settings: &str) -> Result<(), String> {
+        settings::apply(settings, |key, value| self.set(key, value))
+    }
+
+    /// The rules TQBN can't tell from a position's length, written as a `:key=value,...` suffix
+    fn tqbn_suffix(&self) -> String {
+        let mut changes = Vec::new();
+        if self.walls != self.size + 1 {
+            changes.push(format!("walls={}", self.walls));
+        }
+        if self.jumps != Jumps::Standard {
+            changes.push(format!("jumps={}", self.jumps));
+        }
+        if self.blocking {
+            changes.push(String::from("blocking=true"));
+        }
+        if changes.is_empty() {
+            String::new()
+        } else {
+            format!(":{}", changes.join(","))
+        }
+    }
+}
+
+impl FromStr for Rules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Rules::default();
+        rules.apply(s)?;
+        Ok(rules)
+    }
+}
+
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "size={},walls={},jumps={},blocking={}",
+            self.size, self.walls, self.jumps, self.blocking
+        )
+    }
+}
+
+/// A symmetry of the board, mapping a position to one that is just as good for the player to move
+///
+/// Every transform is its own inverse, so the same transform maps a move back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    Identity,
+    /// reflected left to right
+    Mirror,
+    /// rotated 180° with the players swapped
+    ColorFlip,
+    /// both: reflected top to bottom with the players swapped
+    MirrorColorFlip,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 4] = [
+        Transform::Identity,
+        Transform::Mirror,
+        Transform::ColorFlip,
+        Transform::MirrorColorFlip,
+    ];
+
+    fn mirrors(self) -> bool {
+        self == Transform::Mirror || self == Transform::MirrorColorFlip
+    }
+
+    fn flips_colors(self) -> bool {
+        self == Transform::ColorFlip || self == Transform::MirrorColorFlip
+    }
+
+    /// maps a point on a `width`×`width` grid, which is a square or a wall slot
+    fn point(self, width: u8, point: u8) -> u8 {
+        let mut row = point / width;
+        let mut col = point % width;
+        if self.flips_colors() {
+            row = width - 1 - row;
+            col = width - 1 - col;
+        }
+        if self.mirrors() {
+            col = width - 1 - col;
+        }
+        row * width + col
+    }
+
+    /// The square `sqnum` maps to on a board `size` squares across
+    pub fn sqnum(self, size: u8, sqnum: u8) -> u8 {
+        self.point(size, sqnum)
+    }
+
+    /// maps a wall bitboard
+    fn walls(self, size: u8, walls: u64) -> u64 {
+        (0..(size - 1).pow(2))
+            .filter(|i| walls & (1 << i) > 0)
+            .fold(0, |mapped, i| mapped | 1 << self.point(size - 1, i))
+    }
+
+    /// The move `move_string` maps to on a board `size` squares across, if it names a square or
+    /// wall on that board
+    pub fn move_string(self, size: u8, move_string: &str) -> Option<String> {
+        let (sqnum, horizontal) = parse_move(size, move_string)?;
+        let horizontal = match horizontal {
+            None => return Some(string_for_sqnum(size, self.sqnum(size, sqnum))),
+            Some(horizontal) => horizontal,
+        };
+        // walls are named by the square northwest of their center
+        let wall = self.point(size - 1, sqnum - sqnum / size);
+        let mut mapped = string_for_sqnum(size, wall + wall / (size - 1));
+        mapped.push(if horizontal { 'h' } else { 'v' });
+        Some(mapped)
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct Board {
+    rules: Rules,
+
     /// pawn position, in square numbers
     pawns: [u8; 2],
 
     /// each player's remaining walls
     remaining_walls: [u8; 2],
 
-    /// bitboards for horizontal and vertical walls, specifying the center of the wall
+    /// bitboards for horizontal and vertical walls, specifying the center of the wall; bit
+    /// `row * (size - 1) + col` is the wall southeast of the square at `row`, `col`
     hwalls: u64,
     vwalls: u64,
 
@@ -100,9 +402,19 @@ pub struct Board {
 
 impl Board {
     pub fn new() -> Board {
+        Board::with_rules(Rules::default())
+    }
+
+    /// The starting position of a game played with `rules`
+    pub fn with_rules(rules: Rules) -> Board {
+        let center = (b'a' + rules.size / 2) as char;
         Board {
-            pawns: [sqnum_for_coord('e', 9), sqnum_for_coord('e', 1)],
-            remaining_walls: [10, 10],
+            rules,
+            pawns: [
+                sqnum_for_coord(rules.size, center, rules.size),
+                sqnum_for_coord(rules.size, center, 1),
+            ],
+            remaining_walls: [rules.walls, rules.walls],
             hwalls: 0,
             vwalls: 0,
             turn: White,
@@ -111,47 +423,423 @@ impl Board {
     }
 
     pub fn from_tqbn(tqbn: &str) -> Board {
-        assert!(tqbn.len() == 73);
-        let tqbn: Vec<_> = tqbn.chars().collect();
+        Board::parse_tqbn(tqbn).unwrap()
+    }
+
+    /// parses a board in TQBN notation, describing what is wrong with it if it is malformed
+    ///
+    /// The board's size is given by the TQBN's length: `(size - 1)²` wall characters followed by
+    /// the turn and each player's pawn and remaining walls. A wall budget other than the size's
+    /// default follows as a suffix, such as `:walls=4`.
+    pub fn parse_tqbn(tqbn: &str) -> Result<Board, String> {
+        let mut parts = tqbn.trim().splitn(2, ':');
+        let tqbn: Vec<_> = parts.next().unwrap().chars().collect();
+        let size = match [5, 7, 9]
+            .iter()
+            .find(|&&size| tqbn.len() == (size as usize - 1).pow(2) + 9)
+        {
+            Some(size) => *size,
+            None => {
+                return Err(format!(
+                    "expected 25, 45 or 73 characters, found {}",
+                    tqbn.len()
+                ))
+            }
+        };
+        let mut rules = Rules::with_size(size)?;
+        if let Some(suffix) = parts.next() {
+            rules.apply(suffix)?;
+            if rules.size != size {
+                return Err(format!("size {} doesn't match the position", rules.size));
+            }
+        }
 
+        let wall_slots = (size as usize - 1).pow(2);
         let mut hwalls = 0;
         let mut vwalls = 0;
-        for (i, c) in tqbn[0..64].into_iter().enumerate() {
+        for (i, c) in tqbn[0..wall_slots].iter().enumerate() {
             match c.to_ascii_lowercase() {
                 'h' => hwalls |= 1 << i,
                 'v' => vwalls |= 1 << i,
                 'n' => {}
-                _ => {
-                    panic!();
-                }
+                _ => return Err(format!("invalid wall character '{}' at {}", c, i)),
             }
         }
 
-        Board {
-            pawns: [
-                sqnum_for_coord(tqbn[65], tqbn[66].to_digit(10).unwrap() as u8),
-                sqnum_for_coord(tqbn[69], tqbn[70].to_digit(10).unwrap() as u8),
-            ],
-            remaining_walls: [
-                tqbn[67..69]
-                    .iter()
-                    .collect::<String>()
-                    .parse::<u8>()
-                    .unwrap(),
-                tqbn[71..73]
-                    .iter()
-                    .collect::<String>()
-                    .parse::<u8>()
-                    .unwrap(),
-            ],
+        let last_col = (b'a' + size - 1) as char;
+        let pawn = |i: usize| -> Result<u8, String> {
+            let col = tqbn[i].to_ascii_lowercase();
+            match tqbn[i + 1].to_digit(10) {
+                Some(row) if ('a'..=last_col).contains(&col) && row >= 1 && row <= size as u32 => {
+                    Ok(sqnum_for_coord(size, col, row as u8))
+                }
+                _ => Err(format!("invalid pawn square '{}{}'", tqbn[i], tqbn[i + 1])),
+            }
+        };
+        let walls = |i: usize| -> Result<u8, String> {
+            let walls: String = tqbn[i..i + 2].iter().collect();
+            walls
+                .parse::<u8>()
+                .map_err(|_| format!("invalid wall count '{}'", walls))
+        };
+
+        Ok(Board {
+            rules,
+            pawns: [pawn(wall_slots + 1)?, pawn(wall_slots + 5)?],
+            remaining_walls: [walls(wall_slots + 3)?, walls(wall_slots + 7)?],
             hwalls,
             vwalls,
-            turn: match tqbn[64] {
+            turn: match tqbn[wall_slots] {
                 '1' => White,
                 '2' => Black,
-                _ => panic!(),
+                c => return Err(format!("invalid turn '{}'", c)),
             },
             shortest_path_cache: RefCell::new([None, None]),
+        })
+    }
+
+    /// parses a diagram in the format of `Board`'s `Display`, such as one copied from test output
+    ///
+    /// The plain diagram doesn't show the players' remaining walls, so they are taken from
+    /// `remaining_walls` unless the header gives them, as `diagram` writes it: `white to move,
+    /// walls 7 9`, followed by the TQBN suffix of any rules it can't show, such as
+    /// `:jumps=diagonal`. The diagram may be indented, and trailing whitespace is ignored.
+    pub fn parse_diagram(diagram: &str, remaining_walls: Option<[u8; 2]>) -> Result<Board, String> {
+        let mut lines = diagram.lines().skip_while(|line| line.trim().is_empty());
+        let mut header = lines.next().unwrap_or("").trim().splitn(2, ':');
+        let mut turn_and_walls = header.next().unwrap().splitn(2, ',');
+        let turn = match turn_and_walls.next().unwrap().trim() {
+            "white to move" => '1',
+            "black to move" => '2',
+            turn => {
+                return Err(format!(
+                    "expected a 'white to move' header, found '{}'",
+                    turn
+                ))
+            }
+        };
+        let remaining_walls = match turn_and_walls.next() {
+            Some(walls) => {
+                let counts: Vec<_> = walls.split_whitespace().collect();
+                match counts.as_slice() {
+                    ["walls", white, black] => [white, black]
+                        .iter()
+                        .map(|count| {
+                            count
+                                .parse()
+                                .map_err(|_| format!("invalid wall count '{}'", count))
+                        })
+                        .collect::<Result<Vec<u8>, String>>()?,
+                    _ => {
+                        return Err(format!(
+                            "expected 'walls' and two counts, found '{}'",
+                            walls.trim()
+                        ))
+                    }
+                }
+            }
+            None => match remaining_walls {
+                Some(walls) => walls.to_vec(),
+                None => return Err(String::from("no wall counts given")),
+            },
+        };
+        let suffix = header.next();
+
+        let size = match lines.next() {
+            Some(cols) => cols.split_whitespace().count(),
+            None => return Err(String::from("missing column letters")),
+        };
+        if ![5, 7, 9].contains(&size) {
+            return Err(format!("unsupported board size: {}", size));
+        }
+        // rows of squares alternate with lines for the horizontal walls beneath them
+        let lines: Vec<_> = lines.take(2 * size - 1).collect();
+        if lines.len() < 2 * size - 1 {
+            return Err(format!("expected {} rows", size));
+        }
+        let indent = lines[0].len() - lines[0].trim_start().len();
+        let lines: Vec<Vec<char>> = lines
+            .iter()
+            .map(|line| {
+                let unindented = line.len() - line.trim_start().len();
+                line[unindented.min(indent)..].trim_end().chars().collect()
+            })
+            .collect();
+        // a square's column in its row's line
+        let column = |col: usize| 2 + 4 * col;
+
+        let mut pawns = [None, None];
+        let mut hmarks = vec![vec![false; size]; size];
+        let mut vmarks = vec![vec![false; size]; size];
+        for row in 0..size {
+            let line = &lines[2 * row];
+            let number = format!("{} ", row + 1);
+            if line.len() < column(size - 1) + 1
+                || !line.starts_with(&number.chars().collect::<Vec<_>>())
+            {
+                return Err(format!("malformed row {}", row + 1));
+            }
+            for col in 0..size {
+                match line[column(col)] {
+                    'W' if pawns[0].is_none() => pawns[0] = Some((row, col)),
+                    'B' if pawns[1].is_none() => pawns[1] = Some((row, col)),
+                    'W' | 'B' => return Err(String::from("more than one pawn of a color")),
+                    '.' => {}
+                    c => return Err(format!("invalid square '{}' in row {}", c, row + 1)),
+                }
+                vmarks[row][col] = line.get(column(col) + 2) == Some(&'#');
+            }
+            if row < size - 1 {
+                for (col, mark) in hmarks[row].iter_mut().enumerate() {
+                    *mark = lines[2 * row + 1].get(column(col)) == Some(&'#');
+                }
+            }
+        }
+
+        // a wall marks the two squares it spans, so runs of marks split into walls from the start
+        let mut hwalls = vec![false; (size - 1) * (size - 1)];
+        let mut vwalls = vec![false; (size - 1) * (size - 1)];
+        for row in 0..size - 1 {
+            let mut col = 0;
+            while col < size {
+                if hmarks[row][col] {
+                    if col == size - 1 || !hmarks[row][col + 1] {
+                        return Err(format!(
+                            "horizontal wall under row {} is one square long",
+                            row + 1
+                        ));
+                    }
+                    hwalls[row * (size - 1) + col] = true;
+                    col += 1;
+                }
+                col += 1;
+            }
+        }
+        for col in 0..size - 1 {
+            let mut row = 0;
+            while row < size {
+                if vmarks[row][col] {
+                    if row == size - 1 || !vmarks[row + 1][col] {
+                        return Err(format!(
+                            "vertical wall in row {} is one square long",
+                            row + 1
+                        ));
+                    }
+                    vwalls[row * (size - 1) + col] = true;
+                    row += 1;
+                }
+                row += 1;
+            }
+        }
+
+        let mut tqbn: String = hwalls
+            .iter()
+            .zip(&vwalls)
+            .map(|walls| match walls {
+                (true, true) => 'x',
+                (true, false) => 'h',
+                (false, true) => 'v',
+                (false, false) => 'n',
+            })
+            .collect();
+        if tqbn.contains('x') {
+            return Err(String::from("walls cross"));
+        }
+        tqbn.push(turn);
+        for (i, pawn) in pawns.iter().enumerate() {
+            match pawn {
+                Some((row, col)) => {
+                    tqbn.push_str(&string_for_sqnum(size as u8, (row * size + col) as u8))
+                }
+                None => {
+                    return Err(format!(
+                        "missing {} pawn",
+                        if i == 0 { "white" } else { "black" }
+                    ))
+                }
+            }
+            tqbn.push_str(&format!("{:02}", remaining_walls[i]));
+        }
+        if let Some(suffix) = suffix {
+            tqbn.push(':');
+            tqbn.push_str(suffix.trim());
+        }
+        Board::parse_tqbn(&tqbn)
+    }
+
+    /// The diagram of `Display`, with the remaining walls and rules in the header so that
+    /// `parse_diagram` can read it back exactly
+    pub fn diagram(&self) -> String {
+        let text = self.to_string();
+        let (header, body) = text.split_at(text.find('\n').unwrap());
+        format!(
+            "{}, walls {} {}{}{}",
+            header,
+            self.remaining_walls[0],
+            self.remaining_walls[1],
+            self.rules.tqbn_suffix(),
+            body
+        )
+    }
+
+    /// checks that a position could be reached in a game, describing the first problem found
+    pub fn validate(&self) -> Result<(), String> {
+        let wall_cols = self.size() - 1;
+        if self.pawns[White as usize] == self.pawns[Black as usize] {
+            return Err(String::from("pawns share a square"));
+        }
+        if self.hwalls & self.vwalls != 0 {
+            return Err(String::from("walls cross"));
+        }
+        // horizontal walls overlap their east neighbor in the same row, vertical walls the one below
+        let not_last_col = (0..wall_cols * wall_cols)
+            .filter(|i| i % wall_cols < wall_cols - 1)
+            .fold(0u64, |mask, i| mask | 1 << i);
+        if self.hwalls & (self.hwalls >> 1) & not_last_col != 0 {
+            return Err(String::from("horizontal walls overlap"));
+        }
+        if self.vwalls & (self.vwalls >> wall_cols) != 0 {
+            return Err(String::from("vertical walls overlap"));
+        }
+        let placed = (self.hwalls | self.vwalls).count_ones();
+        let walls = self.rules.walls as u32;
+        if self
+            .remaining_walls
+            .iter()
+            .any(|remaining| *remaining as u32 > walls)
+            || placed + (self.remaining_walls[0] + self.remaining_walls[1]) as u32 > 2 * walls
+        {
+            return Err(String::from("too many walls"));
+        }
+        if self.goal(White).contains(&self.pawns[White as usize])
+            && self.goal(Black).contains(&self.pawns[Black as usize])
+        {
+            return Err(String::from("both pawns have reached their goals"));
+        }
+        // with blocking, the player to move may have been cut off, which ends the game
+        if !self.paths_allowed(self.turn.other()) {
+            return Err(String::from("a pawn has no path to its goal"));
+        }
+        Ok(())
+    }
+
+    pub fn to_tqbn(&self) -> String {
+        let mut tqbn = String::new();
+        for i in 0..(self.size() - 1).pow(2) {
+            if self.hwalls & (1 << i) > 0 {
+                tqbn.push('h');
+            } else if self.vwalls & (1 << i) > 0 {
+                tqbn.push('v');
+            } else {
+                tqbn.push('n');
+            }
+        }
+        tqbn.push(match self.turn {
+            White => '1',
+            Black => '2',
+        });
+        for player in [White, Black].iter() {
+            tqbn.push_str(&string_for_sqnum(self.size(), self.pawns[*player as usize]));
+            tqbn.push_str(&format!("{:02}", self.remaining_walls[*player as usize]));
+        }
+        tqbn.push_str(&self.rules.tqbn_suffix());
+        tqbn
+    }
+
+    /// A 64-bit hash of the position, stable across runs and builds, for keying opening books
+    pub fn position_hash(&self) -> u64 {
+        // FNV-1a, since std's hasher may change between Rust versions
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut bytes = Vec::with_capacity(25);
+        bytes.extend_from_slice(&self.hwalls.to_le_bytes());
+        bytes.extend_from_slice(&self.vwalls.to_le_bytes());
+        bytes.extend_from_slice(&self.pawns);
+        bytes.extend_from_slice(&self.remaining_walls);
+        bytes.push(self.turn as u8);
+        // only hashed when changed, to keep the hashes of books for the standard game
+        if self.rules != Rules::default() {
+            bytes.push(self.rules.size);
+            bytes.push(self.rules.walls);
+            bytes.push(self.rules.jumps as u8);
+            bytes.push(self.rules.blocking as u8);
+        }
+        for byte in bytes {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// The position reflected left to right
+    pub fn mirror(&self) -> Board {
+        self.transformed(Transform::Mirror)
+    }
+
+    /// The position rotated 180° with the players swapped, so that each has the other's pawn and
+    /// walls
+    pub fn color_flip(&self) -> Board {
+        self.transformed(Transform::ColorFlip)
+    }
+
+    pub fn transformed(&self, transform: Transform) -> Board {
+        let size = self.size();
+        let mut pawns = [
+            transform.sqnum(size, self.pawns[0]),
+            transform.sqnum(size, self.pawns[1]),
+        ];
+        let mut remaining_walls = self.remaining_walls;
+        let mut turn = self.turn;
+        if transform.flips_colors() {
+            pawns.swap(0, 1);
+            remaining_walls.swap(0, 1);
+            turn = turn.other();
+        }
+        Board {
+            rules: self.rules,
+            pawns,
+            remaining_walls,
+            hwalls: transform.walls(size, self.hwalls),
+            vwalls: transform.walls(size, self.vwalls),
+            turn,
+            shortest_path_cache: RefCell::new([None, None]),
+        }
+    }
+
+    /// The representative of the position's symmetric images, and the transform giving it
+    ///
+    /// All images of a position share a canonical board, which is the position itself if no
+    /// other image is smaller. A move in the canonical board is mapped back through the same
+    /// transform.
+    pub fn canonical(&self) -> (Board, Transform) {
+        Transform::ALL
+            .iter()
+            .map(|transform| (self.transformed(*transform), *transform))
+            .min_by_key(|(board, _)| {
+                (
+                    board.turn as u8,
+                    board.hwalls,
+                    board.vwalls,
+                    board.pawns,
+                    board.remaining_walls,
+                )
+            })
+            .unwrap()
+    }
+
+    fn size(&self) -> u8 {
+        self.rules.size
+    }
+
+    /// the squares of the row `player` is trying to reach
+    pub fn goal(&self, player: Player) -> Range<u8> {
+        let size = self.size();
+        match player {
+            White => 0..size,
+            Black => size * (size - 1)..size * size,
         }
     }
 
@@ -175,25 +863,83 @@ impl Board {
         self.remaining_walls
     }
 
+    /// the horizontal walls' bitboard, with bit `row * (size - 1) + col` for the wall southeast
+    /// of that square
+    pub fn hwalls(&self) -> u64 {
+        self.hwalls
+    }
+
+    /// the vertical walls' bitboard, numbered like `hwalls`
+    pub fn vwalls(&self) -> u64 {
+        self.vwalls
+    }
+
     pub fn winner(&self) -> Option<Player> {
-        if self.pawns[White as usize] < 9 {
+        if self.goal(White).contains(&self.pawns[White as usize]) {
             return Some(White);
         }
-        if self.pawns[Black as usize] > 71 {
+        if self.goal(Black).contains(&self.pawns[Black as usize]) {
             return Some(Black);
         }
+        if self.rules.blocking && self.shortest_path(self.turn).is_empty() {
+            return Some(self.turn.other());
+        }
+        if self.stuck() {
+            return Some(self.turn.other());
+        }
         None
     }
 
+    /// whether the player to move has no legal moves, which can only happen under straight jumps,
+    /// to a pawn boxed in next to the other pawn
+    fn stuck(&self) -> bool {
+        if self.rules.jumps != Jumps::Straight {
+            return false;
+        }
+        let pawn = self.turn_pawn();
+        let boxed_in = [North, South, East, West].iter().all(|direction| {
+            !self.is_open(pawn, direction)
+                || direction.move_sqnum(pawn, self.size()) == self.other_pawn()
+        });
+        boxed_in && self.moves().is_empty()
+    }
+
     pub fn can_win(&self) -> bool {
+        if self.rules.blocking
+            && self.remaining_walls[self.turn as usize] > 0
+            && self
+                .moves_detailed(false, true, false, true)
+                .iter()
+                .any(|child| child.winner() == Some(self.turn))
+        {
+            return true;
+        }
+
+        let size = self.size();
+        // under straight jumps, boxing the other pawn in wins; it must be near to be boxed in
+        let (pawn, other) = (self.turn_pawn() as i32, self.other_pawn() as i32);
+        let distance = (pawn / size as i32 - other / size as i32).abs()
+            + (pawn % size as i32 - other % size as i32).abs();
+        if self.rules.jumps == Jumps::Straight
+            && distance <= 2
+            && self
+                .moves_detailed(false, true, false, true)
+                .iter()
+                .any(|child| child.winner() == Some(self.turn))
+        {
+            return true;
+        }
+
+        // within a row of the goal, or two rows with the other pawn in between to jump over
         let possible_win_row = match self.turn {
             White => {
-                self.turn_pawn() < 18
-                    || (self.turn_pawn() < 26 && self.other_pawn() + 9 == self.turn_pawn())
+                self.turn_pawn() < 2 * size
+                    || (self.turn_pawn() < 3 * size && self.other_pawn() + size == self.turn_pawn())
             }
             Black => {
-                self.turn_pawn() > 62
-                    || (self.turn_pawn() > 53 && self.other_pawn() == self.turn_pawn() + 9)
+                self.turn_pawn() >= size * (size - 2)
+                    || (self.turn_pawn() >= size * (size - 3)
+                        && self.other_pawn() == self.turn_pawn() + size)
             }
         };
         if !possible_win_row {
@@ -205,36 +951,11 @@ impl Board {
                 return true;
             }
         }
-        return false;
+        false
     }
 
     pub fn is_open(&self, sqnum: u8, direction: &Direction) -> bool {
-        let se_wall = (sqnum / 9) * 8 + (sqnum % 9);
-        let sw_wall = if se_wall > 0 { se_wall - 1 } else { 0 };
-        let nw_wall = if se_wall > 9 { se_wall - 9 } else { 0 };
-        let ne_wall = if se_wall > 8 { se_wall - 8 } else { 0 };
-        match direction {
-            North => {
-                sqnum > 8
-                    && (sqnum % 9 == 0 || ((1 << nw_wall) & self.hwalls) == 0)
-                    && ((sqnum + 1) % 9 == 0 || ((1 << ne_wall) & self.hwalls) == 0)
-            }
-            South => {
-                sqnum < 72
-                    && (sqnum % 9 == 0 || ((1 << sw_wall) & self.hwalls) == 0)
-                    && ((sqnum + 1) % 9 == 0 || ((1 << se_wall) & self.hwalls) == 0)
-            }
-            East => {
-                (sqnum + 1) % 9 != 0
-                    && (sqnum < 9 || ((1 << ne_wall) & self.vwalls) == 0)
-                    && (sqnum > 71 || ((1 << se_wall) & self.vwalls) == 0)
-            }
-            West => {
-                (sqnum) % 9 != 0
-                    && (sqnum < 9 || ((1 << nw_wall) & self.vwalls) == 0)
-                    && (sqnum > 71 || ((1 << sw_wall) & self.vwalls) == 0)
-            }
-        }
+        is_open(self.size(), self.hwalls, self.vwalls, sqnum, direction)
     }
 
     pub fn moves_detailed(
@@ -244,6 +965,8 @@ impl Board {
         limit_walls: bool,
         return_wins: bool,
     ) -> Vec<Board> {
+        let size = self.size();
+        let wall_cols = size - 1;
         let turn = self.turn as usize;
         let other = self.turn.other() as usize;
         let pawn = self.pawns[turn];
@@ -257,7 +980,7 @@ impl Board {
             }
 
             let mut child = self.clone();
-            child.pawns[turn] = direction.move_sqnum(pawn);
+            child.pawns[turn] = direction.move_sqnum(pawn, size);
             child.turn = child.turn.other();
 
             let child_pawn = child.pawns[turn];
@@ -275,29 +998,30 @@ impl Board {
             if child.pawns[turn] == child.pawns[other] {
                 child.shortest_path_cache.borrow_mut()[turn] = None;
 
-                if self.is_open(child.pawns[turn], direction) {
-                    child.pawns[turn] = direction.move_sqnum(child.pawns[turn]);
+                let straight = self.is_open(child.pawns[turn], direction);
+                let mut jumps = Vec::new();
+                if straight {
+                    jumps.push(*direction);
+                }
+                let diagonals = match self.rules.jumps {
+                    Jumps::Standard => !straight,
+                    Jumps::Straight => false,
+                    Jumps::Diagonal => true,
+                };
+                if diagonals {
+                    jumps.push(direction.left());
+                    jumps.push(direction.right());
+                }
+                for jump in jumps.iter() {
+                    if !self.is_open(child.pawns[turn], jump) {
+                        continue;
+                    }
+                    let mut child = child.clone();
+                    child.pawns[turn] = jump.move_sqnum(child.pawns[turn], size);
                     if return_wins && child.winner().is_some() {
                         return vec![child];
                     }
                     moves.push(child);
-                } else {
-                    if self.is_open(child.pawns[turn], &direction.left()) {
-                        // clone the child in case we also can jump to the right
-                        let mut child = child.clone();
-                        child.pawns[turn] = direction.left().move_sqnum(child.pawns[turn]);
-                        if return_wins && child.winner().is_some() {
-                            return vec![child];
-                        }
-                        moves.push(child);
-                    }
-                    if self.is_open(child.pawns[turn], &direction.right()) {
-                        child.pawns[turn] = direction.right().move_sqnum(child.pawns[turn]);
-                        if return_wins && child.winner().is_some() {
-                            return vec![child];
-                        }
-                        moves.push(child);
-                    }
                 }
             } else {
                 if return_wins && child.winner().is_some() {
@@ -312,7 +1036,8 @@ impl Board {
         if moves_only || self.remaining_walls[turn] == 0 {
             return moves;
         }
-        for i in 0..64 {
+        let wall_slots = wall_cols * wall_cols;
+        for i in 0..wall_slots {
             let wall_bit = 1 << i;
             if (self.hwalls & wall_bit) > 0 || (self.vwalls & wall_bit) > 0 {
                 continue;
@@ -322,7 +1047,7 @@ impl Board {
             if limit_walls {
                 let mut adjacent_to_pawn = false;
                 for pawn in [self.turn_pawn(), self.other_pawn()].iter() {
-                    let se_wall = (pawn / 9) * 8 + (pawn % 9);
+                    let se_wall = (pawn / size) * wall_cols + (pawn % size);
                     if i == se_wall {
                         adjacent_to_pawn = true;
                         break;
@@ -334,15 +1059,15 @@ impl Board {
                             break;
                         }
                     }
-                    if se_wall > 9 {
-                        let nw_wall = se_wall - 9;
+                    if se_wall > size {
+                        let nw_wall = se_wall - size;
                         if i == nw_wall {
                             adjacent_to_pawn = true;
                             break;
                         }
                     }
-                    if se_wall > 8 {
-                        let ne_wall = se_wall - 8;
+                    if se_wall > wall_cols {
+                        let ne_wall = se_wall - wall_cols;
                         if i == ne_wall {
                             adjacent_to_pawn = true;
                             break;
@@ -355,18 +1080,16 @@ impl Board {
                     && ((wall_bit >> 1) & walls) == 0
                     && ((wall_bit << 2) & walls) == 0
                     && ((wall_bit >> 2) & walls) == 0
-                    && ((wall_bit << 8) & walls) == 0
-                    && ((wall_bit >> 8) & walls) == 0
-                    && ((wall_bit << 16) & walls) == 0
-                    && ((wall_bit >> 16) & walls) == 0
+                    && ((wall_bit << wall_cols) & walls) == 0
+                    && ((wall_bit >> wall_cols) & walls) == 0
+                    && ((wall_bit << (2 * wall_cols)) & walls) == 0
+                    && ((wall_bit >> (2 * wall_cols)) & walls) == 0
                 {
                     continue;
                 }
             }
 
-            if (i == 0 || ((wall_bit >> 1) & self.hwalls) == 0)
-                && (i == 63 || ((wall_bit << 1) & self.hwalls == 0))
-            {
+            if wall_fits(size, self.hwalls, self.vwalls, i, true) {
                 let mut child = self.clone();
                 child.hwalls |= wall_bit;
                 child.remaining_walls[turn] -= 1;
@@ -375,31 +1098,28 @@ impl Board {
                 for player in [White, Black].iter() {
                     let mut invalidate_cache = false;
                     if let Some(cache) = &child.shortest_path_cache.borrow()[*player as usize] {
-                        if child.pawns[*player as usize] + 9 == cache[0] {
-                            if !child.is_open(child.pawns[*player as usize], &South) {
-                                invalidate_cache = true;
-                            }
+                        if child.pawns[*player as usize] + size == cache[0]
+                            && !child.is_open(child.pawns[*player as usize], &South)
+                        {
+                            invalidate_cache = true;
                         }
-                        if !invalidate_cache && cache[0] + 9 == child.pawns[*player as usize] {
-                            if !child.is_open(child.pawns[*player as usize], &North) {
-                                invalidate_cache = true;
-                            }
+                        if !invalidate_cache
+                            && cache[0] + size == child.pawns[*player as usize]
+                            && !child.is_open(child.pawns[*player as usize], &North)
+                        {
+                            invalidate_cache = true;
                         }
                         if !invalidate_cache {
                             for j in 0..cache.len() - 1 {
                                 let x = cache[j];
                                 let y = cache[j + 1];
-                                if x + 9 == y {
-                                    if !child.is_open(x, &South) {
-                                        invalidate_cache = true;
-                                        break;
-                                    }
+                                if x + size == y && !child.is_open(x, &South) {
+                                    invalidate_cache = true;
+                                    break;
                                 }
-                                if y + 9 == x {
-                                    if !child.is_open(x, &North) {
-                                        invalidate_cache = true;
-                                        break;
-                                    }
+                                if y + size == x && !child.is_open(x, &North) {
+                                    invalidate_cache = true;
+                                    break;
                                 }
                             }
                         }
@@ -409,13 +1129,14 @@ impl Board {
                     }
                 }
 
-                if !validate_paths || child.paths_exist() {
+                if !validate_paths || child.paths_allowed(self.turn) {
+                    if return_wins && self.rules.walls_can_win() && child.winner().is_some() {
+                        return vec![child];
+                    }
                     moves.push(child);
                 }
             }
-            if (i < 8 || ((wall_bit >> 8) & self.vwalls) == 0)
-                && (i > 55 || ((wall_bit << 8) & self.vwalls == 0))
-            {
+            if wall_fits(size, self.hwalls, self.vwalls, i, false) {
                 let mut child = self.clone();
                 child.vwalls |= wall_bit;
                 child.remaining_walls[turn] -= 1;
@@ -424,31 +1145,28 @@ impl Board {
                 for player in [White, Black].iter() {
                     let mut invalidate_cache = false;
                     if let Some(cache) = &child.shortest_path_cache.borrow()[*player as usize] {
-                        if child.pawns[*player as usize] + 1 == cache[0] {
-                            if !child.is_open(child.pawns[*player as usize], &East) {
-                                invalidate_cache = true;
-                            }
+                        if child.pawns[*player as usize] + 1 == cache[0]
+                            && !child.is_open(child.pawns[*player as usize], &East)
+                        {
+                            invalidate_cache = true;
                         }
-                        if !invalidate_cache && cache[0] + 1 == child.pawns[*player as usize] {
-                            if !child.is_open(child.pawns[*player as usize], &West) {
-                                invalidate_cache = true;
-                            }
+                        if !invalidate_cache
+                            && cache[0] + 1 == child.pawns[*player as usize]
+                            && !child.is_open(child.pawns[*player as usize], &West)
+                        {
+                            invalidate_cache = true;
                         }
                         if !invalidate_cache {
                             for j in 0..cache.len() - 1 {
                                 let x = cache[j];
                                 let y = cache[j + 1];
-                                if x + 1 == y {
-                                    if !child.is_open(x, &East) {
-                                        invalidate_cache = true;
-                                        break;
-                                    }
+                                if x + 1 == y && !child.is_open(x, &East) {
+                                    invalidate_cache = true;
+                                    break;
                                 }
-                                if y + 1 == x {
-                                    if !child.is_open(x, &West) {
-                                        invalidate_cache = true;
-                                        break;
-                                    }
+                                if y + 1 == x && !child.is_open(x, &West) {
+                                    invalidate_cache = true;
+                                    break;
                                 }
                             }
                         }
@@ -458,7 +1176,10 @@ impl Board {
                     }
                 }
 
-                if !validate_paths || child.paths_exist() {
+                if !validate_paths || child.paths_allowed(self.turn) {
+                    if return_wins && self.rules.walls_can_win() && child.winner().is_some() {
+                        return vec![child];
+                    }
                     moves.push(child);
                 }
             }
@@ -471,6 +1192,23 @@ impl Board {
         self.moves_detailed(false, true, false, false)
     }
 
+    /// finds the child reached by a move in `move_string_to` notation, if the move is legal
+    pub fn child_for_move_string(&self, move_string: &str) -> Option<Board> {
+        let move_string = move_string.to_ascii_lowercase();
+        self.moves()
+            .into_iter()
+            .find(|child| self.move_string_to(child) == move_string)
+    }
+
+    /// whether a wall just placed by `mover` leaves the paths the rules require
+    fn paths_allowed(&self, mover: Player) -> bool {
+        if self.rules.blocking {
+            !self.shortest_path(mover).is_empty()
+        } else {
+            self.paths_exist()
+        }
+    }
+
     pub fn paths_exist(&self) -> bool {
         if self.shortest_path_cache.borrow()[0].is_some()
             && self.shortest_path_cache.borrow()[1].is_some()
@@ -478,7 +1216,7 @@ impl Board {
             return true;
         }
 
-        return self.shortest_path(White).len() > 0 && self.shortest_path(Black).len() > 0;
+        !self.shortest_path(White).is_empty() && !self.shortest_path(Black).is_empty()
     }
 
     pub fn shortest_path(&self, player: Player) -> Vec<u8> {
@@ -487,34 +1225,25 @@ impl Board {
             return cache;
         }
 
+        let size = self.size();
+        let goal = self.goal(player);
         let mut queue = vec![vec![self.pawns[player as usize]]];
-        let mut crumbs = [false; 81];
+        let mut crumbs = vec![false; size as usize * size as usize];
         crumbs[queue[0][0] as usize] = true;
-        while queue.len() > 0 {
-            let path = queue.pop().unwrap();
+        while let Some(path) = queue.pop() {
             let sqnum = path.last().unwrap();
 
             for direction in [North, South, East, West].iter() {
                 if !self.is_open(*sqnum, direction) {
                     continue;
                 }
-                let move_sqnum = direction.move_sqnum(*sqnum);
-                if player == White {
-                    if move_sqnum < 9 {
-                        let mut path = path.clone();
-                        path.push(move_sqnum);
-                        path.remove(0);
-                        self.shortest_path_cache.borrow_mut()[player as usize] = Some(path.clone());
-                        return path;
-                    }
-                } else {
-                    if move_sqnum > 71 {
-                        let mut path = path.clone();
-                        path.push(move_sqnum);
-                        path.remove(0);
-                        self.shortest_path_cache.borrow_mut()[player as usize] = Some(path.clone());
-                        return path;
-                    }
+                let move_sqnum = direction.move_sqnum(*sqnum, size);
+                if goal.contains(&move_sqnum) {
+                    let mut path = path.clone();
+                    path.push(move_sqnum);
+                    path.remove(0);
+                    self.shortest_path_cache.borrow_mut()[player as usize] = Some(path.clone());
+                    return path;
                 }
                 if crumbs[move_sqnum as usize] {
                     continue;
@@ -528,14 +1257,16 @@ impl Board {
         Vec::new()
     }
 
-    /// returns an array of steps needed to reach each sqnum
-    pub fn walk_paths(&self, player: Player) -> [u32; 81] {
+    /// returns the steps needed to reach each sqnum
+    pub fn walk_paths(&self, player: Player) -> Vec<u32> {
+        let size = self.size();
+        let goal = self.goal(player);
         let pawn = self.pawns()[player as usize];
         let mut walk = vec![self.pawns()[player as usize]];
-        let mut counts = [0; 81];
+        let mut counts = vec![0; size as usize * size as usize];
         let mut steps = 1;
 
-        while walk.len() > 0 {
+        while !walk.is_empty() {
             let mut next_walk = Vec::new();
             for sqnum in &walk {
                 for direction in [North, South, East, West].iter() {
@@ -543,7 +1274,7 @@ impl Board {
                         continue;
                     }
 
-                    let move_sqnum = direction.move_sqnum(*sqnum);
+                    let move_sqnum = direction.move_sqnum(*sqnum, size);
 
                     if counts[move_sqnum as usize] > 0 || move_sqnum == pawn {
                         continue;
@@ -551,7 +1282,7 @@ impl Board {
 
                     counts[move_sqnum as usize] = steps;
 
-                    if (player == White && move_sqnum < 9) || (player == Black && move_sqnum > 71) {
+                    if goal.contains(&move_sqnum) {
                         continue;
                     }
 
@@ -565,7 +1296,55 @@ impl Board {
         counts
     }
 
-    pub fn to_string(&self) -> String {
+    pub fn print(&self) {
+        eprint!("{}", self);
+    }
+
+    pub fn move_string_to(&self, child: &Board) -> String {
+        let size = self.size();
+        let turn = self.turn as usize;
+        assert!(
+            self.turn != child.turn
+                && (self.pawns[turn] != child.pawns[turn]
+                    || self.remaining_walls[turn] != child.remaining_walls[turn])
+        );
+        if self.pawns[turn] != child.pawns[turn] {
+            return string_for_sqnum(size, child.pawns[turn]);
+        }
+        let wall;
+        let horizontal;
+        if (child.hwalls & !self.hwalls) > 0 {
+            wall = child.hwalls & !self.hwalls;
+            horizontal = true;
+        } else if (child.vwalls & !self.vwalls) > 0 {
+            wall = child.vwalls & !self.vwalls;
+            horizontal = false;
+        } else {
+            panic!("no change in walls");
+        }
+        let wallnum = if wall > 0 { wall.trailing_zeros() } else { 0 } as u8;
+        let sqnum = wallnum + wallnum / (size - 1);
+        let mut move_string = string_for_sqnum(size, sqnum);
+        move_string.push(if horizontal { 'h' } else { 'v' });
+        move_string
+    }
+}
+
+/// boards are equal when their positions are, regardless of what is cached
+impl PartialEq for Board {
+    fn eq(&self, other: &Board) -> bool {
+        self.rules == other.rules
+            && self.pawns == other.pawns
+            && self.remaining_walls == other.remaining_walls
+            && self.hwalls == other.hwalls
+            && self.vwalls == other.vwalls
+            && self.turn == other.turn
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let size = self.size();
         let mut s = String::new();
         s.push_str(&format!(
             "{} to move\n",
@@ -574,13 +1353,20 @@ impl Board {
                 Black => "black",
             }
         ));
-        s.push_str("  a   b   c   d   e   f   g   h   i\n");
-        for row in 0..9 {
+        let cols: Vec<String> = (0..size)
+            .map(|col| ((b'a' + col) as char).to_string())
+            .collect();
+        s.push_str(&format!("  {}\n", cols.join("   ")));
+        for row in 0..size {
             s.push_str(&format!("{} ", row + 1));
-            for col in 0..9 {
-                let sqnum = row * 9 + col;
-                let se_wall = (sqnum / 9) * 8 + (sqnum % 9);
-                let ne_wall = if se_wall > 7 { se_wall - 8 } else { 0 };
+            for col in 0..size {
+                let sqnum = row * size + col;
+                let se_wall = (sqnum / size) * (size - 1) + (sqnum % size);
+                let ne_wall = if se_wall > size - 2 {
+                    se_wall - (size - 1)
+                } else {
+                    0
+                };
                 if self.pawns[White as usize] == sqnum {
                     s.push('W');
                 } else if self.pawns[Black as usize] == sqnum {
@@ -588,9 +1374,9 @@ impl Board {
                 } else {
                     s.push('.');
                 }
-                if col != 8 {
-                    if (sqnum > 8 && (self.vwalls & (1 << ne_wall)) > 0)
-                        || (sqnum < 72 && (self.vwalls & (1 << se_wall)) > 0)
+                if col != size - 1 {
+                    if (sqnum >= size && (self.vwalls & (1 << ne_wall)) > 0)
+                        || (sqnum < size * (size - 1) && (self.vwalls & (1 << se_wall)) > 0)
                     {
                         s.push_str(" # ");
                     } else {
@@ -599,14 +1385,14 @@ impl Board {
                 }
             }
             s.push('\n');
-            if row < 8 {
-                for col in 0..9 {
+            if row < size - 1 {
+                for col in 0..size {
                     s.push_str("  ");
-                    let sqnum = row * 9 + col;
-                    let se_wall = (sqnum / 9) * 8 + (sqnum % 9);
+                    let sqnum = row * size + col;
+                    let se_wall = (sqnum / size) * (size - 1) + (sqnum % size);
                     let sw_wall = if se_wall > 1 { se_wall - 1 } else { 0 };
-                    if ((sqnum + 1) % 9 != 0 && (self.hwalls & (1 << se_wall)) > 0)
-                        || (sqnum % 9 != 0) && (self.hwalls & (1 << sw_wall) > 0)
+                    if ((sqnum + 1) % size != 0 && (self.hwalls & (1 << se_wall)) > 0)
+                        || (sqnum % size != 0) && (self.hwalls & (1 << sw_wall) > 0)
                     {
                         s.push_str("# ");
                     } else {
@@ -616,39 +1402,7 @@ impl Board {
                 s.push('\n');
             }
         }
-        s
-    }
-
-    pub fn print(&self) {
-        eprint!("{}", self.to_string());
-    }
-
-    pub fn move_string_to(&self, child: &Board) -> String {
-        let turn = self.turn as usize;
-        assert!(
-            self.turn != child.turn
-                && (self.pawns[turn] != child.pawns[turn]
-                    || self.remaining_walls[turn] != child.remaining_walls[turn])
-        );
-        if self.pawns[turn] != child.pawns[turn] {
-            return string_for_sqnum(child.pawns[turn]);
-        }
-        let wall;
-        let horizontal;
-        if (child.hwalls & !self.hwalls) > 0 {
-            wall = child.hwalls & !self.hwalls;
-            horizontal = true;
-        } else if (child.vwalls & !self.vwalls) > 0 {
-            wall = child.vwalls & !self.vwalls;
-            horizontal = false;
-        } else {
-            panic!("no change in walls");
-        }
-        let wallnum = if wall > 0 { wall.trailing_zeros() } else { 0 } as u8;
-        let sqnum = wallnum + wallnum / 8;
-        let mut move_string = string_for_sqnum(sqnum);
-        move_string.push(if horizontal { 'h' } else { 'v' });
-        move_string
+        write!(f, "{}", s)
     }
 }
 
@@ -863,4 +1617,313 @@ mod tests {
         board.hwalls = 1 << 36;
         assert_eq!(board.moves_detailed(false, true, true, false).len(), 25);
     }
+
+    #[test]
+    fn parse_moves() {
+        assert_eq!(parse_move(9, "a1"), Some((0, None)));
+        assert_eq!(parse_move(9, "H8v"), Some((70, Some(false))));
+        assert_eq!(parse_move(5, "d4h"), Some((18, Some(true))));
+        assert_eq!(parse_move(5, "e4h"), None);
+        assert_eq!(parse_move(5, "a6"), None);
+        assert_eq!(parse_move(9, "e"), None);
+        assert_eq!(parse_move(9, "e8x"), None);
+    }
+
+    #[test]
+    fn child_for_move_string() {
+        let board = Board::new();
+        let child = board.child_for_move_string("e8").unwrap();
+        assert_eq!(board.move_string_to(&child), "e8");
+        let child = board.child_for_move_string("D4H").unwrap();
+        assert_eq!(board.move_string_to(&child), "d4h");
+        assert!(board.child_for_move_string("e7").is_none());
+        assert!(board.child_for_move_string("i9h").is_none());
+    }
+
+    #[test]
+    fn tqbn_round_trip() {
+        let tqbn = "nnnhvnnnnnnnnnnnnnnnnnnnnnvnvnnnnnnnnnvnnnvnnnnnnnnnvnnnnnnvnnnn1e906e106";
+        assert_eq!(Board::from_tqbn(tqbn).to_tqbn(), tqbn);
+        let tqbn = "nnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnn1e910e110";
+        assert_eq!(Board::new().to_tqbn(), tqbn);
+    }
+
+    #[test]
+    fn validate_positions() {
+        assert!(Board::new().validate().is_ok());
+        let board = Board::from_tqbn(
+            "nnnhvnnnnnnnnnnnnnnnnnnnnnvnvnnnnnnnnnvnnnvnnnnnnnnnvnnnnnnvnnnn1e906e106",
+        );
+        assert!(board.validate().is_ok());
+        // pawns on the same square
+        let board = Board::from_tqbn(
+            "nnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnn1e510e510",
+        );
+        assert!(board.validate().is_err());
+        // overlapping horizontal walls
+        let board = Board::from_tqbn(
+            "hhnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnn1e910e108",
+        );
+        assert!(board.validate().is_err());
+        // too many walls
+        let board = Board::from_tqbn(
+            "nnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnv1i910e110",
+        );
+        assert!(board.validate().is_err());
+        // white walled into the corner
+        let board = Board::from_tqbn(
+            "nnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnv1i909e110",
+        );
+        assert!(board.validate().is_ok());
+        let board = Board::from_tqbn(
+            "nnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnhnnnnnnnv1i908e110",
+        );
+        assert!(board.validate().is_err());
+    }
+
+    #[test]
+    fn parse_rules() {
+        assert_eq!("".parse::<Rules>().unwrap(), Rules::default());
+        assert_eq!(
+            "size=7".parse::<Rules>().unwrap(),
+            Rules {
+                size: 7,
+                walls: 8,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            "walls=4, size=5, jumps=straight".parse::<Rules>().unwrap(),
+            Rules {
+                size: 5,
+                walls: 4,
+                jumps: Jumps::Straight,
+                blocking: false,
+            }
+        );
+        assert_eq!(
+            "size=5,walls=4".parse::<Rules>().unwrap().to_string(),
+            "size=5,walls=4,jumps=standard,blocking=false"
+        );
+        assert!("jumps=sideways".parse::<Rules>().is_err());
+        assert!("size=8".parse::<Rules>().is_err());
+        assert!("walls=100".parse::<Rules>().is_err());
+        assert!("pawns=4".parse::<Rules>().is_err());
+    }
+
+    #[test]
+    fn small_boards() {
+        let board = Board::with_rules(Rules::with_size(5).unwrap());
+        assert_eq!(board.to_tqbn(), "nnnnnnnnnnnnnnnn1c506c106");
+        assert_eq!(board.moves().len(), 35);
+        assert!(board.validate().is_ok());
+        let child = board.child_for_move_string("b2v").unwrap();
+        assert_eq!(board.move_string_to(&child), "b2v");
+        assert_eq!(Board::from_tqbn(&child.to_tqbn()), child);
+
+        let board = Board::with_rules("size=7,walls=6".parse().unwrap());
+        let tqbn = board.to_tqbn();
+        assert_eq!(tqbn, format!("{}1d706d106:walls=6", "n".repeat(36)));
+        assert_eq!(Board::from_tqbn(&tqbn), board);
+        assert_eq!(board.moves().len(), 75);
+        assert!(Board::parse_tqbn(&format!("{}1d706d106:size=9", "n".repeat(36))).is_err());
+
+        // white, a step from its goal, wins on the next move
+        let board = Board::from_tqbn("nnnnnnnnnnnnnnnn1c206e506");
+        assert!(board.can_win());
+        assert_eq!(board.shortest_path(White), vec![sqnum_for_string(5, "c1")]);
+        assert_eq!(
+            board.child_for_move_string("c1").unwrap().winner(),
+            Some(White)
+        );
+        assert!(Board::parse_tqbn("nnnnnnnnnnnnnnnn1f206e506").is_err());
+    }
+
+    #[test]
+    fn position_hashes() {
+        // the standard game's hashes predate the rules, and books depend on them
+        assert_eq!(Board::new().position_hash(), 0xebc9_3d6d_6b67_8fb7);
+        let blocking = Board::with_rules("blocking=true".parse().unwrap());
+        assert_ne!(blocking.position_hash(), Board::new().position_hash());
+    }
+
+    #[test]
+    fn jump_rules() {
+        let pawn_moves = |jumps: &str, hwalls: u64| {
+            let mut board = Board::with_rules(format!("jumps={}", jumps).parse().unwrap());
+            // white on e5 faces black on e4
+            board.pawns = [40, 31];
+            board.hwalls = hwalls;
+            let mut moves: Vec<_> = only_pawn_moves(&board, board.moves())
+                .iter()
+                .map(|child| board.move_string_to(child))
+                .collect();
+            moves.sort();
+            moves
+        };
+        assert_eq!(pawn_moves("standard", 0), vec!["d5", "e3", "e6", "f5"]);
+        assert_eq!(pawn_moves("straight", 0), vec!["d5", "e3", "e6", "f5"]);
+        assert_eq!(
+            pawn_moves("diagonal", 0),
+            vec!["d4", "d5", "e3", "e6", "f4", "f5"]
+        );
+        // with a wall behind black
+        assert_eq!(
+            pawn_moves("standard", 1 << 20),
+            vec!["d4", "d5", "e6", "f4", "f5"]
+        );
+        assert_eq!(pawn_moves("straight", 1 << 20), vec!["d5", "e6", "f5"]);
+        assert_eq!(
+            pawn_moves("diagonal", 1 << 20),
+            vec!["d4", "d5", "e6", "f4", "f5"]
+        );
+
+        // white, boxed in on e5 by walls and black, has no moves under straight jumps and loses
+        let walls = "nnnnnnnnnnnnnnnnnnnnhnnnnnnnvnnnnnnvhnnnnnnnnnnnnnnnnnnnnnnnnnnn";
+        let board = Board::from_tqbn(&format!("{}1e500e400:jumps=straight", walls));
+        assert!(board.validate().is_ok());
+        assert!(board.moves().is_empty());
+        assert_eq!(board.winner(), Some(Black));
+        // so black, a step away, can win by boxing it in
+        let board = Board::from_tqbn(&format!("{}2e500d403:jumps=straight", walls));
+        assert!(board.can_win());
+        let wins = board.moves_detailed(false, true, false, true);
+        assert_eq!(board.move_string_to(&wins[0]), "e4");
+        let board = Board::from_tqbn(&format!("{}1e500e400:jumps=standard", walls));
+        assert_eq!(board.winner(), None);
+    }
+
+    #[test]
+    fn blocking_walls() {
+        // black on a1 is walled in on the east, so a wall under a2 would cut it off
+        let tqbn = "vnnnnnnnnnnnnnnn1c505a105:blocking=true";
+        let board = Board::from_tqbn(tqbn);
+        assert_eq!(board.to_tqbn(), tqbn);
+        assert!(board.validate().is_ok());
+        assert!(board.can_win());
+        let child = board.child_for_move_string("a2h").unwrap();
+        assert_eq!(child.winner(), Some(White));
+        assert!(child.validate().is_ok());
+
+        let board = Board::from_tqbn("vnnnnnnnnnnnnnnn1c505a105");
+        assert!(!board.can_win());
+        assert!(board.child_for_move_string("a2h").is_none());
+
+        // nobody may cut off their own pawn
+        let board = Board::from_tqbn("vnnnnnnnnnnnnnnn2c505a105:blocking=true");
+        assert!(board.child_for_move_string("a2h").is_none());
+    }
+
+    #[test]
+    fn symmetries() {
+        let start = Board::new();
+        assert_eq!(start.mirror(), start);
+        assert_eq!(start.color_flip().turn(), Black);
+        assert_eq!(start.color_flip().pawns(), start.pawns());
+
+        for (rules, moves) in [
+            (Rules::default(), vec!["e8", "e2", "b7h", "f3v"]),
+            (Rules::with_size(5).unwrap(), vec!["b5", "c2", "a3v"]),
+        ]
+        .iter()
+        {
+            let mut board = Board::with_rules(*rules);
+            for move_string in moves {
+                board = board.child_for_move_string(move_string).unwrap();
+            }
+            let size = rules.size;
+            let (canonical, _) = board.canonical();
+            for transform in Transform::ALL.iter() {
+                let image = board.transformed(*transform);
+                assert_eq!(image.transformed(*transform), board);
+                assert!(image.validate().is_ok());
+                assert_eq!(image.canonical().0, canonical);
+                assert_eq!(
+                    image.shortest_path(image.turn()).len(),
+                    board.shortest_path(board.turn()).len()
+                );
+
+                // moves map to the image's moves, and back again
+                let children = board.moves();
+                assert_eq!(image.moves().len(), children.len());
+                for child in children {
+                    let move_string = board.move_string_to(&child);
+                    let mapped = transform.move_string(size, &move_string).unwrap();
+                    assert_eq!(
+                        image.child_for_move_string(&mapped),
+                        Some(child.transformed(*transform))
+                    );
+                    assert_eq!(transform.move_string(size, &mapped), Some(move_string));
+                }
+            }
+        }
+
+        let board = Board::from_tqbn("hnnnnnnnnnnnnnnn1c505c105");
+        assert_eq!(
+            Transform::Mirror.move_string(5, "a1h"),
+            Some(String::from("d1h"))
+        );
+        assert_eq!(Transform::Mirror.move_string(5, "e5h"), None);
+        assert_eq!(board.mirror().to_tqbn(), "nnnhnnnnnnnnnnnn1c505c105");
+        assert_eq!(board.color_flip().to_tqbn(), "nnnnnnnnnnnnnnnh2c505c105");
+        assert_eq!(board.canonical(), (board.clone(), Transform::Identity));
+    }
+
+    #[test]
+    fn parse_diagrams() {
+        let board = Board::parse_diagram(
+            "
+            black to move, walls 5 5
+              a   b   c   d   e
+            1 .   . # .   .   .
+
+            2 .   B # .   .   .
+
+            3 .   .   .   .   .
+
+            4 .   .   W   .   .
+                      #   #
+            5 .   .   .   .   .
+            ",
+            None,
+        )
+        .unwrap();
+        assert_eq!(board, Board::from_tqbn("nvnnnnnnnnnnnnhn2c405b205"));
+        assert_eq!(
+            Board::parse_diagram(&board.to_string(), Some([5, 5])).unwrap(),
+            board
+        );
+
+        let mut boards = vec![Board::new()];
+        for move_string in &["e8", "e2", "b7h", "f3v", "d2h", "d3v"] {
+            let child = boards.last().unwrap().child_for_move_string(move_string);
+            boards.push(child.unwrap());
+        }
+        boards.push(Board::from_tqbn("nnnnnnnnnnnnnnvn1c505c106:jumps=diagonal"));
+        for board in &boards {
+            assert_eq!(
+                Board::parse_diagram(&board.diagram(), None).unwrap(),
+                *board
+            );
+        }
+
+        let cases = [
+            ("white to move\n  a   b", "no wall counts given"),
+            (
+                "white to play, walls 1 2",
+                "expected a 'white to move' header",
+            ),
+            ("white to move, walls 1", "expected 'walls' and two counts"),
+        ];
+        for (diagram, error) in cases.iter() {
+            assert!(Board::parse_diagram(diagram, None)
+                .unwrap_err()
+                .starts_with(error));
+        }
+        let diagram = boards[4].to_string().replacen(" # ", "   ", 1);
+        assert_eq!(
+            Board::parse_diagram(&diagram, Some([9, 9])).unwrap_err(),
+            "vertical wall in row 4 is one square long"
+        );
+    }
 }
@@ -0,0 +1,415 @@
+//! Four-player Quoridor
+//!
+//! Four pawns start at the middle of each side of a 9×9 board with 5 walls each, and each tries to
+//! reach the opposite side. Players are numbered clockwise from the bottom: player 1 starts on e9
+//! and heads for row 1, player 2 starts on a5 and heads for column i, player 3 starts on e1 and
+//! heads for row 9, and player 4 starts on i5 and heads for column a. The first to arrive wins.
+//!
+//! Positions are written like TQBN: the 64 wall slots, the player to move (`1` to `4`), then each
+//! player's pawn and remaining walls in turn order, such as
+//!
+//! ```text
+//! nnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnn1e905a505e105i505
+//! ```
+
+use std::fmt;
+
+use crate::board::Direction::*;
+use crate::board::{is_open, sqnum_for_coord, string_for_sqnum, wall_fits, Direction};
+
+pub const PLAYERS: usize = 4;
+
+/// walls each player starts with
+pub const WALLS: u8 = 5;
+
+const SIZE: u8 = 9;
+const WALL_SLOTS: u8 = (SIZE - 1) * (SIZE - 1);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FourBoard {
+    /// pawn positions, in square numbers, by player
+    pawns: [u8; PLAYERS],
+    remaining_walls: [u8; PLAYERS],
+    /// wall bitboards, laid out as in `Board`
+    hwalls: u64,
+    vwalls: u64,
+    /// next player to move, from 0
+    turn: usize,
+}
+
+impl Default for FourBoard {
+    fn default() -> Self {
+        FourBoard::new()
+    }
+}
+
+impl FourBoard {
+    pub fn new() -> FourBoard {
+        FourBoard {
+            pawns: [
+                sqnum_for_coord(SIZE, 'e', 9),
+                sqnum_for_coord(SIZE, 'a', 5),
+                sqnum_for_coord(SIZE, 'e', 1),
+                sqnum_for_coord(SIZE, 'i', 5),
+            ],
+            remaining_walls: [WALLS; PLAYERS],
+            hwalls: 0,
+            vwalls: 0,
+            turn: 0,
+        }
+    }
+
+    /// parses a board in four-player TQBN, describing what is wrong with it if it is malformed
+    pub fn parse_tqbn(tqbn: &str) -> Result<FourBoard, String> {
+        let tqbn: Vec<_> = tqbn.trim().chars().collect();
+        let expected = WALL_SLOTS as usize + 1 + 4 * PLAYERS;
+        if tqbn.len() != expected {
+            return Err(format!(
+                "expected {} characters, found {}",
+                expected,
+                tqbn.len()
+            ));
+        }
+
+        let mut board = FourBoard::new();
+        for (i, c) in tqbn[..WALL_SLOTS as usize].iter().enumerate() {
+            match c.to_ascii_lowercase() {
+                'h' => board.hwalls |= 1 << i,
+                'v' => board.vwalls |= 1 << i,
+                'n' => {}
+                _ => return Err(format!("invalid wall character '{}' at {}", c, i)),
+            }
+        }
+        board.turn = match tqbn[WALL_SLOTS as usize].to_digit(10) {
+            Some(turn) if turn >= 1 && turn <= PLAYERS as u32 => turn as usize - 1,
+            _ => return Err(format!("invalid turn '{}'", tqbn[WALL_SLOTS as usize])),
+        };
+        for player in 0..PLAYERS {
+            let i = WALL_SLOTS as usize + 1 + 4 * player;
+            let col = tqbn[i].to_ascii_lowercase();
+            board.pawns[player] = match tqbn[i + 1].to_digit(10) {
+                Some(row) if ('a'..='i').contains(&col) && row >= 1 => {
+                    sqnum_for_coord(SIZE, col, row as u8)
+                }
+                _ => return Err(format!("invalid pawn square '{}{}'", tqbn[i], tqbn[i + 1])),
+            };
+            let walls: String = tqbn[i + 2..i + 4].iter().collect();
+            board.remaining_walls[player] = walls
+                .parse()
+                .map_err(|_| format!("invalid wall count '{}'", walls))?;
+        }
+        Ok(board)
+    }
+
+    pub fn to_tqbn(&self) -> String {
+        let mut tqbn = String::new();
+        for i in 0..WALL_SLOTS {
+            if self.hwalls & (1 << i) > 0 {
+                tqbn.push('h');
+            } else if self.vwalls & (1 << i) > 0 {
+                tqbn.push('v');
+            } else {
+                tqbn.push('n');
+            }
+        }
+        tqbn.push_str(&(self.turn + 1).to_string());
+        for player in 0..PLAYERS {
+            tqbn.push_str(&string_for_sqnum(SIZE, self.pawns[player]));
+            tqbn.push_str(&format!("{:02}", self.remaining_walls[player]));
+        }
+        tqbn
+    }
+
+    /// checks that a position could be reached in a game, describing the first problem found
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, pawn) in self.pawns.iter().enumerate() {
+            if self.pawns[i + 1..].contains(pawn) {
+                return Err(String::from("pawns share a square"));
+            }
+        }
+        if self.remaining_walls.iter().any(|walls| *walls > WALLS)
+            || (self.hwalls | self.vwalls).count_ones()
+                + self.remaining_walls.iter().map(|w| *w as u32).sum::<u32>()
+                > PLAYERS as u32 * WALLS as u32
+        {
+            return Err(String::from("too many walls"));
+        }
+        if (0..PLAYERS)
+            .filter(|player| self.is_goal(*player, self.pawns[*player]))
+            .count()
+            > 1
+        {
+            return Err(String::from("more than one pawn has reached its goal"));
+        }
+        if !self.paths_exist() {
+            return Err(String::from("a pawn has no path to its goal"));
+        }
+        Ok(())
+    }
+
+    pub fn turn(&self) -> usize {
+        self.turn
+    }
+
+    pub fn pawns(&self) -> [u8; PLAYERS] {
+        self.pawns
+    }
+
+    pub fn remaining_walls(&self) -> [u8; PLAYERS] {
+        self.remaining_walls
+    }
+
+    /// whether `sqnum` is on the side `player` is trying to reach
+    pub fn is_goal(&self, player: usize, sqnum: u8) -> bool {
+        match player {
+            0 => sqnum < SIZE,
+            1 => sqnum % SIZE == SIZE - 1,
+            2 => sqnum >= SIZE * (SIZE - 1),
+            _ => sqnum % SIZE == 0,
+        }
+    }
+
+    pub fn winner(&self) -> Option<usize> {
+        (0..PLAYERS).find(|player| self.is_goal(*player, self.pawns[*player]))
+    }
+
+    fn is_open(&self, sqnum: u8, direction: &Direction) -> bool {
+        is_open(SIZE, self.hwalls, self.vwalls, sqnum, direction)
+    }
+
+    fn occupied(&self, sqnum: u8) -> bool {
+        self.pawns.contains(&sqnum)
+    }
+
+    /// the squares the player to move can step or jump to
+    ///
+    /// A pawn may jump a neighbor when the square behind it is open and empty, and otherwise step
+    /// diagonally around it; it can't jump two pawns at once.
+    fn pawn_moves(&self) -> Vec<u8> {
+        let pawn = self.pawns[self.turn];
+        let mut squares = vec![];
+        for direction in [North, South, East, West].iter() {
+            if !self.is_open(pawn, direction) {
+                continue;
+            }
+            let step = direction.move_sqnum(pawn, SIZE);
+            if !self.occupied(step) {
+                squares.push(step);
+                continue;
+            }
+
+            if self.is_open(step, direction) && !self.occupied(direction.move_sqnum(step, SIZE)) {
+                squares.push(direction.move_sqnum(step, SIZE));
+                continue;
+            }
+            for side in [direction.left(), direction.right()].iter() {
+                if self.is_open(step, side) {
+                    let diagonal = side.move_sqnum(step, SIZE);
+                    if !self.occupied(diagonal) && !squares.contains(&diagonal) {
+                        squares.push(diagonal);
+                    }
+                }
+            }
+        }
+        squares
+    }
+
+    /// the positions reachable in one move
+    ///
+    /// Without `validate_paths`, wall placements that leave a pawn without a path to its goal are
+    /// included, which is faster when only a few of the moves will be looked at.
+    pub fn moves_detailed(&self, moves_only: bool, validate_paths: bool) -> Vec<FourBoard> {
+        let mut moves = vec![];
+        for sqnum in self.pawn_moves() {
+            let mut child = self.clone();
+            child.pawns[self.turn] = sqnum;
+            child.turn = (self.turn + 1) % PLAYERS;
+            moves.push(child);
+        }
+
+        if moves_only || self.remaining_walls[self.turn] == 0 {
+            return moves;
+        }
+        for i in 0..WALL_SLOTS {
+            for horizontal in [true, false].iter() {
+                if !wall_fits(SIZE, self.hwalls, self.vwalls, i, *horizontal) {
+                    continue;
+                }
+                let mut child = self.clone();
+                if *horizontal {
+                    child.hwalls |= 1 << i;
+                } else {
+                    child.vwalls |= 1 << i;
+                }
+                child.remaining_walls[self.turn] -= 1;
+                child.turn = (self.turn + 1) % PLAYERS;
+                if !validate_paths || child.paths_exist() {
+                    moves.push(child);
+                }
+            }
+        }
+        moves
+    }
+
+    pub fn moves(&self) -> Vec<FourBoard> {
+        self.moves_detailed(false, true)
+    }
+
+    /// finds the child reached by a move in `move_string_to` notation, if the move is legal
+    pub fn child_for_move_string(&self, move_string: &str) -> Option<FourBoard> {
+        let move_string = move_string.to_ascii_lowercase();
+        self.moves()
+            .into_iter()
+            .find(|child| self.move_string_to(child) == move_string)
+    }
+
+    pub fn paths_exist(&self) -> bool {
+        (0..PLAYERS).all(|player| !self.shortest_path(player).is_empty())
+    }
+
+    /// the squares along a shortest path from `player`'s pawn to its goal, ignoring other pawns,
+    /// or an empty path if it is walled off
+    pub fn shortest_path(&self, player: usize) -> Vec<u8> {
+        let start = self.pawns[player];
+        let mut previous = [None; (SIZE * SIZE) as usize];
+        let mut queue = std::collections::VecDeque::new();
+        queue.push_back(start);
+        while let Some(sqnum) = queue.pop_front() {
+            for direction in [North, South, East, West].iter() {
+                if !self.is_open(sqnum, direction) {
+                    continue;
+                }
+                let next = direction.move_sqnum(sqnum, SIZE);
+                if next == start || previous[next as usize].is_some() {
+                    continue;
+                }
+                previous[next as usize] = Some(sqnum);
+                if self.is_goal(player, next) {
+                    let mut path = vec![next];
+                    while let Some(sqnum) = previous[*path.last().unwrap() as usize] {
+                        if sqnum == start {
+                            break;
+                        }
+                        path.push(sqnum);
+                    }
+                    path.reverse();
+                    return path;
+                }
+                queue.push_back(next);
+            }
+        }
+        Vec::new()
+    }
+
+    /// the move from this position to `child` in the usual notation, such as `e8` or `d4h`
+    pub fn move_string_to(&self, child: &FourBoard) -> String {
+        let turn = self.turn;
+        if self.pawns[turn] != child.pawns[turn] {
+            return string_for_sqnum(SIZE, child.pawns[turn]);
+        }
+        let (wall, horizontal) = if child.hwalls != self.hwalls {
+            (child.hwalls & !self.hwalls, true)
+        } else {
+            (child.vwalls & !self.vwalls, false)
+        };
+        let wallnum = wall.trailing_zeros() as u8;
+        let mut move_string = string_for_sqnum(SIZE, wallnum + wallnum / (SIZE - 1));
+        move_string.push(if horizontal { 'h' } else { 'v' });
+        move_string
+    }
+}
+
+impl fmt::Display for FourBoard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "player {} to move", self.turn + 1)?;
+        writeln!(f, "  a   b   c   d   e   f   g   h   i")?;
+        for row in 0..SIZE {
+            write!(f, "{} ", row + 1)?;
+            for col in 0..SIZE {
+                let sqnum = row * SIZE + col;
+                match self.pawns.iter().position(|pawn| *pawn == sqnum) {
+                    Some(player) => write!(f, "{}", player + 1)?,
+                    None => write!(f, ".")?,
+                }
+                if col != SIZE - 1 {
+                    let blocked = !self.is_open(sqnum, &East);
+                    write!(f, "{}", if blocked { " # " } else { "   " })?;
+                }
+            }
+            writeln!(f)?;
+            if row < SIZE - 1 {
+                for col in 0..SIZE {
+                    let blocked = !self.is_open(row * SIZE + col, &South);
+                    write!(f, "  {}", if blocked { "# " } else { "  " })?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walls() -> String {
+        "n".repeat(WALL_SLOTS as usize)
+    }
+
+    #[test]
+    fn start_position() {
+        let board = FourBoard::new();
+        assert_eq!(board.to_tqbn(), format!("{}1e905a505e105i505", walls()));
+        assert_eq!(FourBoard::parse_tqbn(&board.to_tqbn()).unwrap(), board);
+        assert!(board.validate().is_ok());
+        // three steps and every wall placement
+        assert_eq!(board.moves().len(), 3 + 128);
+        assert_eq!(board.shortest_path(1).len(), 8);
+    }
+
+    #[test]
+    fn turn_order() {
+        let mut board = FourBoard::new();
+        for (turn, move_string) in ["e8", "b5", "e2", "h5"].iter().enumerate() {
+            assert_eq!(board.turn(), turn);
+            board = board.child_for_move_string(move_string).unwrap();
+        }
+        assert_eq!(board.turn(), 0);
+        assert_eq!(board.to_tqbn(), format!("{}1e805b505e205h505", walls()));
+    }
+
+    #[test]
+    fn jumps_around_pawns() {
+        // player 1 on e5 faces player 3 on e4, with player 2 behind it on e3
+        let board = FourBoard::parse_tqbn(&format!("{}1e505e305e405i505", walls())).unwrap();
+        let mut moves: Vec<_> = board
+            .moves_detailed(true, false)
+            .iter()
+            .map(|child| board.move_string_to(child))
+            .collect();
+        moves.sort();
+        assert_eq!(moves, vec!["d4", "d5", "e6", "f4", "f5"]);
+    }
+
+    #[test]
+    fn every_path_stays_open() {
+        // player 2 on a1 is walled in on the east, so a wall under a2 would close it off
+        let mut tqbn = walls();
+        tqbn.replace_range(0..1, "v");
+        let board = FourBoard::parse_tqbn(&format!("{}1e904a105e105i505", tqbn)).unwrap();
+        assert!(board.validate().is_ok());
+        assert!(board.child_for_move_string("a2h").is_none());
+        assert!(board.child_for_move_string("b2h").is_some());
+        assert!(board.moves_detailed(false, false).len() > board.moves().len());
+    }
+
+    #[test]
+    fn winners() {
+        assert_eq!(FourBoard::new().winner(), None);
+        let board = FourBoard::parse_tqbn(&format!("{}2e905i105e105i505", walls())).unwrap();
+        assert_eq!(board.winner(), Some(1));
+        assert!(FourBoard::parse_tqbn(&format!("{}5e905a505e105i505", walls())).is_err());
+        assert!(FourBoard::parse_tqbn(&format!("{}1e905a505e105i5", walls())).is_err());
+    }
+}
@@ -0,0 +1,481 @@
+//! Game records: a starting position plus the moves played from it
+//!
+//! Records are written in a PGN-like format. A header of `[Key "value"]` tags (players, engines,
+//! configuration, date, result, time control, and a `TQBN` tag for games that don't start from the
+//! opening position) is followed by the moves in `move_string_to` notation, numbered like PGN, with
+//! optional `{comments}` after each move and the result at the end:
+//!
+//! ```text
+//! [White "alice"]
+//! [Black "theseus"]
+//! [BlackEngine "iterations=50000,threads=2"]
+//! [Date "2026.10.18"]
+//! [Result "0-1"]
+//!
+//! 1. e8 e2 2. e7 d7h {blocks the path} 3. f7 e3 0-1
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::board::{Board, Player};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameError {
+    /// a header line that isn't a well-formed `[Key "value"]` tag
+    Header(String),
+    Tqbn(String),
+    /// a move that isn't legal in its position, with its index in the move list
+    IllegalMove(usize, String),
+    /// a move played after the game was already won
+    GameOver(usize),
+    /// move text that isn't a move, move number, comment or result
+    Token(String),
+    /// a comment before the first move, which has no move to follow
+    CommentBeforeMoves(String),
+    /// a `{` with no `}` closing it
+    UnclosedComment,
+    /// a `Result` header naming someone other than the winner of the moves
+    WrongResult(String),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::Header(line) => write!(f, "malformed header: {}", line),
+            GameError::Tqbn(error) => write!(f, "invalid TQBN: {}", error),
+            GameError::IllegalMove(i, move_string) => {
+                write!(f, "illegal move {} at ply {}", move_string, i + 1)
+            }
+            GameError::GameOver(i) => write!(f, "move at ply {} after the game ended", i + 1),
+            GameError::Token(token) => write!(f, "unexpected token: {}", token),
+            GameError::CommentBeforeMoves(comment) => {
+                write!(f, "comment before the first move: {}", comment)
+            }
+            GameError::UnclosedComment => write!(f, "unclosed comment"),
+            GameError::WrongResult(result) => write!(f, "result {} contradicts the moves", result),
+        }
+    }
+}
+
+/// The result token for a game's winner, or `*` for an unfinished game
+pub fn result_string(winner: Option<Player>) -> &'static str {
+    match winner {
+        Some(Player::White) => "1-0",
+        Some(Player::Black) => "0-1",
+        None => "*",
+    }
+}
+
+/// Today's date (UTC) in the header's `YYYY.MM.DD` format
+pub fn date_string() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    // civil-from-days, for the proleptic Gregorian calendar
+    let z = (secs / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    /// header tags, in the order they are written
+    pub headers: Vec<(String, String)>,
+    start: Board,
+    moves: Vec<String>,
+    /// the comment following each move, empty if there is none
+    comments: Vec<String>,
+    /// positions[i] is the position after moves[i]
+    positions: Vec<Board>,
+}
+
+impl Game {
+    pub fn new(start: Board) -> Game {
+        Game {
+            headers: Vec::new(),
+            start,
+            moves: Vec::new(),
+            comments: Vec::new(),
+            positions: Vec::new(),
+        }
+    }
+
+    /// Replays `moves` from `start`, checking that each is legal
+    pub fn from_moves(start: Board, moves: &[String]) -> Result<Game, GameError> {
+        let mut game = Game::new(start);
+        for move_string in moves {
+            game.play(move_string)?;
+        }
+        Ok(game)
+    }
+
+    pub fn start(&self) -> &Board {
+        &self.start
+    }
+
+    pub fn moves(&self) -> &[String] {
+        &self.moves
+    }
+
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    /// The position after `ply` moves; 0 is the starting position
+    pub fn position(&self, ply: usize) -> &Board {
+        if ply == 0 {
+            &self.start
+        } else {
+            &self.positions[ply - 1]
+        }
+    }
+
+    /// The current position
+    pub fn board(&self) -> &Board {
+        self.positions.last().unwrap_or(&self.start)
+    }
+
+    pub fn winner(&self) -> Option<Player> {
+        self.board().winner()
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_header(&mut self, key: &str, value: &str) {
+        match self.headers.iter_mut().find(|(k, _)| k == key) {
+            Some(header) => header.1 = value.to_string(),
+            None => self.headers.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// Plays a move in `move_string_to` notation, returning the new position
+    pub fn play(&mut self, move_string: &str) -> Result<&Board, GameError> {
+        let ply = self.moves.len();
+        if self.winner().is_some() {
+            return Err(GameError::GameOver(ply));
+        }
+        match self.board().child_for_move_string(move_string) {
+            Some(child) => {
+                self.moves.push(self.board().move_string_to(&child));
+                self.comments.push(String::new());
+                self.positions.push(child);
+                Ok(self.board())
+            }
+            None => Err(GameError::IllegalMove(ply, move_string.to_string())),
+        }
+    }
+
+    /// Plays the move leading to `child`, which must be one of the current position's moves
+    pub fn push(&mut self, child: &Board) {
+        let move_string = self.board().move_string_to(child);
+        self.moves.push(move_string);
+        self.comments.push(String::new());
+        self.positions.push(child.clone());
+    }
+
+    /// Takes back the last move, returning it
+    pub fn undo(&mut self) -> Option<String> {
+        self.positions.pop()?;
+        self.comments.pop();
+        self.moves.pop()
+    }
+
+    /// Sets the comment after the move at `ply` (0-indexed), which can't contain `}` since that
+    /// would end it early in the record
+    pub fn set_comment(&mut self, ply: usize, comment: &str) -> Result<(), String> {
+        if comment.contains('}') {
+            return Err(format!("comments can't contain '}}': {}", comment));
+        }
+        // surrounding whitespace is dropped when a record is parsed
+        self.comments[ply] = comment.trim().to_string();
+        Ok(())
+    }
+
+    fn result_token(&self) -> &str {
+        match self.header("Result") {
+            Some(result) => result,
+            None => result_string(self.winner()),
+        }
+    }
+
+    fn play_token(&mut self, token: &str) -> Result<(), GameError> {
+        // strip a move number, which may be attached to the move as in `1.e8`
+        let unnumbered = token.trim_start_matches(|c: char| c.is_ascii_digit());
+        let token = if unnumbered.len() < token.len() && unnumbered.starts_with('.') {
+            unnumbered.trim_start_matches('.')
+        } else {
+            token
+        };
+        match token {
+            "" | "1-0" | "0-1" | "1/2-1/2" | "*" => Ok(()),
+            _ if token.chars().all(|c| c.is_ascii_alphanumeric()) => self.play(token).map(|_| ()),
+            _ => Err(GameError::Token(token.to_string())),
+        }
+    }
+
+    /// Parses every game in a file of records
+    pub fn parse_all(text: &str) -> Result<Vec<Game>, GameError> {
+        let mut games = Vec::new();
+        let mut record = String::new();
+        let mut in_moves = false;
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') && in_moves {
+                games.push(record.parse()?);
+                record.clear();
+                in_moves = false;
+            }
+            if !trimmed.is_empty() && !trimmed.starts_with('[') {
+                in_moves = true;
+            }
+            record.push_str(line);
+            record.push('\n');
+        }
+        if !record.trim().is_empty() {
+            games.push(record.parse()?);
+        }
+        Ok(games)
+    }
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut headers = self.headers.clone();
+        if !headers.iter().any(|(k, _)| k == "Result") {
+            headers.push(("Result".to_string(), self.result_token().to_string()));
+        }
+        let standard_start = self.start.to_tqbn() == Board::new().to_tqbn();
+        if !standard_start && !headers.iter().any(|(k, _)| k == "TQBN") {
+            headers.push(("TQBN".to_string(), self.start.to_tqbn()));
+        }
+        for (key, value) in &headers {
+            writeln!(
+                f,
+                "[{} \"{}\"]",
+                key,
+                value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+            )?;
+        }
+        writeln!(f)?;
+
+        let mut tokens = Vec::new();
+        for (i, (move_string, comment)) in self.moves.iter().zip(&self.comments).enumerate() {
+            // moves are numbered from white's moves, as in PGN
+            let turn = self.position(i).turn();
+            let number = (i + self.start.turn() as usize) / 2 + 1;
+            if turn == Player::White {
+                tokens.push(format!("{}.", number));
+            } else if i == 0 {
+                tokens.push(format!("{}...", number));
+            }
+            tokens.push(move_string.clone());
+            if !comment.is_empty() {
+                tokens.push(format!("{{{}}}", comment));
+            }
+        }
+        tokens.push(self.result_token().to_string());
+
+        // wrap the move text like PGN does
+        let mut line_length = 0;
+        for (i, token) in tokens.iter().enumerate() {
+            if i > 0 {
+                if line_length + token.len() + 1 > 79 {
+                    writeln!(f)?;
+                    line_length = 0;
+                } else {
+                    write!(f, " ")?;
+                    line_length += 1;
+                }
+            }
+            write!(f, "{}", token)?;
+            line_length += token.len();
+        }
+        writeln!(f)
+    }
+}
+
+fn parse_header(line: &str) -> Result<(String, String), GameError> {
+    let error = || GameError::Header(line.to_string());
+    let inner = line
+        .strip_prefix('[')
+        .and_then(|l| l.strip_suffix(']'))
+        .ok_or_else(error)?;
+    let space = inner.find(' ').ok_or_else(error)?;
+    let key = inner[..space].to_string();
+    let value = inner[space..].trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or_else(error)?;
+    Ok((key, unescape(value)))
+}
+
+/// undoes the escaping of `\`, `"` and newlines in header values
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some(escaped) => unescaped.push(escaped),
+                None => unescaped.push(c),
+            },
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+impl FromStr for Game {
+    type Err = GameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut headers = Vec::new();
+        let mut movetext = String::new();
+        for line in s.lines() {
+            let line = line.trim();
+            if line.starts_with('[') && movetext.trim().is_empty() {
+                headers.push(parse_header(line)?);
+            } else {
+                movetext.push_str(line);
+                movetext.push('\n');
+            }
+        }
+
+        let start = match headers.iter().find(|(k, _)| k == "TQBN") {
+            Some((_, tqbn)) => Board::parse_tqbn(tqbn).map_err(GameError::Tqbn)?,
+            None => Board::new(),
+        };
+        let mut game = Game::new(start);
+        game.headers = headers;
+
+        let mut chars = movetext.chars();
+        let mut token = String::new();
+        loop {
+            match chars.next() {
+                Some('{') => {
+                    game.play_token(&std::mem::take(&mut token))?;
+                    let mut closed = false;
+                    let comment: String = chars
+                        .by_ref()
+                        .take_while(|&c| {
+                            closed = c == '}';
+                            !closed
+                        })
+                        .collect();
+                    if !closed {
+                        return Err(GameError::UnclosedComment);
+                    }
+                    match game.comments.last_mut() {
+                        Some(last) => *last = comment.trim().to_string(),
+                        None => return Err(GameError::CommentBeforeMoves(comment)),
+                    }
+                }
+                Some(c) if c.is_whitespace() => game.play_token(&std::mem::take(&mut token))?,
+                Some(c) => token.push(c),
+                None => {
+                    game.play_token(&token)?;
+                    break;
+                }
+            }
+        }
+        if let (Some(result), Some(winner)) = (game.header("Result"), game.winner()) {
+            if result != result_string(Some(winner)) {
+                return Err(GameError::WrongResult(result.to_string()));
+            }
+        }
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_round_trip() {
+        let moves: Vec<String> = ["e8", "e2", "e7", "d7h", "f7", "e3"]
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        let mut game = Game::from_moves(Board::new(), &moves).unwrap();
+        game.set_header("White", "alice");
+        game.set_header("BlackEngine", "iterations=50000,threads=2");
+        game.set_header("Annotator", "a \"quoted\" \\ name\non two lines");
+        game.set_comment(3, " blocks the path {for now").unwrap();
+        assert!(game.set_comment(4, "not for long}").is_err());
+        assert_eq!(game.comments()[4], "");
+
+        let record = game.to_string();
+        let parsed: Game = record.parse().unwrap();
+        assert_eq!(parsed.moves(), game.moves());
+        assert_eq!(parsed.comments(), game.comments());
+        assert_eq!(parsed.header("White"), Some("alice"));
+        assert_eq!(parsed.header("Annotator"), game.header("Annotator"));
+        assert_eq!(parsed.board().to_tqbn(), game.board().to_tqbn());
+        assert_eq!(parsed.to_string(), record);
+    }
+
+    #[test]
+    fn black_to_move_start() {
+        let start = Board::new().child_for_move_string("e8").unwrap();
+        let game = Game::from_moves(start, &["e2".to_string(), "e7".to_string()]).unwrap();
+        let record = game.to_string();
+        assert!(record.contains("[TQBN \""));
+        assert!(record.contains("1... e2 2. e7 *"));
+        let parsed: Game = record.parse().unwrap();
+        assert_eq!(parsed.moves(), game.moves());
+    }
+
+    #[test]
+    fn illegal_moves_are_rejected() {
+        let error = "1. e8 e2 2. e6".parse::<Game>().unwrap_err();
+        assert_eq!(error, GameError::IllegalMove(2, "e6".to_string()));
+        assert!("[White alice]\n\n1. e8".parse::<Game>().is_err());
+        assert!("1. e8 e2!".parse::<Game>().is_err());
+
+        assert_eq!(
+            "{opening} 1. e8".parse::<Game>().unwrap_err(),
+            GameError::CommentBeforeMoves(String::from("opening"))
+        );
+        assert_eq!(
+            "1. e8 {forgot to close 1-0".parse::<Game>().unwrap_err(),
+            GameError::UnclosedComment
+        );
+        let record = "[TQBN \"nnnnnnnnnnnnnnnn1c206c405:size=5\"]\n[Result \"0-1\"]\n\n1. c1";
+        assert_eq!(
+            record.parse::<Game>().unwrap_err(),
+            GameError::WrongResult(String::from("0-1"))
+        );
+        assert!(record.replace("0-1", "1-0").parse::<Game>().is_ok());
+    }
+
+    #[test]
+    fn parse_multiple_games() {
+        let text = "[Round \"1\"]\n\n1. e8 e2 *\n\n[Round \"2\"]\n\n1. d8v *\n";
+        let games = Game::parse_all(text).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[1].header("Round"), Some("2"));
+        assert_eq!(games[1].moves(), ["d8v"]);
+    }
+}
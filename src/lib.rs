@@ -1,13 +1,30 @@
 #![feature(proc_macro_hygiene, decl_macro)]
 #[macro_use]
 extern crate rocket;
+extern crate rocket_contrib;
 
 pub mod ui;
 pub use crate::ui::api;
 pub use crate::ui::cli;
-//pub mod tui;
+pub use crate::ui::play;
 
 pub mod board;
 pub use crate::board::Board;
 
 pub mod ai;
+
+pub mod four;
+pub use crate::four::FourBoard;
+
+pub mod game;
+pub use crate::game::Game;
+
+pub mod selfplay;
+
+pub mod tournament;
+
+pub mod annotate;
+
+pub mod puzzle;
+
+mod settings;
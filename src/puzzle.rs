@@ -0,0 +1,194 @@
+//! Puzzles: positions where exactly one move forces a win within a few moves
+//!
+//! A puzzle's `moves` is the fewest of the mover's own moves that force a win, counting the
+//! key move, and only the key move forces a win that fast. Other moves may still win more slowly,
+//! as with mate-in-n puzzles in chess. Puzzles are found and checked with the exact solver,
+//! since the MCTS solver's proofs only cover the walls its tree expands.
+//!
+//! Puzzles are written as lines of JSON:
+//!
+//! ```text
+//! {"tqbn":"...","solution":["d2h","e7","e8","e6","e9"],"moves":3,"difficulty":4,"source":"games.txt#2 ply 31"}
+//! ```
+//!
+//! The solution starts with the key move, and the defender's replies in it hold out longest.
+//! Difficulty is `moves`, plus one if the key move is a wall, plus one if it is a pawn move off
+//! the mover's shortest path.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ai::{moves_to_win, winning_line, winning_moves};
+use crate::board::Board;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub tqbn: String,
+    pub solution: Vec<String>,
+    pub moves: u32,
+    pub difficulty: u32,
+    /// where the position was found
+    #[serde(default)]
+    pub source: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct PuzzleSettings {
+    /// puzzles winning in fewer moves are skipped; a win in one is just a step to the goal
+    pub min_moves: u32,
+    /// the deepest forced win searched for, which bounds the time spent on each position
+    pub max_moves: u32,
+}
+
+impl Default for PuzzleSettings {
+    fn default() -> Self {
+        PuzzleSettings {
+            min_moves: 2,
+            max_moves: 3,
+        }
+    }
+}
+
+fn difficulty(board: &Board, key: &Board, moves: u32) -> u32 {
+    let turn = board.turn() as usize;
+    let is_wall = key.pawns()[turn] == board.pawns()[turn];
+    let off_path = !is_wall
+        && !board
+            .shortest_path(board.turn())
+            .contains(&key.pawns()[turn]);
+    moves + is_wall as u32 + off_path as u32
+}
+
+/// The puzzle in `board`, if exactly one move forces the fastest win and it takes between
+/// `min_moves` and `max_moves`
+pub fn find(board: &Board, settings: &PuzzleSettings) -> Option<Puzzle> {
+    if board.winner().is_some() {
+        return None;
+    }
+    let moves = moves_to_win(board, settings.max_moves)?;
+    if moves < settings.min_moves {
+        return None;
+    }
+    let wins = winning_moves(board, moves);
+    if wins.len() != 1 {
+        return None;
+    }
+    Some(Puzzle {
+        tqbn: board.to_tqbn(),
+        solution: winning_line(board, &wins[0], moves)?,
+        moves,
+        difficulty: difficulty(board, &wins[0], moves),
+        source: String::new(),
+    })
+}
+
+/// Checks that a puzzle's solution is legal and wins, that no faster win exists, and that its key
+/// move is the only one forcing a win in `moves`
+pub fn verify(puzzle: &Puzzle) -> Result<(), String> {
+    let board = Board::parse_tqbn(&puzzle.tqbn).map_err(|error| format!("bad tqbn: {}", error))?;
+    board
+        .validate()
+        .map_err(|error| format!("illegal position: {}", error))?;
+    if board.winner().is_some() {
+        return Err(String::from("the game is already over"));
+    }
+    if puzzle.moves == 0 {
+        return Err(String::from("moves must be at least 1"));
+    }
+    let key = puzzle
+        .solution
+        .first()
+        .ok_or_else(|| String::from("empty solution"))?;
+
+    let mut position = board.clone();
+    for move_string in &puzzle.solution {
+        if position.winner().is_some() {
+            return Err(format!("{} is played after the game ended", move_string));
+        }
+        position = position
+            .child_for_move_string(move_string)
+            .ok_or_else(|| format!("illegal move in solution: {}", move_string))?;
+    }
+    if position.winner() != Some(board.turn()) {
+        return Err(String::from("the solution doesn't win"));
+    }
+    if puzzle.solution.len() as u32 > 2 * puzzle.moves - 1 {
+        return Err(format!(
+            "the solution takes more than {} moves",
+            puzzle.moves
+        ));
+    }
+
+    if let Some(moves) = moves_to_win(&board, puzzle.moves - 1) {
+        return Err(format!("there is a faster win, in {} moves", moves));
+    }
+    let wins = winning_moves(&board, puzzle.moves);
+    let keys: Vec<String> = wins
+        .iter()
+        .map(|child| board.move_string_to(child))
+        .collect();
+    match keys.as_slice() {
+        [only] if only == key => {}
+        [] => {
+            return Err(format!(
+                "{} doesn't force a win in {} moves",
+                key, puzzle.moves
+            ))
+        }
+        _ => {
+            return Err(format!(
+                "the solution isn't unique: {} all win in {} moves",
+                keys.join(", "),
+                puzzle.moves
+            ))
+        }
+    }
+
+    let expected = difficulty(&board, &wins[0], puzzle.moves);
+    if puzzle.difficulty != expected {
+        return Err(format!(
+            "difficulty is {}, not {}",
+            expected, puzzle.difficulty
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_and_verify() {
+        // from an engine game: white is out of walls, and only e2 outruns black
+        let board = Board::from_tqbn("nhnvhvhnnnnnhnhn1e300c401:walls=4");
+        let puzzle = find(&board, &Default::default()).unwrap();
+        assert_eq!(puzzle.solution, vec!["e2", "d4v", "e1"]);
+        assert_eq!(puzzle.moves, 2);
+        assert_eq!(puzzle.difficulty, 2);
+        assert_eq!(verify(&puzzle), Ok(()));
+        let json = serde_json::to_string(&puzzle).unwrap();
+        assert_eq!(serde_json::from_str::<Puzzle>(&json).unwrap(), puzzle);
+
+        let mut wrong = puzzle.clone();
+        wrong.difficulty += 1;
+        assert!(verify(&wrong).is_err());
+        let mut wrong = puzzle.clone();
+        wrong.moves = 3;
+        assert!(verify(&wrong)
+            .unwrap_err()
+            .starts_with("there is a faster win"));
+        let mut wrong = puzzle;
+        wrong.solution.truncate(1);
+        assert_eq!(
+            verify(&wrong),
+            Err(String::from("the solution doesn't win"))
+        );
+
+        // a step to the goal is too easy to be a puzzle, as is boxing a pawn in under straight jumps
+        let board = Board::from_tqbn("nnnnnnnnnnnnnnnn1c206c405");
+        assert_eq!(find(&board, &Default::default()), None);
+        let walls = "nnnnnnnnnnnnnnnnnnnnhnnnnnnnvnnnnnnvhnnnnnnnnnnnnnnnnnnnnnnnnnnn";
+        let board = Board::from_tqbn(&format!("{}2e500d400:jumps=straight", walls));
+        assert_eq!(find(&board, &Default::default()), None);
+    }
+}
@@ -0,0 +1,339 @@
+//! Self-play games for generating training data
+//!
+//! Each position of each game is written as a line of JSON:
+//!
+//! ```text
+//! {"game":12,"ply":3,"tqbn":"...","policy":[["e7",612],["d7v",80]],"value":0.81,"result":1}
+//! ```
+//!
+//! `policy` is the root visit distribution of the search that chose the move, most visited first,
+//! and is empty (with a null `value`) when the move was found without searching. `result` is the
+//! final result from the perspective of the player to move: 1 for a win, -1 for a loss and 0 for a
+//! game stopped at the ply limit.
+//!
+//! Games are grouped into shards of `games_per_shard`, written to `selfplay-NNNNN.jsonl` in the
+//! output directory. A shard is written under a `.tmp` name and renamed once complete, so an
+//! interrupted run can be resumed by running it again: complete shards are kept and the rest are
+//! replayed. Seeded runs replay the same games.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
+use serde::Serialize;
+
+use crate::ai::{Config, Line, Tree};
+use crate::board::{Board, Player, Rules};
+
+#[derive(Clone, Debug)]
+pub struct SelfplaySettings {
+    pub games: usize,
+    pub games_per_shard: usize,
+    /// shards played at the same time
+    pub concurrency: usize,
+    /// root moves are sampled in proportion to visits^(1 / temperature); 0 plays the engine's move
+    pub temperature: f64,
+    /// plies from the start of each game that use `temperature`; later moves are the engine's
+    pub temperature_plies: usize,
+    pub max_plies: usize,
+    pub output_dir: PathBuf,
+    /// rules the games are played with
+    pub rules: Rules,
+}
+
+impl Default for SelfplaySettings {
+    fn default() -> Self {
+        SelfplaySettings {
+            games: 100,
+            games_per_shard: 10,
+            concurrency: 1,
+            temperature: 1.0,
+            temperature_plies: 10,
+            max_plies: 200,
+            output_dir: PathBuf::from("selfplay"),
+            rules: Rules::default(),
+        }
+    }
+}
+
+/// A position from a self-play game, with the search's output there
+#[derive(Clone, Debug)]
+pub struct Position {
+    pub board: Board,
+    /// root moves and their visits, most visited first
+    pub policy: Vec<(String, u32)>,
+    /// search value of the engine's choice, from the perspective of the player to move
+    pub value: Option<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SelfplayGame {
+    pub index: usize,
+    pub positions: Vec<Position>,
+    /// None if the game reached the ply limit
+    pub winner: Option<Player>,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    game: usize,
+    ply: usize,
+    tqbn: String,
+    policy: &'a [(String, u32)],
+    value: Option<f64>,
+    result: i8,
+}
+
+impl SelfplayGame {
+    /// Writes one JSON line per position
+    pub fn write<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for (ply, position) in self.positions.iter().enumerate() {
+            let result = match self.winner {
+                None => 0,
+                Some(winner) if winner == position.board.turn() => 1,
+                Some(_) => -1,
+            };
+            let record = Record {
+                game: self.index,
+                ply,
+                tqbn: position.board.to_tqbn(),
+                policy: &position.policy,
+                value: position.value,
+                result,
+            };
+            writeln!(output, "{}", serde_json::to_string(&record).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+/// Samples a root move with probability proportional to visits^(1 / temperature)
+fn sample<'a, R: Rng>(lines: &'a [Line], temperature: f64, rng: &mut R) -> &'a Line {
+    // scale by the largest count first so high powers don't overflow
+    let most = lines
+        .iter()
+        .map(|line| line.visits)
+        .max()
+        .unwrap_or(0)
+        .max(1) as f64;
+    let weights: Vec<f64> = lines
+        .iter()
+        .map(|line| (line.visits as f64 / most).powf(1.0 / temperature))
+        .collect();
+    let mut choice = rng.gen::<f64>() * weights.iter().sum::<f64>();
+    for (line, weight) in lines.iter().zip(&weights) {
+        if choice < *weight {
+            return line;
+        }
+        choice -= weight;
+    }
+    &lines[0]
+}
+
+/// Plays game `index` of a self-play run
+///
+/// The engine's seed, if set, is offset by the game index, so each game differs but replays the
+/// same way.
+pub fn play_game(config: &Config, settings: &SelfplaySettings, index: usize) -> SelfplayGame {
+    let mut config = config.clone();
+    config.seed = config
+        .seed
+        .map(|seed| seed.wrapping_add(index as u64 * 1000));
+    let mut rng = StdRng::seed_from_u64(config.seed.unwrap_or_else(|| thread_rng().gen()));
+
+    let mut board = Board::with_rules(settings.rules);
+    let mut tree = Tree::default();
+    let mut positions = Vec::new();
+    while board.winner().is_none() && positions.len() < settings.max_plies {
+        let mut log = String::new();
+        let decision =
+            match crate::ai::decide(&board, &config, &mut tree, &Default::default(), &mut log) {
+                Some(decision) => decision,
+                None => break,
+            };
+
+        let child = if positions.len() < settings.temperature_plies
+            && settings.temperature > 0.0
+            && !decision.lines.is_empty()
+        {
+            sample(&decision.lines, settings.temperature, &mut rng)
+                .board
+                .clone()
+        } else {
+            decision.board
+        };
+
+        positions.push(Position {
+            board: board.clone(),
+            policy: decision
+                .lines
+                .iter()
+                .map(|line| (line.move_string.clone(), line.visits))
+                .collect(),
+            value: decision.stats.map(|stats| stats.value),
+        });
+        board = child;
+        if let Some(seed) = config.seed.as_mut() {
+            *seed = seed.wrapping_add(1);
+        }
+    }
+
+    SelfplayGame {
+        index,
+        positions,
+        winner: board.winner(),
+    }
+}
+
+fn shard_path(dir: &Path, shard: usize) -> PathBuf {
+    dir.join(format!("selfplay-{:05}.jsonl", shard))
+}
+
+fn play_shard(config: &Config, settings: &SelfplaySettings, shard: usize) -> io::Result<()> {
+    let path = shard_path(&settings.output_dir, shard);
+    let temp_path = path.with_extension("jsonl.tmp");
+    let mut output = BufWriter::new(File::create(&temp_path)?);
+
+    let first = shard * settings.games_per_shard;
+    let last = settings.games.min(first + settings.games_per_shard);
+    for index in first..last {
+        play_game(config, settings, index).write(&mut output)?;
+    }
+    output.flush()?;
+    drop(output);
+    fs::rename(temp_path, path)
+}
+
+/// Plays the run's games, skipping shards already complete in the output directory
+///
+/// `on_shard` is called with each shard's number as it completes, and whether it was already
+/// complete before this run.
+pub fn run<F>(config: &Config, settings: &SelfplaySettings, mut on_shard: F) -> io::Result<()>
+where
+    F: FnMut(usize, bool),
+{
+    fs::create_dir_all(&settings.output_dir)?;
+    let per_shard = settings.games_per_shard.max(1);
+    let shards = (settings.games + per_shard - 1) / per_shard;
+    let settings = SelfplaySettings {
+        games_per_shard: per_shard,
+        ..settings.clone()
+    };
+
+    let mut todo = Vec::new();
+    for shard in 0..shards {
+        if shard_path(&settings.output_dir, shard).exists() {
+            on_shard(shard, true);
+        } else {
+            todo.push(shard);
+        }
+    }
+
+    let todo = Arc::new(todo);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (results_tx, results_rx) = mpsc::channel();
+    for _ in 0..settings.concurrency.max(1) {
+        let todo = Arc::clone(&todo);
+        let next = Arc::clone(&next);
+        let results_tx = results_tx.clone();
+        let config = config.clone();
+        let settings = settings.clone();
+        thread::spawn(move || {
+            while let Some(shard) = todo.get(next.fetch_add(1, Ordering::SeqCst)) {
+                let result = play_shard(&config, &settings, *shard);
+                if results_tx.send((*shard, result)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(results_tx);
+
+    for (shard, result) in results_rx {
+        result?;
+        on_shard(shard, false);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_follows_temperature() {
+        let board = Board::new();
+        let line = |visits: u32| Line {
+            board: board.clone(),
+            move_string: visits.to_string(),
+            value: 0.0,
+            visits,
+            visit_share: 0.0,
+            pv: Vec::new(),
+        };
+        let lines = vec![line(90), line(10), line(0)];
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let count = |temperature, rng: &mut StdRng| {
+            (0..1000)
+                .filter(|_| sample(&lines, temperature, rng).visits == 90)
+                .count()
+        };
+        let proportional = count(1.0, &mut rng);
+        assert!(proportional > 850 && proportional < 950);
+        assert!(count(0.1, &mut rng) == 1000);
+        assert!((0..1000).all(|_| sample(&lines, 2.0, &mut rng).visits != 0));
+    }
+
+    #[test]
+    fn run_writes_and_resumes_shards() {
+        let config: Config = "iterations=50,threads=1,seed=7".parse().unwrap();
+        let settings = SelfplaySettings {
+            games: 3,
+            games_per_shard: 2,
+            concurrency: 2,
+            max_plies: 12,
+            output_dir: std::env::temp_dir()
+                .join(format!("theseus-selfplay-{}", std::process::id())),
+            rules: Rules::with_size(5).unwrap(),
+            ..Default::default()
+        };
+        let dir = &settings.output_dir;
+        let _ = fs::remove_dir_all(dir);
+        let read = |shard| fs::read_to_string(shard_path(dir, shard)).unwrap();
+
+        let mut done = Vec::new();
+        run(&config, &settings, |shard, skipped| {
+            done.push((shard, skipped))
+        })
+        .unwrap();
+        done.sort();
+        assert_eq!(done, vec![(0, false), (1, false)]);
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 2);
+        let first = (read(0), read(1));
+        assert!(first.0.lines().any(|line| line.starts_with("{\"game\":1,")));
+        assert!(first.1.lines().all(|line| line.starts_with("{\"game\":2,")));
+
+        let mut done = Vec::new();
+        run(&config, &settings, |shard, skipped| {
+            done.push((shard, skipped))
+        })
+        .unwrap();
+        assert_eq!(done, vec![(0, true), (1, true)]);
+
+        fs::remove_file(shard_path(dir, 1)).unwrap();
+        let mut done = Vec::new();
+        run(&config, &settings, |shard, skipped| {
+            done.push((shard, skipped))
+        })
+        .unwrap();
+        assert_eq!(done, vec![(0, true), (1, false)]);
+        assert_eq!((read(0), read(1)), first);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+//! Parsing of comma separated `key=value` settings, shared by the rules and engine configurations
+
+use std::str::FromStr;
+
+/// Parses the value of the setting named `key`
+pub(crate) fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", key, value))
+}
+
+/// Parses the value of the setting named `key`, which must not be zero
+pub(crate) fn positive<T: FromStr + Default + PartialEq>(
+    key: &str,
+    value: &str,
+) -> Result<T, String> {
+    match parse(key, value)? {
+        n if n == T::default() => Err(format!("{} must be at least 1", key)),
+        n => Ok(n),
+    }
+}
+
+/// Passes each of the comma separated `key=value` pairs in `settings` to `set`, stopping at the
+/// first error
+pub(crate) fn apply<F>(settings: &str, mut set: F) -> Result<(), String>
+where
+    F: FnMut(&str, &str) -> Result<(), String>,
+{
+    for setting in settings.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let mut parts = setting.splitn(2, '=');
+        let key = parts.next().unwrap().trim();
+        match parts.next() {
+            Some(value) => set(key, value.trim())?,
+            None => return Err(format!("missing value for {}", key)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_settings() {
+        let mut seen = Vec::new();
+        apply(" a=1, b = x=y ,,", |key, value| {
+            seen.push((key.to_string(), value.to_string()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            seen,
+            vec![
+                (String::from("a"), String::from("1")),
+                (String::from("b"), String::from("x=y"))
+            ]
+        );
+        assert_eq!(
+            apply("a=1,b", |_, _| Ok(())),
+            Err(String::from("missing value for b"))
+        );
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(parse::<u32>("n", "3"), Ok(3));
+        assert_eq!(
+            parse::<u32>("n", "-3"),
+            Err(String::from("invalid value for n: -3"))
+        );
+        assert_eq!(positive::<u64>("n", "2"), Ok(2));
+        assert_eq!(
+            positive::<u32>("n", "0"),
+            Err(String::from("n must be at least 1"))
+        );
+    }
+}
@@ -0,0 +1,380 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::ai::Config;
+use crate::board::{Board, Player};
+use crate::game::{date_string, result_string, Game};
+
+/// A finished game between engine `a` and engine `b`
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    pub index: usize,
+    pub start: Board,
+    /// whether engine `a` played white
+    pub a_white: bool,
+    pub moves: Vec<String>,
+    /// None if the game reached the ply limit
+    pub winner: Option<Player>,
+}
+
+impl GameRecord {
+    /// The game's score from engine `a`'s perspective: 1 for a win, 0.5 for a draw, 0 for a loss
+    pub fn a_score(&self) -> f64 {
+        match self.winner {
+            None => 0.5,
+            Some(Player::White) if self.a_white => 1.0,
+            Some(Player::Black) if !self.a_white => 1.0,
+            Some(_) => 0.0,
+        }
+    }
+
+    /// Converts the game into a record, with headers describing the engines
+    pub fn to_game(&self, a: &Config, b: &Config) -> Game {
+        let mut game = Game::from_moves(self.start.clone(), &self.moves).unwrap();
+        let (white, black) = if self.a_white { (a, b) } else { (b, a) };
+        game.set_header("Event", "theseus match");
+        game.set_header("Round", &(self.index + 1).to_string());
+        game.set_header("White", if self.a_white { "a" } else { "b" });
+        game.set_header("Black", if self.a_white { "b" } else { "a" });
+        game.set_header("WhiteEngine", &white.to_string());
+        game.set_header("BlackEngine", &black.to_string());
+        game.set_header("Date", &date_string());
+        game.set_header(
+            "Result",
+            match self.winner {
+                None => "1/2-1/2",
+                winner => result_string(winner),
+            },
+        );
+        game.set_header("TimeControl", "-");
+        game
+    }
+}
+
+/// Plays a game between two engine configurations, returning the moves and the winner
+///
+/// The game is declared drawn (a winner of None) once it reaches `max_plies`.
+pub fn play_game(
+    start: &Board,
+    white: &Config,
+    black: &Config,
+    max_plies: usize,
+) -> (Vec<String>, Option<Player>) {
+    let mut board = start.clone();
+    let mut moves = Vec::new();
+    while board.winner().is_none() && moves.len() < max_plies {
+        let config = match board.turn() {
+            Player::White => white,
+            Player::Black => black,
+        };
+        let mut log = String::new();
+        let child = match crate::ai::configured(&board, config, &mut log) {
+            Some(child) => child,
+            None => break,
+        };
+        moves.push(board.move_string_to(&child));
+        board = child;
+    }
+    (moves, board.winner())
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchSettings {
+    pub games: usize,
+    /// games played at the same time
+    pub concurrency: usize,
+    /// starting positions; each is played with both colors before moving to the next
+    pub openings: Vec<Board>,
+    pub max_plies: usize,
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        MatchSettings {
+            games: 100,
+            concurrency: 1,
+            openings: vec![Board::new()],
+            max_plies: 200,
+        }
+    }
+}
+
+/// Seeds a game's configuration from the configured seed, so seeded matches are reproducible
+fn game_config(config: &Config, index: usize) -> Config {
+    let mut config = config.clone();
+    config.seed = config
+        .seed
+        .map(|seed| seed.wrapping_add(index as u64 * 1000));
+    config
+}
+
+/// Plays a match between engines `a` and `b`, alternating colors
+///
+/// `on_game` is called as each game finishes (in completion order, not index order) and stops the
+/// match early if it returns false. Games already underway are finished but not reported.
+pub fn run_match<F>(
+    a: &Config,
+    b: &Config,
+    settings: &MatchSettings,
+    mut on_game: F,
+) -> Result<(), String>
+where
+    F: FnMut(GameRecord) -> bool,
+{
+    if settings.openings.is_empty() {
+        return Err(String::from("no openings"));
+    }
+    let next_game = Arc::new(AtomicUsize::new(0));
+    let (results_tx, results_rx) = mpsc::channel();
+
+    for _ in 0..settings.concurrency.max(1) {
+        let next_game = Arc::clone(&next_game);
+        let results_tx = results_tx.clone();
+        let a = a.clone();
+        let b = b.clone();
+        let settings = settings.clone();
+        thread::spawn(move || loop {
+            let index = next_game.fetch_add(1, Ordering::SeqCst);
+            if index >= settings.games {
+                break;
+            }
+
+            let start = &settings.openings[(index / 2) % settings.openings.len()];
+            let a_white = index % 2 == 0;
+            let a_config = game_config(&a, index);
+            let b_config = game_config(&b, index);
+            let (white, black) = if a_white {
+                (&a_config, &b_config)
+            } else {
+                (&b_config, &a_config)
+            };
+
+            let (moves, winner) = play_game(start, white, black, settings.max_plies);
+            let record = GameRecord {
+                index,
+                start: start.clone(),
+                a_white,
+                moves,
+                winner,
+            };
+            if results_tx.send(record).is_err() {
+                break;
+            }
+        });
+    }
+    drop(results_tx);
+
+    for record in results_rx {
+        if !on_game(record) {
+            // stop handing out games; the receiver being dropped stops the workers
+            next_game.store(settings.games, Ordering::SeqCst);
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Converts an expected score into an Elo difference
+pub fn elo(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+/// Running match results from engine `a`'s perspective
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    pub plies: usize,
+}
+
+impl Stats {
+    pub fn add(&mut self, record: &GameRecord) {
+        match record.a_score() {
+            s if s == 1.0 => self.wins += 1,
+            s if s == 0.0 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+        self.plies += record.moves.len();
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    /// The average score per game, or an even 0.5 before any games
+    pub fn score(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games() as f64
+    }
+
+    pub fn average_length(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        self.plies as f64 / self.games() as f64
+    }
+
+    /// Variance of a single game's score
+    pub fn variance(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        let score = self.score();
+        (self.wins as f64 * (1.0 - score).powi(2)
+            + self.losses as f64 * score.powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2))
+            / self.games() as f64
+    }
+
+    /// Elo difference of `a` over `b`, with the bounds of its 95% confidence interval
+    pub fn elo(&self) -> (f64, f64, f64) {
+        let n = self.games() as f64;
+        let score = self.score();
+        // before any games every score is possible, so the interval spans them all
+        let margin = if n == 0.0 {
+            1.0
+        } else {
+            1.96 * (self.variance() / n).sqrt()
+        };
+
+        // keep the scores inside (0, 1) so a clean sweep still gives finite bounds
+        let clamp = |score: f64| score.clamp(1e-6, 1.0 - 1e-6);
+        (
+            elo(clamp(score)),
+            elo(clamp(score - margin)),
+            elo(clamp(score + margin)),
+        )
+    }
+}
+
+/// Expected score for an Elo difference
+pub fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SprtResult {
+    Continue,
+    /// H1 accepted: the candidate is at least `elo1` stronger
+    AcceptH1,
+    /// H0 accepted: the candidate is no more than `elo0` stronger
+    AcceptH0,
+}
+
+/// Sequential probability ratio test of H0: elo = `elo0` against H1: elo = `elo1`
+#[derive(Clone, Copy, Debug)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    /// The lower and upper log-likelihood ratio bounds
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// Log-likelihood ratio of the results, using the normal approximation to the trinomial
+    /// distribution of game scores
+    ///
+    /// Half a pseudo-game of each result is added, so a clean sweep has a nonzero variance and
+    /// still crosses a bound.
+    pub fn llr(&self, stats: &Stats) -> f64 {
+        if stats.games() == 0 {
+            return 0.0;
+        }
+        // doubled counts plus one of each, which has the same score and variance as adding a half
+        let regularized = Stats {
+            wins: 2 * stats.wins + 1,
+            losses: 2 * stats.losses + 1,
+            draws: 2 * stats.draws + 1,
+            plies: 0,
+        };
+        let n = regularized.games() as f64 / 2.0;
+        let score = regularized.score();
+        let variance = regularized.variance();
+
+        let s0 = expected_score(self.elo0);
+        let s1 = expected_score(self.elo1);
+        (s1 - s0) * (2.0 * score - s0 - s1) / (2.0 * variance / n)
+    }
+
+    pub fn result(&self, stats: &Stats) -> SprtResult {
+        let llr = self.llr(stats);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            SprtResult::AcceptH1
+        } else if llr <= lower {
+            SprtResult::AcceptH0
+        } else {
+            SprtResult::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(wins: u32, losses: u32, draws: u32) -> Stats {
+        Stats {
+            wins,
+            losses,
+            draws,
+            plies: 0,
+        }
+    }
+
+    #[test]
+    fn elo_of_even_score() {
+        let (elo, low, high) = stats(10, 10, 5).elo();
+        assert!(elo.abs() < 1e-9);
+        assert!(low < 0.0 && high > 0.0);
+        assert!((expected_score(0.0) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn no_games() {
+        let stats = stats(0, 0, 0);
+        assert_eq!(stats.score(), 0.5);
+        assert_eq!(stats.average_length(), 0.0);
+        let (elo, low, high) = stats.elo();
+        assert_eq!(elo, 0.0);
+        assert!(low < -1000.0 && high > 1000.0);
+
+        let settings = MatchSettings {
+            openings: Vec::new(),
+            ..Default::default()
+        };
+        let config = Config::default();
+        assert_eq!(
+            run_match(&config, &config, &settings, |_| true),
+            Err(String::from("no openings"))
+        );
+    }
+
+    #[test]
+    fn sprt_decisions() {
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 20.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        assert_eq!(sprt.result(&stats(0, 0, 0)), SprtResult::Continue);
+        assert_eq!(sprt.result(&stats(300, 100, 50)), SprtResult::AcceptH1);
+        assert_eq!(sprt.result(&stats(100, 300, 50)), SprtResult::AcceptH0);
+        assert_eq!(sprt.result(&stats(11, 10, 5)), SprtResult::Continue);
+        assert_eq!(sprt.result(&stats(20, 0, 0)), SprtResult::AcceptH1);
+        assert_eq!(sprt.result(&stats(0, 20, 0)), SprtResult::AcceptH0);
+    }
+}
@@ -1,65 +1,1190 @@
 use rocket::config::{Config, Environment};
 use rocket::fairing::{Fairing, Info, Kind};
-use rocket::{Request, Response};
+use rocket::http::{ContentType, Method, Status};
+use rocket::response::{self, content, status, Responder};
+use rocket::{Outcome, Request, Response, Rocket, State};
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
 
-struct AllowOrigin {
-    origin: String,
-}
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-impl AllowOrigin {
-    fn new(origin: String) -> AllowOrigin {
-        AllowOrigin { origin }
-    }
+use super::jobs::{Job, JobStatus, Jobs, Limits};
+use super::metrics::{CountRequests, Metrics};
+use super::server::{SearchSlot, SearchSlots, ServerConfig};
+use super::session::Sessions;
+use super::svg::{svg, Overlays};
+use crate::ai::{Line, Progress, SearchStats};
+use crate::annotate::{MoveReview, ReviewSettings};
+use crate::board::{Board, Player};
+use crate::game::{Game, GameError};
+
+/// Answers cross-origin requests, including `OPTIONS` preflight requests, from allowed origins
+struct Cors {
+    server: ServerConfig,
 }
 
-impl Fairing for AllowOrigin {
+impl Fairing for Cors {
     fn info(&self) -> Info {
         Info {
-            name: "Allow origin",
+            name: "CORS",
             kind: Kind::Response,
         }
     }
 
-    fn on_response(&self, _request: &Request, response: &mut Response) {
-        response.set_raw_header("Access-Control-Allow-Origin", self.origin.clone());
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let origin = match request.headers().get_one("Origin") {
+            Some(origin) if self.server.allows_origin(origin) => origin,
+            _ => return,
+        };
+        if self
+            .server
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*")
+        {
+            response.set_raw_header("Access-Control-Allow-Origin", "*");
+        } else {
+            response.set_raw_header("Access-Control-Allow-Origin", origin.to_string());
+            response.set_raw_header("Vary", "Origin");
+        }
+        if request.method() == Method::Options {
+            response.set_raw_header("Access-Control-Allow-Methods", "GET, POST, DELETE, OPTIONS");
+            response.set_raw_header("Access-Control-Allow-Headers", "Content-Type");
+            response.set_raw_header("Access-Control-Max-Age", "86400");
+        }
+    }
+}
+
+/// Answers preflight requests for every route; `Cors` adds the headers
+#[options("/<_path..>")]
+fn preflight(_path: PathBuf) -> status::NoContent {
+    status::NoContent
+}
+
+/// An error returned to API clients as a JSON body with a machine-readable code
+#[derive(Debug)]
+enum ApiError {
+    /// the TQBN could not be parsed
+    BadTqbn(String),
+    /// the board size and walls asked for are not supported
+    BadRules(String),
+    /// the visit counts to draw are not `move:visits` pairs
+    BadVisits(String),
+    /// the game record to annotate could not be parsed
+    BadRecord(String),
+    BadReviewSettings(String),
+    /// the TQBN parsed, but describes a position that cannot occur
+    IllegalPosition(String),
+    /// the position is already won, so there is nothing to search
+    TerminalPosition,
+    IllegalMove(String),
+    NothingToUndo,
+    NotFound,
+    NoSuchJob,
+    /// the server is running as many searches as it allows
+    Busy,
+}
+
+impl ApiError {
+    fn status(&self) -> Status {
+        match self {
+            ApiError::BadTqbn(_)
+            | ApiError::BadRules(_)
+            | ApiError::BadVisits(_)
+            | ApiError::BadRecord(_)
+            | ApiError::BadReviewSettings(_) => Status::BadRequest,
+            ApiError::NotFound | ApiError::NoSuchJob => Status::NotFound,
+            ApiError::Busy => Status::ServiceUnavailable,
+            _ => Status::UnprocessableEntity,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadTqbn(_) => "bad_tqbn",
+            ApiError::BadRules(_) => "bad_rules",
+            ApiError::BadVisits(_) => "bad_visits",
+            ApiError::BadRecord(_) => "bad_record",
+            ApiError::BadReviewSettings(_) => "bad_review_settings",
+            ApiError::IllegalPosition(_) => "illegal_position",
+            ApiError::TerminalPosition => "terminal_position",
+            ApiError::IllegalMove(_) => "illegal_move",
+            ApiError::NothingToUndo => "nothing_to_undo",
+            ApiError::NotFound | ApiError::NoSuchJob => "not_found",
+            ApiError::Busy => "busy",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            ApiError::BadTqbn(error) => format!("bad tqbn: {}", error),
+            ApiError::BadRules(error) => format!("bad rules: {}", error),
+            ApiError::BadVisits(pair) => format!("bad visits: {}", pair),
+            ApiError::BadRecord(error) => format!("bad record: {}", error),
+            ApiError::BadReviewSettings(error) => format!("bad review settings: {}", error),
+            ApiError::IllegalPosition(error) => format!("illegal position: {}", error),
+            ApiError::TerminalPosition => String::from("the game is already over"),
+            ApiError::IllegalMove(error) => error.clone(),
+            ApiError::NothingToUndo => String::from("no moves to undo"),
+            ApiError::NotFound => String::from("no such game"),
+            ApiError::NoSuchJob => String::from("no such job"),
+            ApiError::Busy => String::from("too many searches running; try again later"),
+        }
+    }
+}
+
+impl From<GameError> for ApiError {
+    fn from(error: GameError) -> ApiError {
+        match error {
+            GameError::GameOver(_) => ApiError::TerminalPosition,
+            error => ApiError::IllegalMove(error.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+}
+
+/// An error body, counted in the metrics
+fn error_body(request: &Request, code: &'static str, message: String) -> Json<ErrorBody> {
+    if let Outcome::Success(metrics) = request.guard::<State<Arc<Metrics>>>() {
+        metrics.count_error(code);
+    }
+    Json(ErrorBody { code, message })
+}
+
+impl<'r> Responder<'r> for ApiError {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let body = error_body(request, self.code(), self.message());
+        status::Custom(self.status(), body).respond_to(request)
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+/// Parses a position for searching, rejecting impossible and finished positions
+fn searchable(tqbn: &str) -> Result<Board, ApiError> {
+    let board = Board::parse_tqbn(tqbn).map_err(ApiError::BadTqbn)?;
+    board.validate().map_err(ApiError::IllegalPosition)?;
+    if board.winner().is_some() {
+        return Err(ApiError::TerminalPosition);
+    }
+    Ok(board)
+}
+
+/// Claims a search slot and reduces `config` to the server's caps, refusing the search if every
+/// slot is taken
+fn admit(
+    server: &ServerConfig,
+    slots: &SearchSlots,
+    config: &mut crate::ai::Config,
+) -> Result<SearchSlot, ApiError> {
+    server.cap(config);
+    slots.acquire().ok_or(ApiError::Busy)
+}
+
+/// Checks that each of `searchmoves` is legal, normalizing their case
+fn legal_searchmoves(board: &Board, searchmoves: Vec<String>) -> Result<Vec<String>, ApiError> {
+    let searchmoves: Vec<String> = searchmoves
+        .iter()
+        .map(|s| s.trim().to_ascii_lowercase())
+        .collect();
+    for move_string in &searchmoves {
+        if board.child_for_move_string(move_string).is_none() {
+            return Err(ApiError::IllegalMove(format!(
+                "illegal move: {}",
+                move_string
+            )));
+        }
+    }
+    Ok(searchmoves)
+}
+
+fn player_name(player: Player) -> &'static str {
+    match player {
+        Player::White => "white",
+        Player::Black => "black",
+    }
+}
+
+#[derive(Serialize)]
+struct MoveBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    #[serde(rename = "move")]
+    move_string: String,
+    /// the position after the move
+    tqbn: String,
+    /// null when the move was found without searching
+    stats: Option<SearchStats>,
+    log: String,
+}
+
+#[get("/?<id>&<tqbn>&<seed>")]
+fn index(
+    id: Option<u64>,
+    tqbn: String,
+    seed: Option<u64>,
+    server: State<ServerConfig>,
+    slots: State<SearchSlots>,
+) -> ApiResult<MoveBody> {
+    let board = searchable(&tqbn)?;
+
+    let mut log = String::new();
+    log.push_str(&format!("input: {}\n", tqbn));
+
+    let mut config = crate::ai::Config {
+        seed,
+        ..Default::default()
+    };
+    let slot = admit(&server, &slots, &mut config)?;
+    let progress = Arc::new(Progress::with_time_limit(server.time_limit(None)));
+    let decision = crate::ai::decide(
+        &board,
+        &config,
+        &mut Default::default(),
+        &progress,
+        &mut log,
+    );
+
+    slot.finish(progress.iterations());
+    let decision = decision.ok_or(ApiError::TerminalPosition)?;
+
+    let move_string = board.move_string_to(&decision.board);
+    log.push_str(&format!("output: {}\n", move_string));
+
+    Ok(Json(MoveBody {
+        id,
+        move_string,
+        tqbn: decision.board.to_tqbn(),
+        stats: decision.stats,
+        log,
+    }))
+}
+
+#[derive(Serialize)]
+struct LineBody {
+    #[serde(rename = "move")]
+    move_string: String,
+    value: f64,
+    visits: u32,
+    share: f64,
+    pv: Vec<String>,
+}
+
+impl From<Line> for LineBody {
+    fn from(line: Line) -> LineBody {
+        LineBody {
+            move_string: line.move_string,
+            value: line.value,
+            visits: line.visits,
+            share: line.visit_share,
+            pv: line.pv,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AnalysisBody {
+    lines: Vec<LineBody>,
+    log: String,
+}
+
+#[get("/analyze?<tqbn>&<multipv>&<searchmoves>&<seed>")]
+fn analyze(
+    tqbn: String,
+    multipv: Option<usize>,
+    searchmoves: Option<String>,
+    seed: Option<u64>,
+    server: State<ServerConfig>,
+    slots: State<SearchSlots>,
+) -> ApiResult<AnalysisBody> {
+    let board = searchable(&tqbn)?;
+
+    let searchmoves = match searchmoves {
+        Some(moves) => legal_searchmoves(&board, moves.split(',').map(String::from).collect())?,
+        None => Vec::new(),
+    };
+
+    let mut log = String::new();
+    let mut config = crate::ai::Config {
+        seed,
+        ..Default::default()
+    };
+    let slot = admit(&server, &slots, &mut config)?;
+    let progress = Arc::new(Progress::with_time_limit(server.time_limit(None)));
+    let lines = crate::ai::analyze_with_progress(
+        &board,
+        &config,
+        multipv.unwrap_or(3),
+        &searchmoves,
+        &progress,
+        &mut log,
+    );
+    slot.finish(progress.iterations());
+
+    Ok(Json(AnalysisBody {
+        lines: lines.into_iter().map(LineBody::from).collect(),
+        log,
+    }))
+}
+
+/// Draws a position as SVG, optionally with the last move highlighted, the shortest paths, and
+/// visit counts given as comma separated `move:visits` pairs
+#[get("/render?<tqbn>&<last>&<paths>&<visits>")]
+fn render(
+    tqbn: String,
+    last: Option<String>,
+    paths: Option<bool>,
+    visits: Option<String>,
+) -> Result<content::Content<String>, ApiError> {
+    let board = Board::parse_tqbn(&tqbn).map_err(ApiError::BadTqbn)?;
+    board.validate().map_err(ApiError::IllegalPosition)?;
+
+    let mut overlays = Overlays {
+        last_move: last,
+        paths: paths.unwrap_or(false),
+        visits: Vec::new(),
+    };
+    let pairs = visits.iter().flat_map(|visits| visits.split(','));
+    for pair in pairs.filter(|pair| !pair.trim().is_empty()) {
+        let mut parts = pair.splitn(2, ':');
+        let move_string = parts.next().unwrap().trim();
+        match parts.next().map(|count| count.trim().parse()) {
+            Some(Ok(count)) => overlays.visits.push((move_string.to_string(), count)),
+            _ => return Err(ApiError::BadVisits(pair.to_string())),
+        }
     }
+
+    Ok(content::Content(ContentType::SVG, svg(&board, &overlays)))
 }
 
-#[get("/?<id>&<tqbn>")]
-fn index(id: u64, tqbn: String) -> String {
-    let _id = id;
+#[derive(Deserialize)]
+struct AnnotateRequest {
+    /// a single game record
+    record: String,
+    /// review settings, as for `theseus annotate --review`
+    review: Option<String>,
+    seed: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct AnnotationBody {
+    /// the record with each move's review added to its comment
+    record: String,
+    moves: Vec<MoveReview>,
+}
 
+/// Reviews every move of a game record; each position is searched within the server's caps, so
+/// long games take a while
+#[post("/annotate", format = "json", data = "<request>")]
+fn annotate(
+    request: Json<AnnotateRequest>,
+    server: State<ServerConfig>,
+    slots: State<SearchSlots>,
+) -> ApiResult<AnnotationBody> {
+    let game: Game = request
+        .record
+        .parse()
+        .map_err(|error: GameError| ApiError::BadRecord(error.to_string()))?;
+    let mut settings: ReviewSettings = request
+        .review
+        .as_ref()
+        .map_or(Ok(Default::default()), |review| review.parse())
+        .map_err(ApiError::BadReviewSettings)?;
+    settings.movetime = Some(server.time_limit(settings.movetime).as_millis() as u64);
+
+    let mut config = crate::ai::Config {
+        seed: request.seed,
+        ..Default::default()
+    };
+    let slot = admit(&server, &slots, &mut config)?;
+    let moves = crate::annotate::review(&game, &config, &settings, |_| {});
+    slot.finish(moves.iter().map(|review| review.iterations).sum());
+
+    Ok(Json(AnnotationBody {
+        record: crate::annotate::annotate(&game, &moves, &config).to_string(),
+        moves,
+    }))
+}
+
+#[derive(Serialize)]
+struct GameBody {
+    id: u64,
+    tqbn: String,
+    turn: &'static str,
+    moves: Vec<String>,
+    winner: Option<&'static str>,
+}
+
+impl GameBody {
+    fn new(id: u64, game: &Game) -> GameBody {
+        GameBody {
+            id,
+            tqbn: game.board().to_tqbn(),
+            turn: player_name(game.board().turn()),
+            moves: game.moves().to_vec(),
+            winner: game.winner().map(player_name),
+        }
+    }
+}
+
+/// Starts a game from `tqbn`, or from the start of a game played with `rules` (`size=7,walls=6`)
+#[post("/games?<tqbn>&<rules>")]
+fn create_game(
+    tqbn: Option<String>,
+    rules: Option<String>,
+    sessions: State<Sessions>,
+) -> ApiResult<GameBody> {
+    let start = match tqbn {
+        Some(tqbn) => searchable(&tqbn)?,
+        None => Board::with_rules(
+            rules
+                .unwrap_or_default()
+                .parse()
+                .map_err(ApiError::BadRules)?,
+        ),
+    };
+    let id = sessions.create(start.clone());
+    Ok(Json(GameBody::new(id, &Game::new(start))))
+}
+
+#[get("/games/<id>")]
+fn get_game(id: u64, sessions: State<Sessions>) -> ApiResult<GameBody> {
+    let session = sessions.get(id).ok_or(ApiError::NotFound)?;
+    let session = session.lock().unwrap();
+    Ok(Json(GameBody::new(id, &session.game)))
+}
+
+#[derive(Serialize)]
+struct HistoryEntry {
+    #[serde(rename = "move")]
+    move_string: String,
+    tqbn: String,
+}
+
+#[derive(Serialize)]
+struct HistoryBody {
+    start: String,
+    history: Vec<HistoryEntry>,
+    /// the game in the record format of `crate::game`
+    record: String,
+}
+
+#[get("/games/<id>/history")]
+fn game_history(id: u64, sessions: State<Sessions>) -> ApiResult<HistoryBody> {
+    let session = sessions.get(id).ok_or(ApiError::NotFound)?;
+    let session = session.lock().unwrap();
+    let game = &session.game;
+    let history = game
+        .moves()
+        .iter()
+        .enumerate()
+        .map(|(i, move_string)| HistoryEntry {
+            move_string: move_string.clone(),
+            tqbn: game.position(i + 1).to_tqbn(),
+        })
+        .collect();
+    Ok(Json(HistoryBody {
+        start: game.start().to_tqbn(),
+        history,
+        record: game.to_string(),
+    }))
+}
+
+#[derive(Deserialize)]
+struct MoveRequest {
+    #[serde(rename = "move")]
+    move_string: String,
+}
+
+#[post("/games/<id>/moves", format = "json", data = "<request>")]
+fn post_move(
+    id: u64,
+    request: Json<MoveRequest>,
+    sessions: State<Sessions>,
+) -> ApiResult<GameBody> {
+    let session = sessions.get(id).ok_or(ApiError::NotFound)?;
+    let mut session = session.lock().unwrap();
+    session.game.play(request.move_string.trim())?;
+    Ok(Json(GameBody::new(id, &session.game)))
+}
+
+#[derive(Serialize)]
+struct EngineMoveBody {
+    #[serde(rename = "move")]
+    move_string: String,
+    game: GameBody,
+    stats: Option<SearchStats>,
+    log: String,
+}
+
+#[post("/games/<id>/engine?<seed>")]
+fn engine_move(
+    id: u64,
+    seed: Option<u64>,
+    sessions: State<Sessions>,
+    server: State<ServerConfig>,
+    slots: State<SearchSlots>,
+) -> ApiResult<EngineMoveBody> {
+    let session = sessions.get(id).ok_or(ApiError::NotFound)?;
+    let mut session = session.lock().unwrap();
+    if session.game.winner().is_some() {
+        return Err(ApiError::TerminalPosition);
+    }
+
+    let mut config = crate::ai::Config {
+        seed,
+        ..Default::default()
+    };
+    let slot = admit(&server, &slots, &mut config)?;
+    let progress = Arc::new(Progress::with_time_limit(server.time_limit(None)));
+    let board = session.game.board().clone();
     let mut log = String::new();
+    let decision = crate::ai::decide(&board, &config, &mut session.tree, &progress, &mut log);
+    slot.finish(progress.iterations());
+    let decision = decision.ok_or(ApiError::TerminalPosition)?;
+    session.game.push(&decision.board);
+
+    Ok(Json(EngineMoveBody {
+        move_string: board.move_string_to(&decision.board),
+        game: GameBody::new(id, &session.game),
+        stats: decision.stats,
+        log,
+    }))
+}
+
+#[post("/games/<id>/undo")]
+fn undo_move(id: u64, sessions: State<Sessions>) -> ApiResult<GameBody> {
+    let session = sessions.get(id).ok_or(ApiError::NotFound)?;
+    let mut session = session.lock().unwrap();
+    session.game.undo().ok_or(ApiError::NothingToUndo)?;
+    Ok(Json(GameBody::new(id, &session.game)))
+}
+
+#[derive(Serialize)]
+struct DeletedBody {
+    id: u64,
+}
+
+#[delete("/games/<id>")]
+fn delete_game(id: u64, sessions: State<Sessions>) -> ApiResult<DeletedBody> {
+    if sessions.remove(id) {
+        Ok(Json(DeletedBody { id }))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
 
-    log.push_str(&format!("input: {}\\n", tqbn));
+/// A job's position and limits; unset limits take the engine defaults, and all are reduced to the
+/// server's caps
+#[derive(Deserialize)]
+struct JobRequest {
+    tqbn: String,
+    /// iterations per thread
+    iterations: Option<u32>,
+    threads: Option<u32>,
+    /// milliseconds to search for, at most
+    movetime: Option<u64>,
+    multipv: Option<usize>,
+    seed: Option<u64>,
+    searchmoves: Option<Vec<String>>,
+}
 
-    let board = crate::Board::from_tqbn(&tqbn.to_string());
-    //    log.push_str(&board.to_string());
+#[derive(Serialize)]
+struct JobBody {
+    id: u64,
+    status: &'static str,
+    tqbn: String,
+    /// iterations so far, summed over the search threads
+    iterations: u64,
+    elapsed_ms: u64,
+    /// the best lines so far, most visited first
+    lines: Vec<LineBody>,
+}
 
-    let child = crate::ai::default(&board, &mut log);
+impl JobBody {
+    fn new(id: u64, job: &Job) -> JobBody {
+        JobBody {
+            id,
+            status: job.status().name(),
+            tqbn: job.tqbn.clone(),
+            iterations: job.iterations(),
+            elapsed_ms: job.elapsed().as_millis() as u64,
+            lines: job.lines().into_iter().map(LineBody::from).collect(),
+        }
+    }
+}
 
-    let move_string = board.move_string_to(&child);
-    log.push_str(&format!("output: {}\\n", move_string));
-    //    log.push_str(&child.to_string());
-    log = log.replace("\n", "\\n");
-    log = log.replace("\t", "\\t");
+#[post("/jobs", format = "json", data = "<request>")]
+fn create_job(
+    request: Json<JobRequest>,
+    jobs: State<Jobs>,
+    server: State<ServerConfig>,
+    slots: State<SearchSlots>,
+) -> Result<status::Created<Json<JobBody>>, ApiError> {
+    let request = request.into_inner();
+    let board = searchable(&request.tqbn)?;
+    let defaults = crate::ai::Config::default();
+    let mut config = crate::ai::Config {
+        iterations: request.iterations.unwrap_or(defaults.iterations),
+        threads: request.threads.unwrap_or(defaults.threads),
+        seed: request.seed,
+        ..defaults
+    };
+    let searchmoves = legal_searchmoves(&board, request.searchmoves.unwrap_or_default())?;
+    let slot = admit(&server, &slots, &mut config)?;
+    let limits = Limits {
+        config,
+        movetime: Some(server.time_limit(request.movetime)),
+        multipv: request.multipv.unwrap_or(3),
+        searchmoves,
+    };
 
-    String::from(format!(
-        "
-    {{
-	\"move\": \"{}\", 
-	\"log\": \"{}\"
-    }}",
-        move_string, log
+    let id = jobs.start(board, limits, slot);
+    let job = jobs.get(id).unwrap();
+    Ok(status::Created(
+        format!("{}/jobs/{}", server.mount.trim_end_matches('/'), id),
+        Some(Json(JobBody::new(id, &job))),
     ))
 }
 
-pub fn api(port: u16) {
+#[get("/jobs/<id>")]
+fn get_job(id: u64, jobs: State<Jobs>) -> ApiResult<JobBody> {
+    let job = jobs.get(id).ok_or(ApiError::NoSuchJob)?;
+    Ok(Json(JobBody::new(id, &job)))
+}
+
+/// Stops a job early; its lines so far stay available until it expires
+#[delete("/jobs/<id>")]
+fn cancel_job(id: u64, jobs: State<Jobs>) -> ApiResult<JobBody> {
+    let job = jobs.get(id).ok_or(ApiError::NoSuchJob)?;
+    job.cancel();
+    Ok(Json(JobBody::new(id, &job)))
+}
+
+/// time between progress events
+const EVENT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A server-sent event stream of a job's progress
+///
+/// A `progress` event is sent every `EVENT_INTERVAL` while the job runs, then a final `done` event,
+/// each carrying the job's JSON body. Reads report `WouldBlock` after each event, which makes Rocket
+/// flush it to the client.
+struct EventStream {
+    id: u64,
+    job: Arc<Job>,
+    event: Vec<u8>,
+    /// bytes of `event` already read
+    position: usize,
+    flushed: bool,
+    finished: bool,
+}
+
+impl EventStream {
+    fn new(id: u64, job: Arc<Job>) -> EventStream {
+        EventStream {
+            id,
+            job,
+            event: Vec::new(),
+            position: 0,
+            flushed: true,
+            finished: false,
+        }
+    }
+
+    fn next_event(&mut self) {
+        let running = self.job.status() == JobStatus::Running;
+        let body = serde_json::to_string(&JobBody::new(self.id, &self.job)).unwrap();
+        let name = if running { "progress" } else { "done" };
+        self.event = format!("event: {}\ndata: {}\n\n", name, body).into_bytes();
+        self.position = 0;
+        self.flushed = false;
+        self.finished = !running;
+    }
+}
+
+impl Read for EventStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position < self.event.len() {
+            let n = (&self.event[self.position..]).read(buf)?;
+            self.position += n;
+            return Ok(n);
+        }
+        if !self.flushed {
+            self.flushed = true;
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        if self.finished {
+            return Ok(0);
+        }
+        if !self.event.is_empty() {
+            thread::sleep(EVENT_INTERVAL);
+        }
+        self.next_event();
+        self.read(buf)
+    }
+}
+
+impl<'r> Responder<'r> for EventStream {
+    fn respond_to(self, _request: &Request) -> response::Result<'r> {
+        Response::build()
+            .header(ContentType::new("text", "event-stream"))
+            .raw_header("Cache-Control", "no-cache")
+            .streamed_body(self)
+            .ok()
+    }
+}
+
+#[get("/jobs/<id>/events")]
+fn job_events(id: u64, jobs: State<Jobs>) -> Result<EventStream, ApiError> {
+    let job = jobs.get(id).ok_or(ApiError::NoSuchJob)?;
+    Ok(EventStream::new(id, job))
+}
+
+#[get("/health")]
+fn health() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+#[derive(Serialize)]
+struct VersionBody {
+    version: &'static str,
+    commit: &'static str,
+    patch: &'static str,
+}
+
+#[get("/version")]
+fn version() -> Json<VersionBody> {
+    Json(VersionBody {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: env!("HEAD_SHA"),
+        patch: env!("PATCH_SHA"),
+    })
+}
+
+#[get("/metrics")]
+fn metrics(metrics: State<Arc<Metrics>>, slots: State<SearchSlots>) -> content::Content<String> {
+    content::Content(
+        ContentType::with_params("text", "plain", ("version", "0.0.4")),
+        metrics.render(slots.active()),
+    )
+}
+
+#[catch(400)]
+fn bad_request(request: &Request) -> Json<ErrorBody> {
+    error_body(request, "bad_request", String::from("malformed request"))
+}
+
+#[catch(404)]
+fn not_found(request: &Request) -> Json<ErrorBody> {
+    error_body(request, "not_found", String::from("no such resource"))
+}
+
+#[catch(422)]
+fn unprocessable_entity(request: &Request) -> Json<ErrorBody> {
+    error_body(request, "bad_body", String::from("unreadable request body"))
+}
+
+#[catch(500)]
+fn internal_error(request: &Request) -> Json<ErrorBody> {
+    error_body(request, "internal", String::from("internal server error"))
+}
+
+fn rocket(server: &ServerConfig) -> Rocket {
     let config = Config::build(Environment::active().unwrap())
-        .port(port)
+        .address(server.address.as_str())
+        .port(server.port)
         .unwrap();
+    let metrics = Arc::new(Metrics::default());
     rocket::custom(config)
-        .attach(AllowOrigin::new(String::from("*")))
-        .mount("/theseus", routes![index])
-        .launch();
+        .attach(Cors {
+            server: server.clone(),
+        })
+        .attach(CountRequests(Arc::clone(&metrics)))
+        .manage(server.clone())
+        .manage(SearchSlots::new(server.max_searches, Arc::clone(&metrics)))
+        .manage(metrics)
+        .manage(Sessions::new(server.max_sessions))
+        .manage(Jobs::default())
+        .mount(
+            &server.mount,
+            routes![
+                index,
+                analyze,
+                render,
+                annotate,
+                create_game,
+                get_game,
+                game_history,
+                post_move,
+                engine_move,
+                undo_move,
+                delete_game,
+                create_job,
+                get_job,
+                cancel_job,
+                job_events,
+                preflight
+            ],
+        )
+        .mount("/", routes![health, version, metrics])
+        .register(catchers![
+            bad_request,
+            not_found,
+            unprocessable_entity,
+            internal_error
+        ])
+}
+
+pub fn api(server: &ServerConfig) {
+    rocket(server).launch();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::Header;
+    use rocket::local::Client;
+    use serde_json::Value;
+
+    fn client() -> Client {
+        Client::new(rocket(&ServerConfig::default())).unwrap()
+    }
+
+    /// Sends a request, checking the status and JSON content type, and returns the body
+    fn json(response: &mut rocket::local::LocalResponse, status: Status) -> Value {
+        assert_eq!(response.status(), status);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        serde_json::from_str(&response.body_string().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn position_errors() {
+        let client = client();
+        let cases = [
+            ("nonsense", Status::BadRequest, "bad_tqbn"),
+            // both pawns on e5
+            (
+                "nnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnn1e510e510",
+                Status::UnprocessableEntity,
+                "illegal_position",
+            ),
+            // white has already reached row 1
+            (
+                "nnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnn2e110e510",
+                Status::UnprocessableEntity,
+                "terminal_position",
+            ),
+        ];
+        for (tqbn, status, code) in cases.iter() {
+            let mut response = client.get(format!("/theseus/?tqbn={}", tqbn)).dispatch();
+            assert_eq!(json(&mut response, *status)["code"], *code);
+        }
+
+        let mut response = client.get("/theseus/nowhere").dispatch();
+        assert_eq!(json(&mut response, Status::NotFound)["code"], "not_found");
+    }
+
+    #[test]
+    fn winning_move() {
+        let client = client();
+        let tqbn = "nnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnn1e210a110";
+        let mut response = client
+            .get(format!("/theseus/?id=7&tqbn={}", tqbn))
+            .dispatch();
+        let body = json(&mut response, Status::Ok);
+        assert_eq!(body["id"], 7);
+        assert_eq!(body["move"], "e1");
+        assert_eq!(body["stats"], Value::Null);
+        let child = Board::parse_tqbn(body["tqbn"].as_str().unwrap()).unwrap();
+        assert_eq!(child.winner(), Some(Player::White));
+    }
+
+    #[test]
+    fn game_session() {
+        let client = client();
+        let mut response = client.post("/theseus/games").dispatch();
+        let id = json(&mut response, Status::Ok)["id"].as_u64().unwrap();
+        let moves = format!("/theseus/games/{}/moves", id);
+
+        let mut response = client
+            .post(moves.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"move": "e5"}"#)
+            .dispatch();
+        let body = json(&mut response, Status::UnprocessableEntity);
+        assert_eq!(body["code"], "illegal_move");
+
+        let mut response = client
+            .post(moves.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"move": "e8"}"#)
+            .dispatch();
+        let body = json(&mut response, Status::Ok);
+        assert_eq!(body["moves"], serde_json::json!(["e8"]));
+        assert_eq!(body["turn"], "black");
+
+        let mut response = client
+            .post(moves)
+            .header(ContentType::JSON)
+            .body("e2")
+            .dispatch();
+        assert_eq!(
+            json(&mut response, Status::BadRequest)["code"],
+            "bad_request"
+        );
+
+        let mut response = client
+            .post(format!("/theseus/games/{}/undo", id))
+            .dispatch();
+        assert_eq!(
+            json(&mut response, Status::Ok)["moves"],
+            serde_json::json!([])
+        );
+
+        let mut response = client
+            .post(format!("/theseus/games/{}/undo", id))
+            .dispatch();
+        let body = json(&mut response, Status::UnprocessableEntity);
+        assert_eq!(body["code"], "nothing_to_undo");
+
+        let mut response = client.delete(format!("/theseus/games/{}", id)).dispatch();
+        assert_eq!(json(&mut response, Status::Ok)["id"], id);
+        let mut response = client.get(format!("/theseus/games/{}", id)).dispatch();
+        assert_eq!(json(&mut response, Status::NotFound)["code"], "not_found");
+    }
+
+    #[test]
+    fn render_svg() {
+        let client = client();
+        let tqbn = "nnnnnnnnnnnnnnnn1c506c106";
+        let mut response = client
+            .get(format!(
+                "/theseus/render?tqbn={}&last=c1&paths=true&visits=c4:30,b4h:12",
+                tqbn
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::SVG));
+        let body = response.body_string().unwrap();
+        assert!(body.starts_with("<svg "));
+        assert!(body.contains("<title>b4h 12</title>"));
+
+        let mut response = client
+            .get(format!("/theseus/render?tqbn={}&visits=c4", tqbn))
+            .dispatch();
+        let body = json(&mut response, Status::BadRequest);
+        assert_eq!(body["code"], "bad_visits");
+    }
+
+    #[test]
+    fn annotate_record() {
+        let client = client();
+        let record = r#"[TQBN \"nnnnnnnnnnnnnnnn1c206c405:size=5,walls=6\"]\n\n1. a4h c5 0-1"#;
+        let mut response = client
+            .post("/theseus/annotate")
+            .header(ContentType::JSON)
+            .body(format!(r#"{{"record": "{}", "seed": 1}}"#, record))
+            .dispatch();
+        let body = json(&mut response, Status::Ok);
+        assert_eq!(body["moves"][0]["judgment"], "blunder");
+        assert_eq!(body["moves"][0]["best_move"], "c1");
+        assert_eq!(body["moves"][1]["judgment"], Value::Null);
+        assert!(body["record"].as_str().unwrap().contains("{blunder: "));
+
+        let mut response = client
+            .post("/theseus/annotate")
+            .header(ContentType::JSON)
+            .body(r#"{"record": "1. e8 e9"}"#)
+            .dispatch();
+        assert_eq!(
+            json(&mut response, Status::BadRequest)["code"],
+            "bad_record"
+        );
+
+        let mut response = client
+            .post("/theseus/annotate")
+            .header(ContentType::JSON)
+            .body(r#"{"record": "1. e8", "review": "blunder=0"}"#)
+            .dispatch();
+        let body = json(&mut response, Status::BadRequest);
+        assert_eq!(body["code"], "bad_review_settings");
+    }
+
+    #[test]
+    fn small_board_game() {
+        let client = client();
+        let mut response = client.post("/theseus/games?rules=size%3D5").dispatch();
+        let body = json(&mut response, Status::Ok);
+        assert_eq!(body["tqbn"], "nnnnnnnnnnnnnnnn1c506c106");
+        let mut response = client.post("/theseus/games?rules=size%3D6").dispatch();
+        assert_eq!(json(&mut response, Status::BadRequest)["code"], "bad_rules");
+    }
+
+    /// Starts a job, returning its id
+    fn start_job(client: &Client, request: &str) -> u64 {
+        let mut response = client
+            .post("/theseus/jobs")
+            .header(ContentType::JSON)
+            .body(request)
+            .dispatch();
+        let body = json(&mut response, Status::Created);
+        let id = body["id"].as_u64().unwrap();
+        let location = format!("/theseus/jobs/{}", id);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some(location.as_str())
+        );
+        id
+    }
+
+    #[test]
+    fn analysis_job() {
+        let client = client();
+        let tqbn = Board::new().to_tqbn();
+        let request = format!(r#"{{"tqbn": "{}", "iterations": 300, "threads": 1}}"#, tqbn);
+        let id = start_job(&client, &request);
+
+        let body = loop {
+            let mut response = client.get(format!("/theseus/jobs/{}", id)).dispatch();
+            let body = json(&mut response, Status::Ok);
+            if body["status"] != "running" {
+                break body;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(body["status"], "done");
+        assert_eq!(body["iterations"], 300);
+        assert_eq!(body["lines"].as_array().unwrap().len(), 3);
+
+        // a finished job streams a single done event
+        let mut response = client
+            .get(format!("/theseus/jobs/{}/events", id))
+            .dispatch();
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("text", "event-stream"))
+        );
+        let reader = response.body().unwrap().into_inner();
+        let mut events = Vec::new();
+        let mut buf = [0; 256];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => events.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{}", e),
+            }
+        }
+        let events = String::from_utf8(events).unwrap();
+        assert!(events.starts_with("event: done\ndata: {"));
+        assert!(events.ends_with("}\n\n"));
+    }
+
+    #[test]
+    fn cancelled_job() {
+        let client = client();
+        let tqbn = Board::new().to_tqbn();
+        let request = format!(r#"{{"tqbn": "{}", "iterations": 100000000}}"#, tqbn);
+        let id = start_job(&client, &request);
+
+        let mut response = client.delete(format!("/theseus/jobs/{}", id)).dispatch();
+        assert_eq!(json(&mut response, Status::Ok)["status"], "cancelled");
+        let mut response = client.get(format!("/theseus/jobs/{}", id)).dispatch();
+        assert_eq!(json(&mut response, Status::Ok)["status"], "cancelled");
+
+        let request = format!(r#"{{"tqbn": "{}", "searchmoves": ["e5"]}}"#, tqbn);
+        let mut response = client
+            .post("/theseus/jobs")
+            .header(ContentType::JSON)
+            .body(request)
+            .dispatch();
+        let body = json(&mut response, Status::UnprocessableEntity);
+        assert_eq!(body["code"], "illegal_move");
+
+        let mut response = client.get("/theseus/jobs/1").dispatch();
+        assert_eq!(
+            json(&mut response, Status::NotFound)["message"],
+            "no such job"
+        );
+    }
+
+    #[test]
+    fn server_settings() {
+        let server = ServerConfig {
+            allowed_origins: vec![String::from("https://a.example")],
+            max_searches: 0,
+            mount: String::from("/api/v1"),
+            ..Default::default()
+        };
+        let client = Client::new(rocket(&server)).unwrap();
+
+        let response = client
+            .options("/api/v1/jobs")
+            .header(Header::new("Origin", "https://a.example"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        let headers = response.headers();
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Origin"),
+            Some("https://a.example")
+        );
+        assert!(headers.get_one("Access-Control-Allow-Methods").is_some());
+
+        let response = client
+            .options("/api/v1/jobs")
+            .header(Header::new("Origin", "https://b.example"))
+            .dispatch();
+        assert!(response
+            .headers()
+            .get_one("Access-Control-Allow-Origin")
+            .is_none());
+
+        // with no search slots every search is refused
+        let tqbn = Board::new().to_tqbn();
+        let mut response = client.get(format!("/api/v1/?tqbn={}", tqbn)).dispatch();
+        assert_eq!(
+            json(&mut response, Status::ServiceUnavailable)["code"],
+            "busy"
+        );
+        let mut response = client.get(format!("/theseus/?tqbn={}", tqbn)).dispatch();
+        json(&mut response, Status::NotFound);
+    }
+
+    #[test]
+    fn monitoring() {
+        let client = client();
+        let mut response = client.get("/health").dispatch();
+        assert_eq!(json(&mut response, Status::Ok)["status"], "ok");
+        let mut response = client.get("/version").dispatch();
+        assert_eq!(json(&mut response, Status::Ok)["commit"], env!("HEAD_SHA"));
+        let mut response = client.get("/theseus/health").dispatch();
+        json(&mut response, Status::NotFound);
+
+        client.get("/theseus/?tqbn=nonsense").dispatch();
+        let tqbn = "nnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnn1e210a110";
+        client.get(format!("/theseus/?tqbn={}", tqbn)).dispatch();
+
+        let mut response = client.get("/metrics").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type().unwrap().media_type().sub(), "plain");
+        let text = response.body_string().unwrap();
+        assert!(text.contains(
+            "theseus_requests_total{method=\"GET\",route=\"/theseus\",status=\"400\"} 1\n"
+        ));
+        assert!(text.contains(
+            "theseus_requests_total{method=\"GET\",route=\"/theseus\",status=\"200\"} 1\n"
+        ));
+        assert!(text.contains("theseus_errors_total{code=\"bad_tqbn\"} 1\n"));
+        assert!(text.contains("theseus_search_duration_seconds_count 1\n"));
+        assert!(text.contains("theseus_active_searches 0\n"));
+    }
 }
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Instant;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::ai::{Config, Tree};
+use crate::Board;
+
+/// The result of analyzing one input line
+#[derive(Serialize)]
+struct Record {
+    /// line number in the input, starting at 1
+    line: usize,
+    /// the input's `id` field, for JSONL input that has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<Value>,
+    #[serde(flatten)]
+    result: Outcome,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Outcome {
+    Move {
+        tqbn: String,
+        #[serde(rename = "move")]
+        move_string: String,
+        /// null when the move was found without searching
+        value: Option<f64>,
+        visits: Option<u32>,
+        time_ms: u64,
+    },
+    Error {
+        error: String,
+    },
+}
+
+/// Splits an input line into its TQBN and optional id
+///
+/// A line starting with `{` is read as a JSON object with a `tqbn` field and an optional `id`;
+/// anything else is taken as a bare TQBN.
+pub(super) fn parse_line(line: &str) -> (Option<Value>, Result<String, String>) {
+    if !line.starts_with('{') {
+        return (None, Ok(line.to_string()));
+    }
+    let object: Value = match serde_json::from_str(line) {
+        Ok(object) => object,
+        Err(error) => return (None, Err(format!("bad json: {}", error))),
+    };
+    let id = object.get("id").cloned();
+    match object.get("tqbn").and_then(Value::as_str) {
+        Some(tqbn) => (id, Ok(tqbn.to_string())),
+        None => (id, Err(String::from("missing tqbn"))),
+    }
+}
+
+fn analyze_line(line: &str, config: &Config) -> (Option<Value>, Outcome) {
+    let (id, tqbn) = parse_line(line);
+    let board = tqbn.and_then(|tqbn| {
+        let board = Board::parse_tqbn(&tqbn).map_err(|error| format!("bad tqbn: {}", error))?;
+        board
+            .validate()
+            .map_err(|error| format!("illegal position: {}", error))?;
+        if board.winner().is_some() {
+            return Err(String::from("the game is already over"));
+        }
+        Ok(board)
+    });
+    let board = match board {
+        Ok(board) => board,
+        Err(error) => return (id, Outcome::Error { error }),
+    };
+
+    let start = Instant::now();
+    let mut log = String::new();
+    let decision = crate::ai::decide(
+        &board,
+        config,
+        &mut Tree::default(),
+        &Arc::default(),
+        &mut log,
+    );
+    let decision = match decision {
+        Some(decision) => decision,
+        None => {
+            let error = String::from("the game is already over");
+            return (id, Outcome::Error { error });
+        }
+    };
+    let outcome = Outcome::Move {
+        tqbn: board.to_tqbn(),
+        move_string: board.move_string_to(&decision.board),
+        value: decision.stats.as_ref().map(|stats| stats.value),
+        visits: decision.stats.as_ref().map(|stats| stats.visits),
+        time_ms: start.elapsed().as_millis() as u64,
+    };
+    (id, outcome)
+}
+
+/// Runs the engine on every position in `input`, writing one JSON object per position to `output`
+///
+/// Positions are analyzed `jobs` at a time, but results are written in input order. Blank lines
+/// are skipped, and lines that can't be analyzed produce an object with an `error` field rather
+/// than stopping the batch. Returns the number of such errors.
+pub fn analyze<R: BufRead, W: Write>(
+    input: R,
+    mut output: W,
+    config: &Config,
+    jobs: usize,
+) -> io::Result<usize> {
+    let mut lines = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            lines.push((i + 1, line.trim().to_string()));
+        }
+    }
+    let lines = Arc::new(lines);
+
+    let next_line = Arc::new(AtomicUsize::new(0));
+    let (results_tx, results_rx) = mpsc::channel();
+    for _ in 0..jobs.max(1) {
+        let lines = Arc::clone(&lines);
+        let next_line = Arc::clone(&next_line);
+        let results_tx = results_tx.clone();
+        let config = config.clone();
+        thread::spawn(move || loop {
+            let index = next_line.fetch_add(1, Ordering::SeqCst);
+            let (number, line) = match lines.get(index) {
+                Some(line) => line,
+                None => break,
+            };
+            let (id, result) = analyze_line(line, &config);
+            let record = Record {
+                line: *number,
+                id,
+                result,
+            };
+            if results_tx.send((index, record)).is_err() {
+                break;
+            }
+        });
+    }
+    drop(results_tx);
+
+    // results arrive in completion order; hold them until the ones before them are written
+    let mut pending = BTreeMap::new();
+    let mut next_output = 0;
+    let mut errors = 0;
+    for (index, record) in results_rx {
+        pending.insert(index, record);
+        while let Some(record) = pending.remove(&next_output) {
+            if let Outcome::Error { .. } = record.result {
+                errors += 1;
+            }
+            writeln!(output, "{}", serde_json::to_string(&record).unwrap())?;
+            output.flush()?;
+            next_output += 1;
+        }
+    }
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_lines() {
+        let walls = "n".repeat(64);
+        let input = format!(
+            "{w}1e210a110\n\nnonsense\n{{\"id\": 7, \"tqbn\": \"{w}2e510e810\"}}\n{{\"id\": 8}}\n{{oops\n",
+            w = walls
+        );
+        let mut output = Vec::new();
+        let errors = analyze(input.as_bytes(), &mut output, &Config::default(), 2).unwrap();
+        assert_eq!(errors, 3);
+
+        let records: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let lines: Vec<_> = records
+            .iter()
+            .map(|r| r["line"].as_u64().unwrap())
+            .collect();
+        assert_eq!(lines, vec![1, 3, 4, 5, 6]);
+        assert_eq!(records[0]["move"], "e1");
+        assert_eq!(records[0]["value"], Value::Null);
+        assert!(records[1]["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("bad tqbn"));
+        assert_eq!(records[2]["id"], 7);
+        assert_eq!(records[2]["move"], "e9");
+        assert_eq!(records[3]["id"], 8);
+        assert_eq!(records[3]["error"], "missing tqbn");
+        assert!(records[4]["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("bad json"));
+    }
+}
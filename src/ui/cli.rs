@@ -1,6 +1,8 @@
 extern crate clap;
 
-use clap::{App, Arg, SubCommand};
+use std::io::{Read, Write};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
 
 pub fn cli() {
     let mut app = App::new("theseus")
@@ -13,17 +15,435 @@ pub fn cli() {
                     Arg::with_name("tqbn")
                         .help("Board in TQBN notation")
                         .required(true),
+                )
+                .arg(
+                    Arg::with_name("multipv")
+                        .short("n")
+                        .long("multipv")
+                        .help("Prints this many candidate moves instead of a single move")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("searchmoves")
+                        .long("searchmoves")
+                        .help("Only considers these moves at the root (comma separated)")
+                        .takes_value(true)
+                        .use_delimiter(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("tree")
+                        .long("tree")
+                        .help("Writes the top of the search tree to this file, as Graphviz DOT if it ends in .dot and JSON otherwise")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("tree-depth")
+                        .long("tree-depth")
+                        .help("Plies of the search tree to write")
+                        .default_value("2"),
+                )
+                .arg(
+                    Arg::with_name("tree-min-visits")
+                        .long("tree-min-visits")
+                        .help("Leaves out nodes of the written tree with fewer visits")
+                        .default_value("10"),
+                )
+                .args(&config_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("four")
+                .about("Makes a move from a four-player board state")
+                .arg(
+                    Arg::with_name("tqbn")
+                        .help("Board in four-player TQBN notation")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("config")
+                        .long("config")
+                        .help("Four-player engine settings, as key=value pairs (comma separated)")
+                        .takes_value(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("play")
+                .about("Plays a game against the engine in the terminal")
+                .arg(
+                    Arg::with_name("side")
+                        .short("s")
+                        .long("side")
+                        .help("Side to play as")
+                        .possible_values(&["white", "black"])
+                        .default_value("white"),
+                )
+                .arg(
+                    Arg::with_name("engine")
+                        .short("e")
+                        .long("engine")
+                        .help("Engine to play against")
+                        .possible_values(&["default", "random"])
+                        .default_value("default"),
+                )
+                .arg(
+                    Arg::with_name("tqbn")
+                        .long("tqbn")
+                        .help("Starts from this board in TQBN notation")
+                        .takes_value(true),
+                )
+                .arg(rules_arg())
+                .args(&config_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("match")
+                .about("Plays two engine configurations against each other")
+                .arg(
+                    Arg::with_name("engine-a")
+                        .short("a")
+                        .long("engine-a")
+                        .help("Settings for engine a, as key=value pairs (comma separated)")
+                        .takes_value(true)
+                        .default_value(""),
+                )
+                .arg(
+                    Arg::with_name("engine-b")
+                        .short("b")
+                        .long("engine-b")
+                        .help("Settings for engine b, as key=value pairs (comma separated)")
+                        .takes_value(true)
+                        .default_value(""),
+                )
+                .args(&match_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("sprt")
+                .about("Tests a candidate configuration against the default engine with an SPRT")
+                .arg(
+                    Arg::with_name("candidate")
+                        .long("candidate")
+                        .help("Settings for the candidate engine, as key=value pairs (comma separated)")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("base")
+                        .long("base")
+                        .help("Settings applied to both engines before the candidate's, such as a lower iteration count")
+                        .takes_value(true)
+                        .default_value(""),
+                )
+                .arg(
+                    Arg::with_name("elo0")
+                        .long("elo0")
+                        .help("Elo difference of the null hypothesis")
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::with_name("elo1")
+                        .long("elo1")
+                        .help("Elo difference of the alternative hypothesis")
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::with_name("alpha")
+                        .long("alpha")
+                        .help("Probability of a false positive")
+                        .default_value("0.05"),
+                )
+                .arg(
+                    Arg::with_name("beta")
+                        .long("beta")
+                        .help("Probability of a false negative")
+                        .default_value("0.05"),
+                )
+                .args(&match_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .about("Finds the engine's move for each position in a file, writing JSONL")
+                .arg(
+                    Arg::with_name("input")
+                        .help("File of TQBNs, one per line, or JSONL objects with a tqbn field; - or absent reads stdin"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("Writes results to this file instead of stdout")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .short("j")
+                        .long("jobs")
+                        .help("Positions analyzed at the same time")
+                        .takes_value(true),
+                )
+                .args(&config_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("annotate")
+                .about("Reviews each move of game records with the engine, writing annotated records")
+                .arg(
+                    Arg::with_name("input")
+                        .help("File of game records; - or absent reads stdin"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("Writes annotated records to this file instead of stdout")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("review")
+                        .long("review")
+                        .help("Review settings (inaccuracy, mistake, blunder, alternatives, movetime), as key=value pairs (comma separated), such as mistake=0.5,blunder=1")
+                        .takes_value(true),
+                )
+                .args(&config_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("render")
+                .about("Draws a position as an SVG image")
+                .arg(
+                    Arg::with_name("tqbn")
+                        .help("Board in TQBN notation")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("Writes the image to this file instead of stdout")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("last-move")
+                        .long("last-move")
+                        .help("Highlights this move as the one that reached the position")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("paths")
+                        .long("paths")
+                        .help("Draws each player's shortest path to its goal"),
+                )
+                .arg(
+                    Arg::with_name("heatmap")
+                        .long("heatmap")
+                        .help("Searches the position and shades each move by its visits"),
+                )
+                .args(&config_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("book")
+                .about("Manages opening books")
+                .subcommand(
+                    SubCommand::with_name("build")
+                        .about("Builds an opening book from game records, or by searching the opening if none are given")
+                        .arg(
+                            Arg::with_name("output")
+                                .short("o")
+                                .long("output")
+                                .help("Book file to write")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("games")
+                                .long("games")
+                                .help("File of game records to take moves from")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("depth")
+                                .short("d")
+                                .long("depth")
+                                .help("Plies from the start to include")
+                                .default_value("6"),
+                        )
+                        .arg(
+                            Arg::with_name("min-share")
+                                .long("min-share")
+                                .help("Fraction of a search's visits a move needs to be included and explored")
+                                .default_value("0.1"),
+                        )
+                        .arg(rules_arg())
+                        .args(&config_args()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("puzzles")
+                .about("Finds and checks puzzles: positions where exactly one move forces a win")
+                .subcommand(
+                    SubCommand::with_name("generate")
+                        .about("Scans game records and positions for puzzles, writing JSONL")
+                        .arg(
+                            Arg::with_name("games")
+                                .long("games")
+                                .help("File of game records to scan")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        )
+                        .arg(
+                            Arg::with_name("positions")
+                                .long("positions")
+                                .help("File of TQBNs, one per line, or JSONL objects with a tqbn field, such as self-play shards")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .short("o")
+                                .long("output")
+                                .help("Writes puzzles to this file instead of stdout")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("min-moves")
+                                .long("min-moves")
+                                .help("Fewest moves, including the key move, a puzzle's win may take")
+                                .default_value("2"),
+                        )
+                        .arg(
+                            Arg::with_name("max-moves")
+                                .long("max-moves")
+                                .help("Most moves a puzzle's win may take; each one makes the search much slower")
+                                .default_value("3"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("verify")
+                        .about("Checks that each puzzle's solution wins and is the only one")
+                        .arg(
+                            Arg::with_name("input")
+                                .help("File of puzzles, as written by generate; - or absent reads stdin"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("selfplay")
+                .about("Plays the engine against itself, writing training data as sharded JSONL")
+                .arg(
+                    Arg::with_name("output-dir")
+                        .short("o")
+                        .long("output-dir")
+                        .help("Directory for the shards; complete shards already there are kept")
+                        .default_value("selfplay"),
+                )
+                .arg(
+                    Arg::with_name("games")
+                        .short("g")
+                        .long("games")
+                        .help("Number of games to play")
+                        .default_value("100"),
+                )
+                .arg(
+                    Arg::with_name("games-per-shard")
+                        .long("games-per-shard")
+                        .help("Games written to each shard file")
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::with_name("concurrency")
+                        .short("c")
+                        .long("concurrency")
+                        .help("Number of shards to play at once")
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::with_name("temperature")
+                        .long("temperature")
+                        .help("Opening moves are sampled by visits^(1/temperature); 0 always plays the best move")
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::with_name("temperature-plies")
+                        .long("temperature-plies")
+                        .help("Plies at the start of each game that are sampled")
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::with_name("max-plies")
+                        .long("max-plies")
+                        .help("Plies after which a game is stopped as a draw")
+                        .default_value("200"),
+                )
+                .arg(rules_arg())
+                .args(&config_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("uci")
+                .about("Speaks a UCI-like engine protocol on stdin and stdout")
+                .args(&config_args()),
+        )
         .subcommand(
             SubCommand::with_name("api")
                 .about("Runs HTTP API server")
+                .arg(
+                    Arg::with_name("config-file")
+                        .short("c")
+                        .long("config-file")
+                        .help("Reads server settings from this TOML file; flags override it")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("address")
+                        .long("address")
+                        .help("Listen on this address")
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::with_name("port")
                         .short("p")
                         .long("port")
                         .help("Listen on this port")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("allowed-origins")
+                        .long("allowed-origins")
+                        .help("Origins allowed to make cross-origin requests (comma separated; * allows any)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-searches")
+                        .long("max-searches")
+                        .help("Searches that may run at once")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-sessions")
+                        .long("max-sessions")
+                        .help("Games kept at once; starting another drops the least recently used")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-iterations")
+                        .long("max-iterations")
+                        .help("Iterations per thread any one search may run")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-threads")
+                        .long("max-threads")
+                        .help("Threads any one search may use")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-movetime")
+                        .long("max-movetime")
+                        .help("Milliseconds any one search may run for")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("mount")
+                        .long("mount")
+                        .help("Path to serve the API under")
+                        .takes_value(true),
                 ),
         );
 
@@ -33,21 +453,262 @@ pub fn cli() {
             let tqbn = sub_m.value_of("tqbn").unwrap();
             eprintln!("input: {}", tqbn);
             let board = crate::Board::from_tqbn(tqbn);
-            board.print();
+            crate::ui::eprint_board(&board, &Default::default());
+
+            if sub_m.is_present("multipv") || sub_m.is_present("searchmoves") {
+                analyze(&board, sub_m);
+                return;
+            }
 
             let mut log = String::new();
-            let child = crate::ai::default(&board, &mut log);
+            let mut tree = crate::ai::Tree::default();
+            let child =
+                crate::ai::configured_with_tree(&board, &config(sub_m), &mut tree, &mut log);
             eprint!("{}", log);
+            let child = child.unwrap_or_else(|| {
+                eprintln!("the game is already over");
+                std::process::exit(1);
+            });
+            if let Some(path) = sub_m.value_of("tree") {
+                write_tree(&tree, path, sub_m);
+            }
             let move_string = board.move_string_to(&child);
             eprintln!("output: {}", move_string);
-            child.print();
+            let highlights = crate::ui::Highlights {
+                last_move: Some(move_string.clone()),
+                paths: Vec::new(),
+            };
+            crate::ui::eprint_board(&child, &highlights);
 
             println!("{}", move_string);
         }
-        ("api", Some(sub_m)) => {
-            let port: u16 = sub_m.value_of("port").unwrap_or("8000").parse().unwrap();
-            crate::api(port);
+        ("four", Some(sub_m)) => {
+            let tqbn = sub_m.value_of("tqbn").unwrap();
+            eprintln!("input: {}", tqbn);
+            let board = crate::FourBoard::parse_tqbn(tqbn)
+                .and_then(|board| board.validate().map(|_| board))
+                .unwrap_or_else(|error| {
+                    eprintln!("bad tqbn: {}", error);
+                    std::process::exit(1);
+                });
+            eprint!("{}", board);
+            if let Some(winner) = board.winner() {
+                eprintln!("the game is over: player {} won", winner + 1);
+                std::process::exit(1);
+            }
+            let config: crate::ai::FourConfig = sub_m
+                .value_of("config")
+                .unwrap_or("")
+                .parse()
+                .unwrap_or_else(|error| {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                });
+
+            let mut log = String::new();
+            let child = crate::ai::mcts4(&board, &config, &mut log);
+            eprint!("{}", log);
+            let child = child.unwrap_or_else(|| {
+                eprintln!("player {} has no legal moves", board.turn() + 1);
+                std::process::exit(1);
+            });
+            let move_string = board.move_string_to(&child);
+            eprintln!("output: {}", move_string);
+            eprint!("{}", child);
+
+            println!("{}", move_string);
+        }
+        ("play", Some(sub_m)) => {
+            let start = match sub_m.value_of("tqbn") {
+                Some(tqbn) => crate::Board::parse_tqbn(tqbn)
+                    .and_then(|board| board.validate().map(|_| board))
+                    .unwrap_or_else(|error| {
+                        eprintln!("bad tqbn: {}", error);
+                        std::process::exit(1);
+                    }),
+                None => crate::Board::with_rules(rules(sub_m)),
+            };
+            if start.winner().is_some() {
+                eprintln!("the game is already over");
+                std::process::exit(1);
+            }
+            let human = match sub_m.value_of("side").unwrap() {
+                "black" => crate::board::Player::Black,
+                _ => crate::board::Player::White,
+            };
+            let engine = match sub_m.value_of("engine").unwrap() {
+                "random" => crate::ui::Engine::Random,
+                _ => crate::ui::Engine::Default,
+            };
+            crate::play(start, human, engine, &config(sub_m));
+        }
+        ("match", Some(sub_m)) => {
+            let a = parse_config(sub_m.value_of("engine-a").unwrap());
+            let b = parse_config(sub_m.value_of("engine-b").unwrap());
+            let settings = match_settings(sub_m);
+            let mut output = open_output(sub_m);
+
+            println!("a:\t\t{}", a);
+            println!("b:\t\t{}", b);
+
+            let mut stats = crate::tournament::Stats::default();
+            let result = crate::tournament::run_match(&a, &b, &settings, |record| {
+                stats.add(&record);
+                save_record(&mut output, &record, &a, &b);
+                eprintln!(
+                    "game {}: {}\tW/L/D {}/{}/{}",
+                    record.index + 1,
+                    result_string(record.winner),
+                    stats.wins,
+                    stats.losses,
+                    stats.draws
+                );
+                true
+            });
+            if let Err(error) = result {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+
+            let (elo, low, high) = stats.elo();
+            println!("games:\t\t{}", stats.games());
+            println!("W/L/D:\t\t{}/{}/{}", stats.wins, stats.losses, stats.draws);
+            println!("score:\t\t{:.3}", stats.score());
+            println!("elo:\t\t{:.1} [{:.1}, {:.1}]", elo, low, high);
+            println!("length:\t\t{:.1} plies", stats.average_length());
+        }
+        ("sprt", Some(sub_m)) => {
+            let base = sub_m.value_of("base").unwrap();
+            let default = parse_config(base);
+            let candidate = parse_config(&format!(
+                "{},{}",
+                base,
+                sub_m.value_of("candidate").unwrap()
+            ));
+            let sprt = crate::tournament::Sprt {
+                elo0: sub_m.value_of("elo0").unwrap().parse().unwrap(),
+                elo1: sub_m.value_of("elo1").unwrap().parse().unwrap(),
+                alpha: sub_m.value_of("alpha").unwrap().parse().unwrap(),
+                beta: sub_m.value_of("beta").unwrap().parse().unwrap(),
+            };
+            let mut settings = match_settings(sub_m);
+            if !sub_m.is_present("games") {
+                settings.games = 100_000;
+            }
+            let mut output = open_output(sub_m);
+
+            let (lower, upper) = sprt.bounds();
+            println!("candidate:\t{}", candidate);
+            println!("default:\t{}", default);
+            println!(
+                "elo0 {} elo1 {} alpha {} beta {}, llr bounds [{:.3}, {:.3}]",
+                sprt.elo0, sprt.elo1, sprt.alpha, sprt.beta, lower, upper
+            );
+
+            let mut stats = crate::tournament::Stats::default();
+            let mut result = crate::tournament::SprtResult::Continue;
+            let outcome = crate::tournament::run_match(&candidate, &default, &settings, |record| {
+                stats.add(&record);
+                save_record(&mut output, &record, &candidate, &default);
+                result = sprt.result(&stats);
+                println!(
+                    "game {}: {}\tW/L/D {}/{}/{}\tllr {:.3}",
+                    stats.games(),
+                    result_string(record.winner),
+                    stats.wins,
+                    stats.losses,
+                    stats.draws,
+                    sprt.llr(&stats)
+                );
+                result == crate::tournament::SprtResult::Continue
+            });
+            if let Err(error) = outcome {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+
+            let (elo, low, high) = stats.elo();
+            println!("elo:\t\t{:.1} [{:.1}, {:.1}]", elo, low, high);
+            println!(
+                "{}",
+                match result {
+                    crate::tournament::SprtResult::AcceptH1 => "H1 accepted: candidate passes",
+                    crate::tournament::SprtResult::AcceptH0 => "H0 accepted: candidate fails",
+                    crate::tournament::SprtResult::Continue => "inconclusive: game limit reached",
+                }
+            );
+        }
+        ("analyze", Some(sub_m)) => {
+            let config = config(sub_m);
+            let jobs = sub_m.value_of("jobs").unwrap_or("1").parse().unwrap();
+            let stdin = std::io::stdin();
+            let input: Box<dyn std::io::BufRead> = match sub_m.value_of("input") {
+                None | Some("-") => Box::new(stdin.lock()),
+                Some(path) => match std::fs::File::open(path) {
+                    Ok(file) => Box::new(std::io::BufReader::new(file)),
+                    Err(error) => {
+                        eprintln!("{}: {}", path, error);
+                        std::process::exit(1);
+                    }
+                },
+            };
+            let output: Box<dyn Write> = match open_output(sub_m) {
+                Some(file) => Box::new(file),
+                None => Box::new(std::io::stdout()),
+            };
+            let errors = super::batch::analyze(input, output, &config, jobs).unwrap();
+            if errors > 0 {
+                eprintln!("{} lines could not be analyzed", errors);
+            }
+        }
+        ("annotate", Some(sub_m)) => annotate(sub_m),
+        ("render", Some(sub_m)) => render(sub_m),
+        ("book", Some(sub_m)) => match sub_m.subcommand() {
+            ("build", Some(sub_m)) => build_book(sub_m),
+            _ => {
+                println!("{}", sub_m.usage());
+            }
+        },
+        ("puzzles", Some(sub_m)) => match sub_m.subcommand() {
+            ("generate", Some(sub_m)) => generate_puzzles(sub_m),
+            ("verify", Some(sub_m)) => verify_puzzles(sub_m),
+            _ => {
+                println!("{}", sub_m.usage());
+            }
+        },
+        ("selfplay", Some(sub_m)) => {
+            let config = config(sub_m);
+            let settings = crate::selfplay::SelfplaySettings {
+                games: sub_m.value_of("games").unwrap().parse().unwrap(),
+                games_per_shard: sub_m.value_of("games-per-shard").unwrap().parse().unwrap(),
+                concurrency: sub_m.value_of("concurrency").unwrap().parse().unwrap(),
+                temperature: sub_m.value_of("temperature").unwrap().parse().unwrap(),
+                temperature_plies: sub_m
+                    .value_of("temperature-plies")
+                    .unwrap()
+                    .parse()
+                    .unwrap(),
+                max_plies: sub_m.value_of("max-plies").unwrap().parse().unwrap(),
+                output_dir: sub_m.value_of("output-dir").unwrap().into(),
+                rules: rules(sub_m),
+            };
+            let result = crate::selfplay::run(&config, &settings, |shard, existing| {
+                if existing {
+                    println!("shard {}: already complete", shard);
+                } else {
+                    println!("shard {}: done", shard);
+                }
+            });
+            if let Err(error) = result {
+                eprintln!("{}: {}", settings.output_dir.display(), error);
+                std::process::exit(1);
+            }
         }
+        ("uci", Some(sub_m)) => {
+            let stdin = std::io::stdin();
+            super::uci::run(stdin.lock(), std::io::stdout(), config(sub_m));
+        }
+        ("api", Some(sub_m)) => crate::api(&server_config(sub_m)),
         _ => {
             app.print_help().unwrap();
             println!();
@@ -65,3 +726,469 @@ pub fn cli() {
 
     */
 }
+
+fn build_book(sub_m: &ArgMatches) {
+    let depth = sub_m.value_of("depth").unwrap().parse().unwrap();
+    let book = match sub_m.value_of("games") {
+        Some(path) => {
+            let games = std::fs::read_to_string(path)
+                .map_err(|error| error.to_string())
+                .and_then(|text| {
+                    crate::game::Game::parse_all(&text).map_err(|error| error.to_string())
+                })
+                .unwrap_or_else(|error| {
+                    eprintln!("{}: {}", path, error);
+                    std::process::exit(1);
+                });
+            println!("games:\t\t{}", games.len());
+            crate::ai::Book::from_games(&games, depth)
+        }
+        None => {
+            let min_share = sub_m.value_of("min-share").unwrap().parse().unwrap();
+            crate::ai::Book::from_search(
+                &crate::Board::with_rules(rules(sub_m)),
+                &config(sub_m),
+                depth,
+                min_share,
+                |board, lines| {
+                    let best = lines.first().map_or("", |line| &line.move_string);
+                    println!("{}\t{}", board.to_tqbn(), best);
+                },
+            )
+        }
+    };
+    println!("positions:\t{}", book.len());
+
+    let path = sub_m.value_of("output").unwrap();
+    if let Err(error) = std::fs::write(path, book.to_string()) {
+        eprintln!("{}: {}", path, error);
+        std::process::exit(1);
+    }
+}
+
+fn write_tree(tree: &crate::ai::Tree, path: &str, sub_m: &ArgMatches) {
+    let depth = sub_m.value_of("tree-depth").unwrap().parse().unwrap();
+    let min_visits = sub_m.value_of("tree-min-visits").unwrap().parse().unwrap();
+    let root = match tree.export(depth, min_visits) {
+        Some(root) => root,
+        None => {
+            eprintln!("no search tree to write: the move was chosen without searching");
+            return;
+        }
+    };
+    let text = if path.ends_with(".dot") {
+        root.to_dot()
+    } else {
+        serde_json::to_string_pretty(&root).unwrap()
+    };
+    if let Err(error) = std::fs::write(path, text) {
+        eprintln!("{}: {}", path, error);
+        std::process::exit(1);
+    }
+}
+
+fn annotate(sub_m: &ArgMatches) {
+    let config = config(sub_m);
+    let settings: crate::annotate::ReviewSettings = sub_m
+        .value_of("review")
+        .unwrap_or("")
+        .parse()
+        .unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+    let input = sub_m.value_of("input").unwrap_or("-");
+    let games = crate::game::Game::parse_all(&read_input(input)).unwrap_or_else(|error| {
+        eprintln!("{}: {}", input, error);
+        std::process::exit(1);
+    });
+
+    let mut output: Box<dyn Write> = match open_output(sub_m) {
+        Some(file) => Box::new(file),
+        None => Box::new(std::io::stdout()),
+    };
+    for (i, game) in games.iter().enumerate() {
+        let reviews = crate::annotate::review(game, &config, &settings, |review| {
+            eprintln!(
+                "game {} ply {}\t{}\t{}",
+                i + 1,
+                review.ply + 1,
+                review.move_string,
+                review.comment()
+            );
+        });
+
+        // flagged moves for each player, counted as inaccuracies, mistakes and blunders
+        let mut counts = [[0; 3]; 2];
+        for review in &reviews {
+            if let Some(judgment) = review.judgment {
+                let player = game.position(review.ply).turn() as usize;
+                counts[player][judgment as usize] += 1;
+            }
+        }
+        for (name, counts) in ["white", "black"].iter().zip(&counts) {
+            eprintln!(
+                "game {} {}:\tinaccuracies {}, mistakes {}, blunders {}",
+                i + 1,
+                name,
+                counts[0],
+                counts[1],
+                counts[2]
+            );
+        }
+
+        let annotated = crate::annotate::annotate(game, &reviews, &config);
+        writeln!(output, "{}", annotated).unwrap();
+    }
+}
+
+fn render(sub_m: &ArgMatches) {
+    let tqbn = sub_m.value_of("tqbn").unwrap();
+    let board = crate::Board::parse_tqbn(tqbn)
+        .and_then(|board| board.validate().map(|_| board))
+        .unwrap_or_else(|error| {
+            eprintln!("bad tqbn: {}", error);
+            std::process::exit(1);
+        });
+
+    let mut overlays = crate::ui::Overlays {
+        last_move: sub_m.value_of("last-move").map(String::from),
+        paths: sub_m.is_present("paths"),
+        visits: Vec::new(),
+    };
+    if sub_m.is_present("heatmap") && board.winner().is_none() {
+        let mut log = String::new();
+        let lines = crate::ai::analyze(&board, &config(sub_m), usize::MAX, &[], &mut log);
+        eprint!("{}", log);
+        overlays.visits = lines
+            .into_iter()
+            .map(|line| (line.move_string, line.visits))
+            .collect();
+    }
+
+    let image = crate::ui::svg(&board, &overlays);
+    match open_output(sub_m) {
+        Some(mut file) => file.write_all(image.as_bytes()).unwrap(),
+        None => print!("{}", image),
+    }
+}
+
+fn read_input(path: &str) -> String {
+    let text = match path {
+        "-" => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text).map(|_| text)
+        }
+        path => std::fs::read_to_string(path),
+    };
+    text.unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        std::process::exit(1);
+    })
+}
+
+fn generate_puzzles(sub_m: &ArgMatches) {
+    let settings = crate::puzzle::PuzzleSettings {
+        min_moves: sub_m.value_of("min-moves").unwrap().parse().unwrap(),
+        max_moves: sub_m.value_of("max-moves").unwrap().parse().unwrap(),
+    };
+
+    // each position to scan, with where it came from
+    let mut positions = Vec::new();
+    for path in sub_m.values_of("games").into_iter().flatten() {
+        let games = crate::game::Game::parse_all(&read_input(path)).unwrap_or_else(|error| {
+            eprintln!("{}: {}", path, error);
+            std::process::exit(1);
+        });
+        for (i, game) in games.iter().enumerate() {
+            for ply in 0..=game.moves().len() {
+                let source = format!("{}#{} ply {}", path, i + 1, ply);
+                positions.push((game.position(ply).clone(), source));
+            }
+        }
+    }
+    for path in sub_m.values_of("positions").into_iter().flatten() {
+        for (i, line) in read_input(path).lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let board = super::batch::parse_line(line)
+                .1
+                .and_then(|tqbn| crate::Board::parse_tqbn(&tqbn))
+                .and_then(|board| board.validate().map(|_| board));
+            match board {
+                Ok(board) => positions.push((board, format!("{}:{}", path, i + 1))),
+                Err(error) => eprintln!("{}:{}: {}", path, i + 1, error),
+            }
+        }
+    }
+
+    let mut output: Box<dyn Write> = match open_output(sub_m) {
+        Some(file) => Box::new(file),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut seen = std::collections::HashSet::new();
+    let mut found = 0;
+    for (board, source) in positions {
+        if !seen.insert(board.to_tqbn()) {
+            continue;
+        }
+        if let Some(mut puzzle) = crate::puzzle::find(&board, &settings) {
+            puzzle.source = source;
+            writeln!(output, "{}", serde_json::to_string(&puzzle).unwrap()).unwrap();
+            found += 1;
+        }
+    }
+    eprintln!("positions:\t{}", seen.len());
+    eprintln!("puzzles:\t{}", found);
+}
+
+fn verify_puzzles(sub_m: &ArgMatches) {
+    let path = sub_m.value_of("input").unwrap_or("-");
+    let mut puzzles = 0;
+    let mut failures = 0;
+    for (i, line) in read_input(path).lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        puzzles += 1;
+        let result = serde_json::from_str(line)
+            .map_err(|error| format!("bad json: {}", error))
+            .and_then(|puzzle| crate::puzzle::verify(&puzzle));
+        if let Err(error) = result {
+            println!("{}:{}: {}", path, i + 1, error);
+            failures += 1;
+        }
+    }
+    println!("verified {} of {} puzzles", puzzles - failures, puzzles);
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// The server settings from the config file, if any, overridden by flags
+fn server_config(sub_m: &ArgMatches) -> crate::ui::ServerConfig {
+    let mut server = match sub_m.value_of("config-file") {
+        Some(path) => crate::ui::ServerConfig::from_file(path).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }),
+        None => Default::default(),
+    };
+    if let Some(address) = sub_m.value_of("address") {
+        server.address = address.to_string();
+    }
+    if let Some(port) = sub_m.value_of("port") {
+        server.port = port.parse().unwrap();
+    }
+    if let Some(origins) = sub_m.value_of("allowed-origins") {
+        server.allowed_origins = origins.split(',').map(|s| s.trim().to_string()).collect();
+    }
+    if let Some(max_searches) = sub_m.value_of("max-searches") {
+        server.max_searches = max_searches.parse().unwrap();
+    }
+    if let Some(max_sessions) = sub_m.value_of("max-sessions") {
+        server.max_sessions = max_sessions.parse().unwrap();
+    }
+    if let Some(max_iterations) = sub_m.value_of("max-iterations") {
+        server.max_iterations = max_iterations.parse().unwrap();
+    }
+    if let Some(max_threads) = sub_m.value_of("max-threads") {
+        server.max_threads = max_threads.parse().unwrap();
+    }
+    if let Some(max_movetime) = sub_m.value_of("max-movetime") {
+        server.max_movetime = max_movetime.parse().unwrap();
+    }
+    if let Some(mount) = sub_m.value_of("mount") {
+        server.mount = mount.to_string();
+    }
+    server
+}
+
+fn config_args<'a, 'b>() -> [Arg<'a, 'b>; 4] {
+    [
+        Arg::with_name("config")
+            .long("config")
+            .help("Engine settings, as key=value pairs (comma separated)")
+            .takes_value(true),
+        Arg::with_name("iterations")
+            .long("iterations")
+            .help("Search iterations per thread")
+            .takes_value(true),
+        Arg::with_name("threads")
+            .long("threads")
+            .help("Number of search threads")
+            .takes_value(true),
+        Arg::with_name("seed")
+            .long("seed")
+            .help("Seed for a reproducible search")
+            .takes_value(true),
+    ]
+}
+
+fn parse_config(settings: &str) -> crate::ai::Config {
+    match settings.parse() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn config(sub_m: &ArgMatches) -> crate::ai::Config {
+    let mut config = parse_config(sub_m.value_of("config").unwrap_or(""));
+    for key in ["iterations", "threads", "seed"].iter() {
+        if let Some(value) = sub_m.value_of(key) {
+            if let Err(error) = config.set(key, value) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+    config
+}
+
+fn rules_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("rules")
+        .long("rules")
+        .help("Rules (size, walls, jumps, blocking), as key=value pairs (comma separated), such as size=7,walls=6")
+        .takes_value(true)
+}
+
+fn rules(sub_m: &ArgMatches) -> crate::board::Rules {
+    match sub_m.value_of("rules").unwrap_or("").parse() {
+        Ok(rules) => rules,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn match_args<'a, 'b>() -> [Arg<'a, 'b>; 6] {
+    [
+        Arg::with_name("games")
+            .short("g")
+            .long("games")
+            .help("Number of games to play")
+            .takes_value(true),
+        Arg::with_name("concurrency")
+            .short("c")
+            .long("concurrency")
+            .help("Number of games to play at once")
+            .takes_value(true),
+        Arg::with_name("openings")
+            .long("openings")
+            .help("File of starting positions in TQBN notation, one per line")
+            .takes_value(true),
+        rules_arg(),
+        Arg::with_name("max-plies")
+            .long("max-plies")
+            .help("Declares games drawn after this many plies")
+            .takes_value(true),
+        Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .help("Saves game records to this file")
+            .takes_value(true),
+    ]
+}
+
+fn match_settings(sub_m: &ArgMatches) -> crate::tournament::MatchSettings {
+    let mut settings = crate::tournament::MatchSettings::default();
+    if let Some(games) = sub_m.value_of("games") {
+        settings.games = games.parse().unwrap();
+    }
+    if let Some(concurrency) = sub_m.value_of("concurrency") {
+        settings.concurrency = concurrency.parse().unwrap();
+    }
+    if let Some(path) = sub_m.value_of("openings") {
+        settings.openings = Vec::new();
+        for (i, line) in read_input(path).lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let board = crate::Board::parse_tqbn(line)
+                .and_then(|board| board.validate().map(|_| board))
+                .unwrap_or_else(|error| {
+                    eprintln!("{}:{}: {}", path, i + 1, error);
+                    std::process::exit(1);
+                });
+            settings.openings.push(board);
+        }
+        if settings.openings.is_empty() {
+            eprintln!("{}: no openings", path);
+            std::process::exit(1);
+        }
+    } else {
+        settings.openings = vec![crate::Board::with_rules(rules(sub_m))];
+    }
+    if let Some(max_plies) = sub_m.value_of("max-plies") {
+        settings.max_plies = max_plies.parse().unwrap();
+    }
+    settings
+}
+
+fn open_output(sub_m: &ArgMatches) -> Option<std::fs::File> {
+    sub_m
+        .value_of("output")
+        .map(|path| std::fs::File::create(path).unwrap())
+}
+
+fn result_string(winner: Option<crate::board::Player>) -> &'static str {
+    match winner {
+        Some(crate::board::Player::White) => "1-0",
+        Some(crate::board::Player::Black) => "0-1",
+        None => "1/2-1/2",
+    }
+}
+
+fn save_record(
+    output: &mut Option<std::fs::File>,
+    record: &crate::tournament::GameRecord,
+    a: &crate::ai::Config,
+    b: &crate::ai::Config,
+) {
+    if let Some(output) = output {
+        writeln!(output, "{}", record.to_game(a, b)).unwrap();
+    }
+}
+
+fn analyze(board: &crate::Board, sub_m: &ArgMatches) {
+    let multipv = match sub_m.value_of("multipv").unwrap_or("3").parse() {
+        Ok(multipv) if multipv > 0 => multipv,
+        _ => {
+            eprintln!("multipv must be a positive number");
+            std::process::exit(1);
+        }
+    };
+    let searchmoves: Vec<String> = match sub_m.values_of("searchmoves") {
+        Some(values) => values.map(|s| s.to_ascii_lowercase()).collect(),
+        None => Vec::new(),
+    };
+    for move_string in &searchmoves {
+        if board.child_for_move_string(move_string).is_none() {
+            eprintln!("illegal move: {}", move_string);
+            std::process::exit(1);
+        }
+    }
+
+    let mut log = String::new();
+    let lines = crate::ai::analyze(board, &config(sub_m), multipv, &searchmoves, &mut log);
+    eprint!("{}", log);
+
+    for (i, line) in lines.iter().enumerate() {
+        println!(
+            "{} {} value {:.3} visits {} share {:.3} pv {}",
+            i + 1,
+            line.move_string,
+            line.value,
+            line.visits,
+            line.visit_share,
+            line.pv.join(" ")
+        );
+    }
+}
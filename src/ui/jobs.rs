@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::server::SearchSlot;
+use super::session::new_id;
+use crate::ai::{Config, Line, Progress};
+use crate::Board;
+
+/// finished jobs are dropped this long after they finish
+const JOB_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JobStatus {
+    Running,
+    /// the search reached its iteration or time limit
+    Done,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Limits and options for an analysis job
+#[derive(Clone, Debug)]
+pub struct Limits {
+    pub config: Config,
+    /// stops the search after this long, even if it has iterations left
+    pub movetime: Option<Duration>,
+    pub multipv: usize,
+    pub searchmoves: Vec<String>,
+}
+
+#[derive(Default)]
+struct Outcome {
+    cancelled: bool,
+    /// the final lines and when the search finished
+    finished: Option<(Vec<Line>, Instant)>,
+}
+
+/// An analysis running in the background
+pub struct Job {
+    pub tqbn: String,
+    pub multipv: usize,
+    progress: Arc<Progress>,
+    started: Instant,
+    outcome: Mutex<Outcome>,
+}
+
+impl Job {
+    pub fn status(&self) -> JobStatus {
+        let outcome = self.outcome.lock().unwrap();
+        if outcome.cancelled {
+            JobStatus::Cancelled
+        } else if outcome.finished.is_some() {
+            JobStatus::Done
+        } else {
+            JobStatus::Running
+        }
+    }
+
+    /// The best lines found so far, or the final lines once the search has finished
+    pub fn lines(&self) -> Vec<Line> {
+        if let Some((lines, _)) = &self.outcome.lock().unwrap().finished {
+            return lines.clone();
+        }
+        self.progress.lines(self.multipv)
+    }
+
+    /// Iterations run so far, summed over the search threads
+    pub fn iterations(&self) -> u64 {
+        self.progress.iterations()
+    }
+
+    /// Time spent searching, up to when the search finished
+    pub fn elapsed(&self) -> Duration {
+        match &self.outcome.lock().unwrap().finished {
+            Some((_, finished)) => finished.duration_since(self.started),
+            None => self.started.elapsed(),
+        }
+    }
+
+    /// Stops the search, keeping the lines found so far; does nothing if it has already finished
+    pub fn cancel(&self) {
+        let mut outcome = self.outcome.lock().unwrap();
+        if outcome.finished.is_none() {
+            outcome.cancelled = true;
+            self.progress.stop();
+        }
+    }
+
+    fn finish(&self, lines: Vec<Line>) {
+        self.outcome.lock().unwrap().finished = Some((lines, Instant::now()));
+    }
+
+    fn expired(&self) -> bool {
+        match &self.outcome.lock().unwrap().finished {
+            Some((_, finished)) => finished.elapsed() >= JOB_TIMEOUT,
+            None => false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Mutex<HashMap<u64, Arc<Job>>>,
+}
+
+impl Jobs {
+    /// Starts analyzing `board` in the background, returning the job's id
+    ///
+    /// The job holds `slot` until its search finishes.
+    pub fn start(&self, board: Board, limits: Limits, slot: SearchSlot) -> u64 {
+        self.expire();
+        let job = Arc::new(Job {
+            tqbn: board.to_tqbn(),
+            multipv: limits.multipv,
+            progress: Arc::new(match limits.movetime {
+                Some(movetime) => Progress::with_time_limit(movetime),
+                None => Progress::default(),
+            }),
+            started: Instant::now(),
+            outcome: Mutex::default(),
+        });
+
+        let mut jobs = self.jobs.lock().unwrap();
+        let id = new_id(&jobs);
+        jobs.insert(id, Arc::clone(&job));
+
+        thread::spawn(move || {
+            let mut log = String::new();
+            let lines = crate::ai::analyze_with_progress(
+                &board,
+                &limits.config,
+                limits.multipv,
+                &limits.searchmoves,
+                &job.progress,
+                &mut log,
+            );
+            job.finish(lines);
+            slot.finish(job.iterations());
+        });
+
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<Job>> {
+        self.expire();
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    fn expire(&self) {
+        self.jobs.lock().unwrap().retain(|_, job| !job.expired());
+    }
+}
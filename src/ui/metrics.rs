@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Request, Response};
+
+/// upper bounds of the search duration histogram's buckets, in seconds
+const DURATION_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+#[derive(Default)]
+struct Searches {
+    /// searches finished within each of `DURATION_BUCKETS`; each count includes the smaller buckets
+    buckets: [u64; DURATION_BUCKETS.len()],
+    count: u64,
+    seconds: f64,
+    /// iterations summed over all threads
+    iterations: u64,
+}
+
+/// Counters for the `/metrics` endpoint, in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    /// requests by method, route and status
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    /// error responses by code
+    errors: Mutex<BTreeMap<String, u64>>,
+    searches: Mutex<Searches>,
+}
+
+impl Metrics {
+    pub fn count_request(&self, method: &str, route: &str, status: u16) {
+        let key = (method.to_string(), route.to_string(), status);
+        *self.requests.lock().unwrap().entry(key).or_insert(0) += 1;
+    }
+
+    pub fn count_error(&self, code: &str) {
+        *self
+            .errors
+            .lock()
+            .unwrap()
+            .entry(code.to_string())
+            .or_insert(0) += 1;
+    }
+
+    /// Records a finished search's duration and iterations, summed over its threads
+    pub fn record_search(&self, duration: Duration, iterations: u64) {
+        let seconds = duration.as_secs_f64();
+        let mut searches = self.searches.lock().unwrap();
+        for (bucket, bound) in searches.buckets.iter_mut().zip(&DURATION_BUCKETS) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        searches.count += 1;
+        searches.seconds += seconds;
+        searches.iterations += iterations;
+    }
+
+    /// Renders every metric in the Prometheus text exposition format
+    pub fn render(&self, active_searches: usize) -> String {
+        let mut text = String::new();
+
+        metric_header(
+            &mut text,
+            "theseus_requests_total",
+            "counter",
+            "HTTP requests handled, by method, route and status",
+        );
+        for ((method, route, status), count) in self.requests.lock().unwrap().iter() {
+            writeln!(
+                text,
+                "theseus_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method,
+                escape(route),
+                status,
+                count
+            )
+            .unwrap();
+        }
+
+        metric_header(
+            &mut text,
+            "theseus_errors_total",
+            "counter",
+            "Error responses, by error code",
+        );
+        for (code, count) in self.errors.lock().unwrap().iter() {
+            writeln!(text, "theseus_errors_total{{code=\"{}\"}} {}", code, count).unwrap();
+        }
+
+        let searches = self.searches.lock().unwrap();
+        metric_header(
+            &mut text,
+            "theseus_search_duration_seconds",
+            "histogram",
+            "Time taken by searches",
+        );
+        for (bound, count) in DURATION_BUCKETS.iter().zip(&searches.buckets) {
+            writeln!(
+                text,
+                "theseus_search_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, count
+            )
+            .unwrap();
+        }
+        writeln!(
+            text,
+            "theseus_search_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            searches.count
+        )
+        .unwrap();
+        writeln!(
+            text,
+            "theseus_search_duration_seconds_sum {}",
+            searches.seconds
+        )
+        .unwrap();
+        writeln!(
+            text,
+            "theseus_search_duration_seconds_count {}",
+            searches.count
+        )
+        .unwrap();
+
+        metric_header(
+            &mut text,
+            "theseus_search_iterations_total",
+            "counter",
+            "Search iterations run, summed over threads",
+        );
+        writeln!(
+            text,
+            "theseus_search_iterations_total {}",
+            searches.iterations
+        )
+        .unwrap();
+
+        metric_header(
+            &mut text,
+            "theseus_search_iterations_per_second",
+            "gauge",
+            "Search iterations per second of search time, averaged over all searches",
+        );
+        let rate = if searches.seconds > 0.0 {
+            searches.iterations as f64 / searches.seconds
+        } else {
+            0.0
+        };
+        writeln!(text, "theseus_search_iterations_per_second {}", rate).unwrap();
+
+        metric_header(
+            &mut text,
+            "theseus_active_searches",
+            "gauge",
+            "Searches running, including analysis jobs",
+        );
+        writeln!(text, "theseus_active_searches {}", active_searches).unwrap();
+
+        text
+    }
+}
+
+fn metric_header(text: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(text, "# HELP {} {}", name, help).unwrap();
+    writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/// Escapes a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Counts every response by the route that produced it
+pub struct CountRequests(pub Arc<Metrics>);
+
+impl Fairing for CountRequests {
+    fn info(&self) -> Info {
+        Info {
+            name: "Count requests",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        // count routes rather than paths, so ids don't create new series
+        let route = match request.route() {
+            Some(route) => route.uri.path().to_string(),
+            None => String::from("unmatched"),
+        };
+        self.0
+            .count_request(request.method().as_str(), &route, response.status().code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets() {
+        let metrics = Metrics::default();
+        metrics.record_search(Duration::from_millis(300), 1000);
+        metrics.record_search(Duration::from_secs(200), 3000);
+        let text = metrics.render(0);
+        assert!(text.contains("theseus_search_duration_seconds_bucket{le=\"0.25\"} 0\n"));
+        assert!(text.contains("theseus_search_duration_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(text.contains("theseus_search_duration_seconds_bucket{le=\"120\"} 1\n"));
+        assert!(text.contains("theseus_search_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("theseus_search_iterations_total 4000\n"));
+    }
+}
@@ -1,4 +1,17 @@
 mod api;
 pub use api::api;
+mod batch;
 mod cli;
 pub use cli::cli;
+mod jobs;
+mod metrics;
+mod server;
+pub use server::ServerConfig;
+mod session;
+mod svg;
+pub use svg::{svg, Overlays};
+mod term;
+pub use term::{eprint_board, print_board, Highlights};
+mod tui;
+pub use tui::{play, Engine};
+mod uci;
@@ -0,0 +1,180 @@
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use super::metrics::Metrics;
+use crate::ai::Config;
+
+/// Settings of the HTTP API server, read from a TOML file such as
+///
+/// ```toml
+/// address = "127.0.0.1"
+/// port = 8000
+/// allowed_origins = ["https://example.com"]
+/// max_searches = 4
+/// max_sessions = 100
+/// mount = "/theseus"
+/// ```
+///
+/// where every key is optional.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+    /// origins allowed to make cross-origin requests; `*` allows any origin
+    pub allowed_origins: Vec<String>,
+    /// searches that may run at once, including analysis jobs; more are refused until one ends
+    pub max_searches: usize,
+    /// games kept at once; starting another drops the least recently used
+    pub max_sessions: usize,
+    /// iterations per thread any one search may run; larger requests are reduced to this
+    pub max_iterations: u32,
+    /// threads any one search may use
+    pub max_threads: u32,
+    /// milliseconds any one search may run for
+    pub max_movetime: u64,
+    /// path the API is served under; `/health`, `/version` and `/metrics` stay at the root
+    pub mount: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: String::from("localhost"),
+            port: 8000,
+            allowed_origins: vec![String::from("*")],
+            max_searches: 4,
+            max_sessions: 100,
+            max_iterations: 1_000_000,
+            max_threads: 8,
+            max_movetime: 120_000,
+            mount: String::from("/theseus"),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_file(path: &str) -> Result<ServerConfig, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        toml::from_str(&text).map_err(|error| format!("{}: {}", path, error))
+    }
+
+    /// Whether a request from `origin` may be answered cross-origin
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == origin)
+    }
+
+    /// Reduces an engine configuration to the server's iteration and thread caps
+    pub fn cap(&self, config: &mut Config) {
+        config.iterations = config.iterations.min(self.max_iterations).max(1);
+        config.threads = config.threads.min(self.max_threads).max(1);
+    }
+
+    /// The time a search may run for, given the time requested, if any
+    pub fn time_limit(&self, movetime: Option<u64>) -> Duration {
+        Duration::from_millis(movetime.map_or(self.max_movetime, |movetime| {
+            movetime.min(self.max_movetime)
+        }))
+    }
+}
+
+/// Counts running searches, so the server can refuse searches beyond its limit
+pub struct SearchSlots {
+    active: Arc<AtomicUsize>,
+    max: usize,
+    metrics: Arc<Metrics>,
+}
+
+impl SearchSlots {
+    /// Allows `max` searches at once, recording finished searches in `metrics`
+    pub fn new(max: usize, metrics: Arc<Metrics>) -> SearchSlots {
+        SearchSlots {
+            active: Arc::default(),
+            max,
+            metrics,
+        }
+    }
+
+    /// Claims a slot for a search, or returns None if all are taken
+    pub fn acquire(&self) -> Option<SearchSlot> {
+        let mut active = self.active.load(Ordering::SeqCst);
+        loop {
+            if active >= self.max {
+                return None;
+            }
+            match self.active.compare_exchange(
+                active,
+                active + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => {
+                    return Some(SearchSlot {
+                        active: Arc::clone(&self.active),
+                        metrics: Arc::clone(&self.metrics),
+                        started: Instant::now(),
+                    })
+                }
+                Err(current) => active = current,
+            }
+        }
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+}
+
+/// A claimed search slot, released when dropped
+pub struct SearchSlot {
+    active: Arc<AtomicUsize>,
+    metrics: Arc<Metrics>,
+    started: Instant,
+}
+
+impl SearchSlot {
+    /// Releases the slot, recording the search's duration and its iterations summed over threads
+    pub fn finish(self, iterations: u64) {
+        self.metrics
+            .record_search(self.started.elapsed(), iterations);
+    }
+}
+
+impl Drop for SearchSlot {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_config_file() {
+        let config: ServerConfig =
+            toml::from_str("port = 9000\nallowed_origins = [\"https://a.example\"]\n").unwrap();
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.mount, "/theseus");
+        assert!(config.allows_origin("https://a.example"));
+        assert!(!config.allows_origin("https://b.example"));
+        assert!(toml::from_str::<ServerConfig>("prot = 9000").is_err());
+    }
+
+    #[test]
+    fn search_slots() {
+        let slots = SearchSlots::new(2, Arc::default());
+        let a = slots.acquire().unwrap();
+        let _b = slots.acquire().unwrap();
+        assert!(slots.acquire().is_none());
+        drop(a);
+        assert_eq!(slots.active(), 1);
+        assert!(slots.acquire().is_some());
+    }
+}
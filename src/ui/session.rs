@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::{thread_rng, Rng};
+
+use crate::ai::Tree;
+use crate::{Board, Game};
+
+/// sessions untouched for this long are dropped
+const SESSION_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Picks an unused id for a new entry in `map`
+///
+/// Ids stay below 2^53 so JavaScript clients can represent them exactly.
+pub fn new_id<T>(map: &HashMap<u64, T>) -> u64 {
+    let mut id = thread_rng().gen_range(1, 1 << 53);
+    while map.contains_key(&id) {
+        id = thread_rng().gen_range(1, 1 << 53);
+    }
+    id
+}
+
+/// A game being played through the API, with the engine's search trees kept between its moves
+pub struct Session {
+    pub game: Game,
+    pub tree: Tree,
+}
+
+/// a session, and when it was last used
+type Entry = (Arc<Mutex<Session>>, Instant);
+
+pub struct Sessions {
+    sessions: Mutex<HashMap<u64, Entry>>,
+    /// sessions kept at once; each holds its search trees, so this bounds the memory they use
+    max: usize,
+}
+
+impl Sessions {
+    /// Keeps at most `max` sessions, dropping the least recently used to make room for new ones
+    pub fn new(max: usize) -> Sessions {
+        Sessions {
+            sessions: Mutex::default(),
+            max: max.max(1),
+        }
+    }
+
+    /// Starts a game from `start`, returning its id
+    pub fn create(&self, start: Board) -> u64 {
+        self.expire();
+        let mut sessions = self.sessions.lock().unwrap();
+        while sessions.len() >= self.max {
+            let oldest = sessions
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(id, _)| *id)
+                .unwrap();
+            sessions.remove(&oldest);
+        }
+        let id = new_id(&sessions);
+        let session = Session {
+            game: Game::new(start),
+            tree: Tree::default(),
+        };
+        sessions.insert(id, (Arc::new(Mutex::new(session)), Instant::now()));
+        id
+    }
+
+    /// Finds a session, marking it as used
+    pub fn get(&self, id: u64) -> Option<Arc<Mutex<Session>>> {
+        self.expire();
+        let mut sessions = self.sessions.lock().unwrap();
+        let (session, last_used) = sessions.get_mut(&id)?;
+        *last_used = Instant::now();
+        Some(Arc::clone(session))
+    }
+
+    pub fn remove(&self, id: u64) -> bool {
+        self.sessions.lock().unwrap().remove(&id).is_some()
+    }
+
+    fn expire(&self) {
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, (_, last_used)| last_used.elapsed() < SESSION_TIMEOUT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_is_dropped() {
+        let sessions = Sessions::new(2);
+        let a = sessions.create(Board::new());
+        let b = sessions.create(Board::new());
+        assert!(sessions.get(a).is_some());
+        let c = sessions.create(Board::new());
+        assert!(sessions.get(a).is_some());
+        assert!(sessions.get(b).is_none());
+        assert!(sessions.get(c).is_some());
+    }
+}
@@ -0,0 +1,303 @@
+//! Positions drawn as SVG images
+//!
+//! Row 1 is at the top, as in `Board`'s text display, so white moves up the image and black
+//! down it. Walls are drawn in the gutters between squares, spanning two squares and the gutter
+//! between them.
+
+use std::fmt::Write;
+
+use crate::board::{parse_move, string_for_sqnum, Board, Player};
+
+/// width of a square
+const SQUARE: u32 = 40;
+/// width of the gutters walls are drawn in
+const GUTTER: u32 = 8;
+const CELL: u32 = SQUARE + GUTTER;
+/// space left of the board for row numbers, and right of it to balance
+const MARGIN: u32 = 32;
+/// space above and below the board for each player's remaining walls
+const HEADER: u32 = 36;
+/// space below the board for column letters
+const LABELS: u32 = 20;
+
+const SQUARE_COLOR: &str = "#e9dcc0";
+const WALL_COLOR: &str = "#7a4a1e";
+const HIGHLIGHT_COLOR: &str = "#f2c14e";
+const HEAT_COLOR: &str = "#e03131";
+
+/// Extra information drawn over a position
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Overlays {
+    /// the move that reached the position, highlighted
+    pub last_move: Option<String>,
+    /// whether to draw each player's shortest path to its goal
+    pub paths: bool,
+    /// root moves and their visit counts from a search, shading each move's square or wall by
+    /// its share of the most visited move's visits
+    pub visits: Vec<(String, u32)>,
+}
+
+/// the top left corner of a square
+fn corner(size: u8, sqnum: u8) -> (u32, u32) {
+    let row = (sqnum / size) as u32;
+    let col = (sqnum % size) as u32;
+    (MARGIN + col * CELL, HEADER + row * CELL)
+}
+
+fn center(size: u8, sqnum: u8) -> (u32, u32) {
+    let (x, y) = corner(size, sqnum);
+    (x + SQUARE / 2, y + SQUARE / 2)
+}
+
+/// a wall named by the square northwest of its center, as a rect's attributes
+fn wall_rect(size: u8, sqnum: u8, horizontal: bool) -> String {
+    let (x, y) = corner(size, sqnum);
+    let length = CELL + SQUARE;
+    if horizontal {
+        format!(
+            r#"x="{}" y="{}" width="{}" height="{}""#,
+            x,
+            y + SQUARE,
+            length,
+            GUTTER
+        )
+    } else {
+        format!(
+            r#"x="{}" y="{}" width="{}" height="{}""#,
+            x + SQUARE,
+            y,
+            GUTTER,
+            length
+        )
+    }
+}
+
+/// the text above or below the board naming a player and their remaining walls
+fn player_label(board: &Board, player: Player, y: u32) -> String {
+    let name = match player {
+        Player::White => "white",
+        Player::Black => "black",
+    };
+    let walls = board.remaining_walls()[player as usize];
+    format!(
+        r#"<text x="{}" y="{}" font-size="16"{}>{}: {} wall{}{}</text>"#,
+        MARGIN,
+        y,
+        if board.turn() == player {
+            r#" font-weight="bold""#
+        } else {
+            ""
+        },
+        name,
+        walls,
+        if walls == 1 { "" } else { "s" },
+        if board.turn() == player {
+            " (to move)"
+        } else {
+            ""
+        }
+    )
+}
+
+/// Draws `board` and `overlays` as an SVG image
+///
+/// Moves in the overlays that don't name a square or wall on the board are skipped.
+pub fn svg(board: &Board, overlays: &Overlays) -> String {
+    let size = board.rules().size;
+    let span = size as u32 * CELL - GUTTER;
+    let width = span + 2 * MARGIN;
+    let height = span + 2 * HEADER + LABELS;
+    let wall_cols = size - 1;
+
+    let mut s = String::new();
+    writeln!(
+        s,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" font-family="sans-serif">"#,
+        width, height
+    )
+    .unwrap();
+    writeln!(
+        s,
+        r##"<rect width="{}" height="{}" fill="#ffffff"/>"##,
+        width, height
+    )
+    .unwrap();
+
+    // squares, with the last pawn move highlighted
+    let last_move = overlays
+        .last_move
+        .as_ref()
+        .and_then(|move_string| parse_move(size, move_string));
+    for sqnum in 0..size * size {
+        let (x, y) = corner(size, sqnum);
+        let fill = match last_move {
+            Some((square, None)) if square == sqnum => HIGHLIGHT_COLOR,
+            _ => SQUARE_COLOR,
+        };
+        writeln!(
+            s,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" rx="3" fill="{}"/>"#,
+            x, y, SQUARE, SQUARE, fill
+        )
+        .unwrap();
+    }
+
+    // coordinates
+    for i in 0..size {
+        let (x, y) = center(size, i * size);
+        writeln!(
+            s,
+            r#"<text x="{}" y="{}" font-size="14" text-anchor="middle">{}</text>"#,
+            x - SQUARE / 2 - MARGIN / 2,
+            y + 5,
+            i + 1
+        )
+        .unwrap();
+        let (x, _) = center(size, i);
+        writeln!(
+            s,
+            r#"<text x="{}" y="{}" font-size="14" text-anchor="middle">{}</text>"#,
+            x,
+            HEADER + span + 16,
+            (b'a' + i) as char
+        )
+        .unwrap();
+    }
+
+    // visit counts: pawn moves shade their square, walls are drawn faintly
+    let most = overlays.visits.iter().map(|(_, visits)| *visits).max();
+    for (move_string, visits) in &overlays.visits {
+        let share = match most {
+            Some(most) if most > 0 => *visits as f64 / most as f64,
+            _ => 0.0,
+        };
+        let attributes = match parse_move(size, move_string) {
+            Some((sqnum, None)) => {
+                let (x, y) = corner(size, sqnum);
+                format!(
+                    r#"x="{}" y="{}" width="{}" height="{}" rx="3""#,
+                    x, y, SQUARE, SQUARE
+                )
+            }
+            Some((sqnum, Some(horizontal))) => wall_rect(size, sqnum, horizontal),
+            None => continue,
+        };
+        writeln!(
+            s,
+            r#"<rect {} fill="{}" fill-opacity="{:.3}"><title>{} {}</title></rect>"#,
+            attributes,
+            HEAT_COLOR,
+            0.1 + 0.6 * share,
+            move_string.trim().to_ascii_lowercase(),
+            visits
+        )
+        .unwrap();
+    }
+
+    // walls
+    for i in 0..wall_cols * wall_cols {
+        for (walls, horizontal) in [(board.hwalls(), true), (board.vwalls(), false)].iter() {
+            if walls & (1 << i) == 0 {
+                continue;
+            }
+            let sqnum = i + i / wall_cols;
+            let fill = match last_move {
+                Some((square, Some(h))) if square == sqnum && h == *horizontal => HIGHLIGHT_COLOR,
+                _ => WALL_COLOR,
+            };
+            writeln!(
+                s,
+                r#"<rect {} rx="2" fill="{}"><title>{}{}</title></rect>"#,
+                wall_rect(size, sqnum, *horizontal),
+                fill,
+                string_for_sqnum(size, sqnum),
+                if *horizontal { 'h' } else { 'v' }
+            )
+            .unwrap();
+        }
+    }
+
+    // shortest paths, from each pawn to its goal
+    if overlays.paths {
+        for (player, color) in [(Player::White, "#1c7ed6"), (Player::Black, "#495057")].iter() {
+            let path = board.shortest_path(*player);
+            if path.is_empty() {
+                continue;
+            }
+            let points: Vec<String> = Some(board.pawns()[*player as usize])
+                .into_iter()
+                .chain(path)
+                .map(|sqnum| {
+                    let (x, y) = center(size, sqnum);
+                    format!("{},{}", x, y)
+                })
+                .collect();
+            writeln!(
+                s,
+                r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="3" stroke-dasharray="6 4" stroke-opacity="0.8"/>"#,
+                points.join(" "),
+                color
+            )
+            .unwrap();
+        }
+    }
+
+    // pawns
+    for (player, fill) in [(Player::White, "#ffffff"), (Player::Black, "#222222")].iter() {
+        let (x, y) = center(size, board.pawns()[*player as usize]);
+        writeln!(
+            s,
+            r##"<circle cx="{}" cy="{}" r="{}" fill="{}" stroke="#222222" stroke-width="2"/>"##,
+            x,
+            y,
+            SQUARE * 3 / 8,
+            fill
+        )
+        .unwrap();
+    }
+
+    // black starts at the top, so its walls are listed there
+    writeln!(s, "{}", player_label(board, Player::Black, HEADER - 12)).unwrap();
+    writeln!(s, "{}", player_label(board, Player::White, height - 12)).unwrap();
+    s.push_str("</svg>\n");
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_position() {
+        let board = Board::new()
+            .child_for_move_string("e8")
+            .unwrap()
+            .child_for_move_string("d7h")
+            .unwrap();
+        let image = svg(&board, &Default::default());
+        assert!(image.starts_with("<svg "));
+        assert!(image.ends_with("</svg>\n"));
+        assert_eq!(image.matches(SQUARE_COLOR).count(), 81);
+        assert_eq!(image.matches("<circle ").count(), 2);
+        assert_eq!(image.matches("<title>d7h</title>").count(), 1);
+        assert!(image.contains("black: 9 walls"));
+        assert!(image.contains("white: 10 walls (to move)"));
+        assert!(!image.contains("<polyline "));
+
+        let overlays = Overlays {
+            last_move: Some(String::from("d7h")),
+            paths: true,
+            visits: vec![
+                (String::from("e7"), 300),
+                (String::from("c3v"), 150),
+                (String::from("z9"), 10),
+            ],
+        };
+        let image = svg(&board, &overlays);
+        assert_eq!(image.matches("<polyline ").count(), 2);
+        assert!(image.contains(&format!("fill=\"{}\"><title>d7h", HIGHLIGHT_COLOR)));
+        assert!(image.contains("fill-opacity=\"0.700\"><title>e7 300</title>"));
+        assert!(image.contains("fill-opacity=\"0.400\"><title>c3v 150</title>"));
+        assert!(!image.contains("z9"));
+    }
+}
@@ -0,0 +1,271 @@
+//! Boards drawn for the terminal with Unicode box drawing and ANSI colors
+//!
+//! Squares are three characters wide, with one-character gutters between them for walls. The
+//! gutters' crossings are dotted, so walls can be read off against them.
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+use crate::board::{parse_move, Board, Player};
+
+const RESET: &str = "\x1b[0m";
+const WALL: &str = "\x1b[33m";
+const LAST_MOVE: &str = "\x1b[1;93m";
+const WHITE: &str = "\x1b[1;97m";
+const BLACK: &str = "\x1b[1;94m";
+const WHITE_PATH: &str = "\x1b[36m";
+const BLACK_PATH: &str = "\x1b[35m";
+
+/// What to draw over a board besides its pawns and walls
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Highlights {
+    /// the move that reached the position
+    pub last_move: Option<String>,
+    /// players whose shortest paths to their goals are dotted
+    pub paths: Vec<Player>,
+}
+
+fn paint(s: &str, code: &str, color: bool) -> String {
+    if color {
+        format!("{}{}{}", code, s, RESET)
+    } else {
+        s.to_string()
+    }
+}
+
+fn player_name(player: Player) -> &'static str {
+    match player {
+        Player::White => "white",
+        Player::Black => "black",
+    }
+}
+
+fn pawn(player: Player, color: bool) -> String {
+    match player {
+        Player::White => paint("○", WHITE, color),
+        Player::Black => paint("●", BLACK, color),
+    }
+}
+
+/// Draws `board` with Unicode walls and pawns, in color if `color` is set
+///
+/// The last move's square is bracketed and its wall drawn with double lines, so both still show
+/// without color. Each player's remaining walls are listed beside their starting row.
+pub fn unicode(board: &Board, highlights: &Highlights, color: bool) -> String {
+    let size = board.rules().size as usize;
+    let wall_cols = size - 1;
+    let grid = 2 * size;
+    let pawns = board.pawns();
+
+    let last_move = highlights
+        .last_move
+        .as_ref()
+        .and_then(|move_string| parse_move(size as u8, move_string));
+    // the wall placed by the last move, as a slot
+    let last_wall = match last_move {
+        Some((sqnum, Some(horizontal))) => {
+            let sqnum = sqnum as usize;
+            Some((sqnum - sqnum / size, horizontal))
+        }
+        _ => None,
+    };
+    let mut on_path = vec![Vec::new(); size * size];
+    for player in &highlights.paths {
+        for sqnum in board.shortest_path(*player) {
+            on_path[sqnum as usize].push(*player);
+        }
+    }
+
+    // the wall in slot `row`, `col`, drawn with `light` or, for the last move, `double`
+    let wall = |row: usize, col: usize, horizontal: bool, light: &str, double: &str| {
+        if row >= wall_cols || col >= wall_cols {
+            return None;
+        }
+        let slot = row * wall_cols + col;
+        let walls = if horizontal {
+            board.hwalls()
+        } else {
+            board.vwalls()
+        };
+        if walls & (1 << slot) == 0 {
+            None
+        } else if last_wall == Some((slot, horizontal)) {
+            Some(paint(double, LAST_MOVE, color))
+        } else {
+            Some(paint(light, WALL, color))
+        }
+    };
+
+    let cols: Vec<String> = (0..size)
+        .map(|col| ((b'a' + col as u8) as char).to_string())
+        .collect();
+    let mut s = format!("     {}\n", cols.join("   "));
+
+    for g in 0..=grid {
+        if g % 2 == 1 {
+            s.push_str(&format!("{:>2} ", g / 2 + 1));
+        } else {
+            s.push_str("   ");
+        }
+        for h in 0..=grid {
+            let cell = match (g % 2, h % 2) {
+                // the frame
+                _ if g == 0 || g == grid => match h {
+                    0 if g == 0 => String::from("┌"),
+                    0 => String::from("└"),
+                    _ if h == grid && g == 0 => String::from("┐"),
+                    _ if h == grid => String::from("┘"),
+                    _ if h % 2 == 1 => String::from("───"),
+                    _ => String::from("─"),
+                },
+                _ if h == 0 || h == grid => String::from("│"),
+                // gutter crossings, which are the centers of walls
+                (0, 0) => {
+                    let (row, col) = (g / 2 - 1, h / 2 - 1);
+                    wall(row, col, true, "━", "═")
+                        .or_else(|| wall(row, col, false, "┃", "║"))
+                        .unwrap_or_else(|| String::from("·"))
+                }
+                // horizontal gutters, under a square
+                (0, 1) => {
+                    let (row, col) = (g / 2 - 1, h / 2);
+                    wall(row, col, true, "━━━", "═══")
+                        .or_else(|| {
+                            col.checked_sub(1)
+                                .and_then(|col| wall(row, col, true, "━━━", "═══"))
+                        })
+                        .unwrap_or_else(|| String::from("   "))
+                }
+                // vertical gutters, beside a square
+                (1, 0) => {
+                    let (row, col) = (g / 2, h / 2 - 1);
+                    wall(row, col, false, "┃", "║")
+                        .or_else(|| {
+                            row.checked_sub(1)
+                                .and_then(|row| wall(row, col, false, "┃", "║"))
+                        })
+                        .unwrap_or_else(|| String::from(" "))
+                }
+                _ => {
+                    let sqnum = (g / 2) * size + h / 2;
+                    let content = if sqnum == pawns[0] as usize {
+                        pawn(Player::White, color)
+                    } else if sqnum == pawns[1] as usize {
+                        pawn(Player::Black, color)
+                    } else {
+                        match on_path[sqnum].as_slice() {
+                            [] => String::from(" "),
+                            [Player::White] => paint("◦", WHITE_PATH, color),
+                            [Player::Black] => paint("•", BLACK_PATH, color),
+                            _ => String::from("∗"),
+                        }
+                    };
+                    match last_move {
+                        Some((square, None)) if square as usize == sqnum => format!(
+                            "{}{}{}",
+                            paint("[", LAST_MOVE, color),
+                            content,
+                            paint("]", LAST_MOVE, color)
+                        ),
+                        _ => format!(" {} ", content),
+                    }
+                }
+            };
+            s.push_str(&cell);
+        }
+
+        // black starts at the top and white at the bottom, so their walls are listed there
+        let player = match g {
+            1 => Some(Player::Black),
+            _ if g == grid - 1 => Some(Player::White),
+            _ => None,
+        };
+        if let Some(player) = player {
+            let walls = board.remaining_walls()[player as usize];
+            s.push_str(&format!(
+                "  {} {}: {} wall{}{}",
+                pawn(player, color),
+                player_name(player),
+                walls,
+                if walls == 1 { "" } else { "s" },
+                if board.turn() == player {
+                    " (to move)"
+                } else {
+                    ""
+                }
+            ));
+        }
+        s.push('\n');
+    }
+    s
+}
+
+/// whether to draw in color on a terminal; see https://no-color.org
+fn use_color() -> bool {
+    env::var_os("NO_COLOR").is_none()
+}
+
+/// Prints `board` to stdout, in Unicode on a terminal and in the plain ASCII of `Board`'s
+/// `Display` otherwise
+pub fn print_board(board: &Board, highlights: &Highlights) {
+    if io::stdout().is_terminal() {
+        print!("{}", unicode(board, highlights, use_color()));
+    } else {
+        print!("{}", board);
+    }
+}
+
+/// Prints `board` to stderr, like `print_board`
+pub fn eprint_board(board: &Board, highlights: &Highlights) {
+    if io::stderr().is_terminal() {
+        eprint!("{}", unicode(board, highlights, use_color()));
+    } else {
+        eprint!("{}", board);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_board() {
+        let board = Board::from_tqbn("nnnnnnnnnnnnnnhn1c506c105");
+        let highlights = Highlights {
+            last_move: Some(String::from("c4h")),
+            paths: vec![Player::Black],
+        };
+        let drawing = unicode(&board, &highlights, false);
+        let expected = concat!(
+            "     a   b   c   d   e\n",
+            "   ┌───────────────────┐\n",
+            " 1 │         ●         │  ● black: 5 walls\n",
+            "   │   ·   ·   ·   ·   │\n",
+            " 2 │         •         │\n",
+            "   │   ·   ·   ·   ·   │\n",
+            " 3 │         •         │\n",
+            "   │   ·   ·   ·   ·   │\n",
+            " 4 │     •   •         │\n",
+            "   │   ·   ·═══════·   │\n",
+            " 5 │     •   ○         │  ○ white: 6 walls (to move)\n",
+            "   └───────────────────┘\n",
+        );
+        assert_eq!(drawing, expected);
+
+        let colored = unicode(&board, &highlights, true);
+        assert!(colored.contains(&format!("{}═══{}", LAST_MOVE, RESET)));
+        assert!(colored.contains(&format!("{}•{}", BLACK_PATH, RESET)));
+    }
+
+    #[test]
+    fn last_pawn_move() {
+        let board = Board::new().child_for_move_string("e8").unwrap();
+        let highlights = Highlights {
+            last_move: Some(String::from("e8")),
+            paths: Vec::new(),
+        };
+        let drawing = unicode(&board, &highlights, false);
+        assert!(drawing.contains("[○]"));
+        assert_eq!(drawing.matches('[').count(), 1);
+    }
+}
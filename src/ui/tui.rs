@@ -1,48 +1,173 @@
 use std::io;
+use std::io::Write;
+
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
+
+use super::term::{print_board, Highlights};
+use crate::board::{Board, Player};
+
+pub enum Engine {
+    Default,
+    Random,
+}
+
+fn player_name(player: Player) -> &'static str {
+    match player {
+        Player::White => "white",
+        Player::Black => "black",
+    }
+}
+
+fn print_help() {
+    println!("enter a move (e.g. e8 or d4h), or one of:");
+    println!("  undo     take back your last move");
+    println!("  redo     replay an undone move");
+    println!("  history  list the moves played");
+    println!("  paths    show or hide the shortest paths");
+    println!("  help     show this message");
+    println!("  quit     leave the game");
+}
+
+/// lists `moves` numbered as in game records, starting with `start` to move
+fn print_history(start: Player, moves: &[String]) {
+    if moves.is_empty() {
+        println!("no moves played");
+        return;
+    }
+    let (first_number, pairs) = match start {
+        Player::White => (1, moves),
+        Player::Black => {
+            println!("{:3}... {}", 1, moves[0]);
+            (2, &moves[1..])
+        }
+    };
+    for (i, pair) in pairs.chunks(2).enumerate() {
+        match pair {
+            [first, second] => println!("{:3}. {:4} {}", first_number + i, first, second),
+            [first] => println!("{:3}. {}", first_number + i, first),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Plays an interactive game in the terminal, with the human moving as `human`
+pub fn play(start: Board, human: Player, engine: Engine, config: &crate::ai::Config) {
+    let mut rng = StdRng::seed_from_u64(config.seed.unwrap_or_else(|| thread_rng().gen()));
+
+    // positions[i + 1] is the result of moves[i]
+    let mut positions = vec![start];
+    let mut moves: Vec<String> = Vec::new();
+    let mut undone: Vec<(Board, String)> = Vec::new();
+    let mut paths = false;
+
+    println!(
+        "you are playing {}; type help for commands",
+        player_name(human)
+    );
 
-pub fn run() {
-    let mut board = crate::board::Board::new();
-    let mut input;
     loop {
-        board.print();
-
-        input = String::from("");
-        println!("Enter move:");
-        let result = io::stdin().read_line(&mut input);
-        input = input.trim().to_string();
-        if result.is_err() || input.len() < 2 || input.len() > 3 {
-            println!("invalid input");
-            continue;
+        let board = positions.last().unwrap().clone();
+        println!();
+        let highlights = Highlights {
+            last_move: moves.last().cloned(),
+            paths: if paths {
+                vec![Player::White, Player::Black]
+            } else {
+                Vec::new()
+            },
+        };
+        print_board(&board, &highlights);
+
+        if let Some(winner) = board.winner() {
+            println!();
+            if winner == human {
+                println!("{} wins; you win!", player_name(winner));
+            } else {
+                println!("{} wins; theseus wins", player_name(winner));
+            }
+            print_history(positions[0].turn(), &moves);
+            return;
         }
 
-        let mut chars = input.chars();
-        let col = chars.next().unwrap();
-        let mut row = chars.next().unwrap() as u8;
-        if col < 'a' || col > 'i' || row < 49 || row > 57 {
-            println!("invalid coords");
+        if board.turn() != human {
+            let child = match engine {
+                Engine::Default => {
+                    let mut log = String::new();
+                    crate::ai::configured(&board, config, &mut log)
+                }
+                Engine::Random => crate::ai::random(&board, &mut rng),
+            }
+            .expect("a player has moves until the game is over");
+            let move_string = board.move_string_to(&child);
+            println!("theseus plays {}", move_string);
+            positions.push(child);
+            moves.push(move_string);
+            undone.clear();
             continue;
         }
-        row -= 48;
-
-        let mut wall_place = false;
-        let mut vertical_wall = false;
-        if input.len() == 3 {
-            match chars.next().unwrap() {
-                'v' => {
-                    vertical_wall = true;
+
+        loop {
+            print!("{} to move> ", player_name(board.turn()));
+            io::stdout().flush().unwrap();
+
+            let mut input = String::new();
+            if io::stdin().read_line(&mut input).unwrap() == 0 {
+                println!();
+                return;
+            }
+            let input = input.trim().to_ascii_lowercase();
+
+            match input.as_str() {
+                "" => continue,
+                "quit" | "exit" => return,
+                "help" => print_help(),
+                "history" => print_history(positions[0].turn(), &moves),
+                "paths" => {
+                    paths = !paths;
+                    break;
                 }
-                'h' => {
-                    vertical_wall = false;
+                "undo" => {
+                    let played = &positions[..positions.len() - 1];
+                    if !played.iter().any(|position| position.turn() == human) {
+                        println!("nothing to undo");
+                        continue;
+                    }
+                    // take back plies until it is the human's turn again
+                    loop {
+                        let position = positions.pop().unwrap();
+                        undone.push((position, moves.pop().unwrap()));
+                        if positions.len() == 1 || positions.last().unwrap().turn() == human {
+                            break;
+                        }
+                    }
+                    break;
                 }
-                _ => {
-                    println!("invalid wall orientation");
-                    continue;
+                "redo" => {
+                    if undone.is_empty() {
+                        println!("nothing to redo");
+                        continue;
+                    }
+                    while let Some((position, move_string)) = undone.pop() {
+                        let turn = position.turn();
+                        positions.push(position);
+                        moves.push(move_string);
+                        if turn == human {
+                            break;
+                        }
+                    }
+                    break;
                 }
+                _ => match board.child_for_move_string(&input) {
+                    Some(child) => {
+                        positions.push(child);
+                        moves.push(input);
+                        undone.clear();
+                        break;
+                    }
+                    None => println!("illegal move: {}", input),
+                },
             }
-            wall_place = true;
         }
-
-        let sqnum = crate::board::sqnum_for_coord(col, row);
-        // TODO find child in board.moves that reflects input
     }
 }
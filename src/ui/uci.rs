@@ -0,0 +1,401 @@
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::ai::{Config, Progress, Tree};
+use crate::board::{Board, Rules};
+
+/// time between `info` lines during a search
+const INFO_INTERVAL: Duration = Duration::from_millis(500);
+
+/// how often the `info` thread checks whether the search has finished
+const INFO_POLL: Duration = Duration::from_millis(10);
+
+type Output = Arc<Mutex<dyn Write + Send>>;
+
+fn send(output: &Output, line: &str) {
+    let mut output = output.lock().unwrap();
+    writeln!(output, "{}", line).unwrap();
+    output.flush().unwrap();
+}
+
+/// A search running in the background, which hands back the search trees when it finishes
+struct Search {
+    progress: Arc<Progress>,
+    handle: JoinHandle<Tree>,
+    /// whether the search runs until stopped
+    infinite: bool,
+}
+
+struct Engine {
+    config: Config,
+    /// rules `startpos` and `ucinewgame` start from
+    rules: Rules,
+    board: Board,
+    /// None while a search has the trees
+    tree: Option<Tree>,
+    search: Option<Search>,
+    output: Output,
+}
+
+impl Engine {
+    fn send(&self, line: &str) {
+        send(&self.output, line);
+    }
+
+    /// Waits for the running search, if any, stopping it first if `stop` is set
+    fn finish_search(&mut self, stop: bool) {
+        if let Some(search) = self.search.take() {
+            if stop {
+                search.progress.stop();
+            }
+            self.tree = Some(search.handle.join().unwrap());
+        }
+    }
+
+    fn uci(&self) {
+        self.send(&format!("id name theseus {}", env!("CARGO_PKG_VERSION")));
+        self.send(&format!("id author {}", env!("CARGO_PKG_AUTHORS")));
+        for setting in Config::default().to_string().split(',') {
+            let mut parts = setting.splitn(2, '=');
+            let (name, value) = (parts.next().unwrap(), parts.next().unwrap());
+            self.send(&format!(
+                "option name {} type string default {}",
+                name, value
+            ));
+        }
+        self.send("option name seed type string default <random>");
+        self.send("option name book type string default <none>");
+        self.send(&format!(
+            "option name rules type string default {}",
+            Rules::default()
+        ));
+        self.send("uciok");
+    }
+
+    /// `setoption name <name> value <value>`
+    fn setoption(&mut self, args: &[&str]) {
+        let (name, value) = match args {
+            ["name", name, "value", value] => (*name, *value),
+            _ => return self.send("info string usage: setoption name <name> value <value>"),
+        };
+        let name = name.to_ascii_lowercase();
+        let result = if name == "rules" {
+            value.parse().map(|rules| self.rules = rules)
+        } else {
+            self.config.set(&name, value)
+        };
+        if let Err(error) = result {
+            self.send(&format!("info string {}", error));
+        }
+    }
+
+    /// `position (startpos | tqbn <tqbn>) [moves <move>...]`
+    ///
+    /// The current position is kept unless the whole command is valid.
+    fn position(&mut self, args: &[&str]) {
+        let (mut board, rest) = match args {
+            ["startpos", rest @ ..] => (Board::with_rules(self.rules), rest),
+            ["tqbn", tqbn, rest @ ..] => {
+                match Board::parse_tqbn(tqbn).and_then(|board| board.validate().map(|_| board)) {
+                    Ok(board) => (board, rest),
+                    Err(error) => return self.send(&format!("info string bad tqbn: {}", error)),
+                }
+            }
+            _ => {
+                return self
+                    .send("info string usage: position (startpos | tqbn <tqbn>) [moves ...]")
+            }
+        };
+
+        match rest {
+            [] => {}
+            ["moves", moves @ ..] => {
+                for move_string in moves {
+                    if board.winner().is_some() {
+                        return self.send(&format!("info string game over before {}", move_string));
+                    }
+                    match board.child_for_move_string(move_string) {
+                        Some(child) => board = child,
+                        None => {
+                            return self.send(&format!("info string illegal move: {}", move_string))
+                        }
+                    }
+                }
+            }
+            _ => return self.send("info string expected moves after the position"),
+        }
+        self.board = board;
+    }
+
+    /// `go [movetime <ms>] [iterations <n>] [infinite]`
+    fn go(&mut self, args: &[&str]) {
+        let mut config = self.config.clone();
+        let mut movetime = None;
+        let mut infinite = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let value = match *arg {
+                "infinite" => {
+                    config.iterations = u32::MAX;
+                    infinite = true;
+                    continue;
+                }
+                "movetime" | "iterations" => {
+                    match args.next().and_then(|v| v.parse::<u64>().ok()) {
+                        Some(value) if value > 0 => value,
+                        _ => {
+                            return self
+                                .send(&format!("info string {} needs a positive number", arg))
+                        }
+                    }
+                }
+                _ => return self.send(&format!("info string unknown go argument: {}", arg)),
+            };
+            if *arg == "movetime" {
+                movetime = Some(Duration::from_millis(value));
+            } else {
+                config.iterations = value.min(u32::MAX as u64) as u32;
+            }
+        }
+
+        if self.board.winner().is_some() {
+            self.send("info string the game is over");
+            return self.send("bestmove (none)");
+        }
+
+        let progress = Arc::new(match movetime {
+            Some(movetime) => Progress::with_time_limit(movetime),
+            None => Progress::default(),
+        });
+        let board = self.board.clone();
+        let mut tree = self.tree.take().unwrap_or_default();
+        let output = Arc::clone(&self.output);
+        let search_progress = Arc::clone(&progress);
+        let handle = thread::spawn(move || {
+            let progress = search_progress;
+            let done = Arc::new(AtomicBool::new(false));
+            let reporter = {
+                let progress = Arc::clone(&progress);
+                let output = Arc::clone(&output);
+                let done = Arc::clone(&done);
+                thread::spawn(move || report(&progress, &output, &done))
+            };
+
+            let mut log = String::new();
+            let decision = crate::ai::decide(&board, &config, &mut tree, &progress, &mut log);
+            done.store(true, Ordering::SeqCst);
+            reporter.join().unwrap();
+
+            let decision = match decision {
+                Some(decision) => decision,
+                // as chess engines do when the game is over
+                None => {
+                    send(&output, "bestmove (none)");
+                    return tree;
+                }
+            };
+            if let Some(stats) = decision.stats {
+                send(
+                    &output,
+                    &format!(
+                        "info time {} iterations {} value {:.3} visits {}",
+                        stats.time_ms,
+                        stats.iterations as u64 * stats.threads as u64,
+                        stats.value,
+                        stats.visits
+                    ),
+                );
+            }
+            send(
+                &output,
+                &format!("bestmove {}", board.move_string_to(&decision.board)),
+            );
+            tree
+        });
+        self.search = Some(Search {
+            progress,
+            handle,
+            infinite,
+        });
+    }
+}
+
+/// Sends an `info` line about the search's best line every `INFO_INTERVAL` until `done` is set
+fn report(progress: &Progress, output: &Output, done: &AtomicBool) {
+    let started = Instant::now();
+    let mut next = started + INFO_INTERVAL;
+    while !done.load(Ordering::SeqCst) {
+        if Instant::now() < next {
+            thread::sleep(INFO_POLL);
+            continue;
+        }
+        next += INFO_INTERVAL;
+        if let Some(line) = progress.lines(1).first() {
+            let elapsed = started.elapsed();
+            let iterations = progress.iterations();
+            send(
+                output,
+                &format!(
+                    "info time {} iterations {} ips {:.0} value {:.3} visits {} pv {}",
+                    elapsed.as_millis(),
+                    iterations,
+                    iterations as f64 / elapsed.as_secs_f64(),
+                    line.value,
+                    line.visits,
+                    line.pv.join(" ")
+                ),
+            );
+        }
+    }
+}
+
+/// Runs the line-based engine protocol, reading commands from `input` until `quit` or the end of
+/// the input, and writing responses to `output`
+///
+/// At the end of the input, a search with an iteration or time limit is allowed to finish, while
+/// an infinite one is stopped.
+///
+/// The protocol is modeled on UCI: `uci`, `isready`, `setoption name <name> value <value>`,
+/// `ucinewgame`, `position (startpos | tqbn <tqbn>) [moves <move>...]`,
+/// `go [movetime <ms>] [iterations <n>] [infinite]`, `stop` and `quit`. Searches run in the
+/// background, sending `info` lines as they progress and `bestmove` when they finish.
+pub fn run<R, W>(input: R, output: W, config: Config)
+where
+    R: BufRead,
+    W: Write + Send + 'static,
+{
+    let mut engine = Engine {
+        config,
+        rules: Rules::default(),
+        board: Board::new(),
+        tree: Some(Tree::default()),
+        search: None,
+        output: Arc::new(Mutex::new(output)),
+    };
+
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => continue,
+        };
+
+        match command {
+            "uci" => engine.uci(),
+            "isready" => engine.send("readyok"),
+            "setoption" => {
+                engine.finish_search(true);
+                engine.setoption(args);
+            }
+            "ucinewgame" => {
+                engine.finish_search(true);
+                engine.tree = Some(Tree::default());
+                engine.board = Board::with_rules(engine.rules);
+            }
+            "position" => {
+                engine.finish_search(true);
+                engine.position(args);
+            }
+            "go" => {
+                engine.finish_search(true);
+                engine.go(args);
+            }
+            "stop" => engine.finish_search(true),
+            "quit" => {
+                engine.finish_search(true);
+                return;
+            }
+            _ => engine.send(&format!("info string unknown command: {}", command)),
+        }
+    }
+
+    // at the end of the input, let a limited search finish so piped commands get their answer
+    let infinite = engine
+        .search
+        .as_ref()
+        .map_or(false, |search| search.infinite);
+    engine.finish_search(infinite);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A writer whose contents can be read after the protocol finishes with it
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn session(commands: &str) -> Vec<String> {
+        let output = Shared::default();
+        run(commands.as_bytes(), output.clone(), Config::default());
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn handshake_and_options() {
+        let lines = session("uci\nsetoption name threads value x\nisready\nfoo\n");
+        assert!(lines[0].starts_with("id name theseus"));
+        assert!(lines.contains(&String::from(
+            "option name iterations type string default 50000"
+        )));
+        assert!(lines.contains(&String::from("uciok")));
+        assert!(lines.contains(&String::from("info string invalid value for threads: x")));
+        assert!(lines.contains(&String::from("readyok")));
+        assert_eq!(lines.last().unwrap(), "info string unknown command: foo");
+    }
+
+    #[test]
+    fn winning_move() {
+        // white walks to e2, black replies, and white can then win at once
+        let tqbn = "nnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnn1e310a110";
+        let lines = session(&format!(
+            "position tqbn {} moves e2 b1\ngo iterations 10\nstop\nposition tqbn {} moves e2 x9\n",
+            tqbn, tqbn
+        ));
+        assert_eq!(
+            lines,
+            vec![
+                String::from("bestmove e1"),
+                String::from("info string illegal move: x9"),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_position_keeps_board() {
+        let walls = "n".repeat(64);
+        let lines = session(&format!(
+            "position tqbn {0}1e310a110 moves e2 b1\n\
+             position tqbn {0}1e310a110 moves e2 x9\n\
+             position tqbn {0}1e310a110 moves e2 b1 e1 a2\n\
+             position tqbn {0}1e310e310\n\
+             position startpos e8\n\
+             go iterations 10\nstop\n",
+            walls
+        ));
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "info string illegal move: x9");
+        assert_eq!(lines[1], "info string game over before a2");
+        assert!(lines[2].starts_with("info string bad tqbn: "));
+        assert_eq!(lines[3], "info string expected moves after the position");
+        assert_eq!(lines[4], "bestmove e1");
+    }
+}